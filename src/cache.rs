@@ -0,0 +1,234 @@
+//! Tokens & such worth keeping between runs, in the user's cache dir
+//!
+//! Every extractor gets its own namespace (a dir) & every value its own file,
+//! the unix time it expires at on the first line & the value after it
+
+use crate::{Error, RESET, YELLOW};
+use std::{
+    env::{consts::OS, temp_dir, var_os},
+    fs::{create_dir_all, read_to_string, remove_dir_all, remove_file, rename, write, File},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Neither read nor write the cache for the rest of the run
+pub fn disable() {
+    DISABLED.store(true, Relaxed);
+}
+
+/// `$XDG_CACHE_HOME/titans`, `~/.cache/titans` or `%LOCALAPPDATA%\titans`,
+/// the temp dir if there's no home
+pub fn dir() -> PathBuf {
+    let base = if OS == "windows" {
+        var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+
+    base.unwrap_or_else(temp_dir).join("titans")
+}
+
+/// Delete everything cached
+pub fn clear() -> Result<(), Error> {
+    clear_in(&dir())
+}
+
+/// `key` of `namespace` unless it expired or the cache is off
+pub fn get(namespace: &str, key: &str) -> Option<String> {
+    if DISABLED.load(Relaxed) {
+        return None;
+    }
+
+    get_in(&dir(), namespace, key)
+}
+
+/// Keep `value` for `ttl`, written to a temp file that's renamed over the old
+/// one so no reader ever sees half of it
+pub fn set(namespace: &str, key: &str, value: &str, ttl: Duration) -> Result<(), Error> {
+    if DISABLED.load(Relaxed) {
+        return Ok(());
+    }
+
+    set_in(&dir(), namespace, key, value, ttl)
+}
+
+/// Cached `key` of `namespace` or whatever `fetch` gets, which is cached for
+/// `ttl`. Runs at the same time wait on a lock so only one of them fetches,
+/// a cache that can't be read or written only means fetching every time
+pub fn get_or_fetch(
+    namespace: &str,
+    key: &str,
+    ttl: Duration,
+    fetch: impl FnOnce() -> Result<String, Error>,
+) -> Result<String, Error> {
+    if DISABLED.load(Relaxed) {
+        return fetch();
+    }
+
+    get_or_fetch_in(&dir(), namespace, key, ttl, fetch)
+}
+
+fn clear_in(dir: &Path) -> Result<(), Error> {
+    match remove_dir_all(dir) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn get_in(dir: &Path, namespace: &str, key: &str) -> Option<String> {
+    let entry = read_to_string(dir.join(namespace).join(key)).ok()?;
+    let (expires, value) = entry.split_once('\n')?;
+
+    (expires.parse::<u64>().ok()? > now()).then(|| value.to_owned())
+}
+
+fn set_in(dir: &Path, namespace: &str, key: &str, value: &str, ttl: Duration) -> Result<(), Error> {
+    let dir = dir.join(namespace);
+    create_dir_all(&dir)?;
+
+    let tmp = dir.join(format!("{key}.{}.tmp", process::id()));
+    write(&tmp, format!("{}\n{value}", now() + ttl.as_secs()))?;
+    rename(&tmp, dir.join(key)).inspect_err(|_| {
+        remove_file(&tmp).ok();
+    })?;
+
+    Ok(())
+}
+
+fn get_or_fetch_in(
+    dir: &Path,
+    namespace: &str,
+    key: &str,
+    ttl: Duration,
+    fetch: impl FnOnce() -> Result<String, Error>,
+) -> Result<String, Error> {
+    // released when dropped
+    let _lock = lock(dir, namespace, key);
+
+    if let Some(value) = get_in(dir, namespace, key) {
+        return Ok(value);
+    }
+
+    let value = fetch()?;
+
+    if let Err(err) = set_in(dir, namespace, key, &value, ttl) {
+        eprintln!("{YELLOW}Failed to cache {namespace} {key}:{RESET} {err}");
+    }
+
+    Ok(value)
+}
+
+/// Exclusive lock on `key` of `namespace` (`File::lock` is why the MSRV is 1.89)
+fn lock(dir: &Path, namespace: &str, key: &str) -> Option<File> {
+    let dir = dir.join(namespace);
+    create_dir_all(&dir).ok()?;
+
+    let file = File::create(dir.join(format!("{key}.lock"))).ok()?;
+    file.lock().ok()?;
+
+    Some(file)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn temp_cache(name: &str) -> PathBuf {
+        let dir = temp_dir().join(format!("titans-cache-{name}-{}", process::id()));
+        clear_in(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn round_trips_until_it_expires() {
+        let dir = temp_cache("round-trip");
+
+        assert_eq!(get_in(&dir, "twatter", "guest_token"), None);
+
+        set_in(
+            &dir,
+            "twatter",
+            "guest_token",
+            "123\n456",
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(
+            get_in(&dir, "twatter", "guest_token").as_deref(),
+            Some("123\n456")
+        );
+        assert_eq!(get_in(&dir, "youtube", "guest_token"), None);
+
+        // expires this very second
+        set_in(&dir, "twatter", "guest_token", "789", Duration::ZERO).unwrap();
+        assert_eq!(get_in(&dir, "twatter", "guest_token"), None);
+
+        write(dir.join("twatter").join("garbage"), "not a time").unwrap();
+        assert_eq!(get_in(&dir, "twatter", "garbage"), None);
+
+        clear_in(&dir).unwrap();
+    }
+
+    #[test]
+    fn fetches_only_when_missing() {
+        let dir = temp_cache("fetch");
+        let fetches = Cell::new(0);
+        let fetch = || {
+            fetches.set(fetches.get() + 1);
+            Ok(format!("token {}", fetches.get()))
+        };
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(
+            get_or_fetch_in(&dir, "youtube", "key", ttl, fetch).unwrap(),
+            "token 1"
+        );
+        assert_eq!(
+            get_or_fetch_in(&dir, "youtube", "key", ttl, fetch).unwrap(),
+            "token 1"
+        );
+        assert_eq!(fetches.get(), 1);
+
+        assert!(get_or_fetch_in(&dir, "youtube", "other", ttl, || Err(Error::NoFormats)).is_err());
+        assert_eq!(get_in(&dir, "youtube", "other"), None);
+
+        clear_in(&dir).unwrap();
+    }
+
+    #[test]
+    fn clears_everything() {
+        let dir = temp_cache("clear");
+
+        set_in(
+            &dir,
+            "twatter",
+            "guest_token",
+            "123",
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        set_in(&dir, "youtube", "signature", "456", Duration::from_secs(60)).unwrap();
+
+        clear_in(&dir).unwrap();
+        assert!(!dir.exists());
+        assert_eq!(get_in(&dir, "twatter", "guest_token"), None);
+
+        // nothing cached is as cleared as it gets
+        clear_in(&dir).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! Chapter ends & exporting them for ffmpeg or mpv
+
+use crate::Chapter;
+use std::fmt::Write;
+
+/// Sort by start & end every chapter without an end where the next one starts,
+/// the last one at `duration_ms`
+pub fn fill_ends(chapters: &mut [Chapter], duration_ms: Option<u64>) {
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+
+    for i in 0..chapters.len() {
+        let start = chapters[i].start_ms;
+
+        if chapters[i].end_ms <= start {
+            chapters[i].end_ms = chapters
+                .get(i + 1)
+                .map(|next| next.start_ms)
+                .or(duration_ms)
+                .unwrap_or(start)
+                .max(start);
+        }
+    }
+}
+
+/// FFMETADATA file, ffmpeg's `-i` & mpv's `--chapters-file` both read it
+pub fn to_ffmetadata(chapters: &[Chapter]) -> String {
+    let mut metadata = String::from(";FFMETADATA1\n");
+
+    for chapter in chapters {
+        let _ = write!(
+            metadata,
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start_ms,
+            chapter.end_ms,
+            escape(&chapter.title)
+        );
+    }
+
+    metadata
+}
+
+/// `=`, `;`, `#`, `\` & newlines are special in FFMETADATA
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(start_ms: u64, end_ms: u64, title: &str) -> Chapter {
+        Chapter {
+            start_ms,
+            end_ms,
+            title: title.into(),
+            category: None,
+        }
+    }
+
+    fn ends(chapters: &[Chapter]) -> Vec<(u64, u64)> {
+        chapters
+            .iter()
+            .map(|chapter| (chapter.start_ms, chapter.end_ms))
+            .collect()
+    }
+
+    #[test]
+    fn fills_ends_from_the_next_chapter_and_the_duration() {
+        let mut chapters = vec![chapter(0, 0, "a"), chapter(60_000, 0, "b")];
+
+        fill_ends(&mut chapters, Some(90_000));
+        assert_eq!(ends(&chapters), [(0, 60_000), (60_000, 90_000)]);
+    }
+
+    #[test]
+    fn last_end_without_a_duration() {
+        let mut chapters = vec![chapter(0, 0, "a"), chapter(60_000, 0, "b")];
+
+        fill_ends(&mut chapters, None);
+        // nothing to go by, an empty chapter rather than a made up end
+        assert_eq!(ends(&chapters), [(0, 60_000), (60_000, 60_000)]);
+
+        // a duration before the start doesn't make it end early
+        let mut chapters = vec![chapter(60_000, 0, "a")];
+
+        fill_ends(&mut chapters, Some(30_000));
+        assert_eq!(ends(&chapters), [(60_000, 60_000)]);
+    }
+
+    #[test]
+    fn sorts_unsorted_chapters_and_keeps_known_ends() {
+        let mut chapters = vec![
+            chapter(60_000, 0, "c"),
+            chapter(0, 10_000, "a"),
+            chapter(30_000, 0, "b"),
+        ];
+
+        fill_ends(&mut chapters, Some(90_000));
+
+        let titles = chapters.iter().map(|c| &*c.title).collect::<Vec<_>>();
+        assert_eq!(titles, ["a", "b", "c"]);
+        // a gap after a known end stays a gap
+        assert_eq!(
+            ends(&chapters),
+            [(0, 10_000), (30_000, 60_000), (60_000, 90_000)]
+        );
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(escape("plain title"), "plain title");
+        assert_eq!(escape(r"a=b;c#d\e"), r"a\=b\;c\#d\\e");
+        assert_eq!(escape("two\nlines"), "two\\\nlines");
+        assert_eq!(escape("ünïcödé 🎵"), "ünïcödé 🎵");
+    }
+
+    #[test]
+    fn writes_ffmetadata() {
+        let chapters = [chapter(0, 1500, "Intro"), chapter(1500, 60_000, "Q&A; #1")];
+
+        assert_eq!(
+            to_ffmetadata(&chapters),
+            r";FFMETADATA1
+[CHAPTER]
+TIMEBASE=1/1000
+START=0
+END=1500
+title=Intro
+[CHAPTER]
+TIMEBASE=1/1000
+START=1500
+END=60000
+title=Q&A\; \#1
+"
+        );
+        assert_eq!(to_ffmetadata(&[]), ";FFMETADATA1\n");
+    }
+}
@@ -0,0 +1,186 @@
+//! Netscape cookies.txt, sent along with every request once loaded
+
+use crate::Error;
+use isahc::http::{header::COOKIE, HeaderValue, Request, Uri};
+use once_cell::sync::OnceCell;
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static JAR: OnceCell<Box<[Cookie]>> = OnceCell::new();
+static FILE: OnceCell<PathBuf> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    /// Without the leading dot
+    pub domain: Box<str>,
+    pub include_subdomains: bool,
+    pub path: Box<str>,
+    pub secure: bool,
+    /// Unix time, 0 for session cookies
+    pub expires: u64,
+    pub name: Box<str>,
+    pub value: Box<str>,
+}
+
+/// Read the cookies.txt at `path` & send its cookies with every request from
+/// now on, only the first file loaded counts
+pub fn load(path: &Path) -> Result<usize, Error> {
+    let cookies = parse(&read_to_string(path)?);
+    let count = cookies.len();
+
+    if JAR.set(cookies.into()).is_ok() {
+        FILE.set(path.into()).ok();
+    }
+
+    Ok(count)
+}
+
+/// The loaded cookies.txt, for mpv & aria2c which read it themselves
+pub fn file() -> Option<&'static Path> {
+    FILE.get().map(PathBuf::as_path)
+}
+
+/// Cookies of a Netscape cookies.txt, lines that aren't one are skipped
+pub fn parse(text: &str) -> Vec<Cookie> {
+    text.lines()
+        .filter_map(|line| {
+            // browsers export httponly cookies with this prefix, other # lines are comments
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+
+            if line.starts_with('#') {
+                return None;
+            }
+
+            let mut fields = line.trim_end_matches('\r').splitn(7, '\t');
+            let domain = fields.next()?;
+            let include_subdomains = fields.next()? == "TRUE";
+            let path = fields.next()?;
+            let secure = fields.next()? == "TRUE";
+            let expires = fields.next()?.parse().ok()?;
+            let name = fields.next()?;
+            let value = fields.next()?;
+
+            Some(Cookie {
+                domain: domain.trim_start_matches('.').into(),
+                include_subdomains: include_subdomains || domain.starts_with('.'),
+                path: path.into(),
+                secure,
+                expires,
+                name: name.into(),
+                value: value.into(),
+            })
+        })
+        .collect()
+}
+
+/// Value of the `Cookie` header for `url`, if any cookie is meant for it
+pub fn header(url: &str) -> Option<String> {
+    let uri: Uri = url.parse().ok()?;
+    let header = matching(&uri)
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    (!header.is_empty()).then_some(header)
+}
+
+/// Value of the cookie `name` that'd be sent to `url`
+pub fn get(url: &str, name: &str) -> Option<&'static str> {
+    let uri: Uri = url.parse().ok()?;
+    let value = matching(&uri).find(|cookie| *cookie.name == *name)?;
+
+    Some(&value.value)
+}
+
+/// Add the `Cookie` header to `request` unless it sets one itself
+pub(crate) fn attach<B>(mut request: Request<B>) -> Request<B> {
+    if !request.headers().contains_key(COOKIE) {
+        if let Some(value) = header(&request.uri().to_string())
+            .and_then(|header| HeaderValue::from_str(&header).ok())
+        {
+            request.headers_mut().insert(COOKIE, value);
+        }
+    }
+
+    request
+}
+
+fn matching(uri: &Uri) -> impl Iterator<Item = &'static Cookie> {
+    matching_in(JAR.get().map_or(&[], |jar| &jar[..]), uri)
+}
+
+/// Cookies of `jar` a request to `uri` would carry
+fn matching_in<'a>(jar: &'a [Cookie], uri: &Uri) -> impl Iterator<Item = &'a Cookie> {
+    let https = uri.scheme_str() == Some("https");
+    let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+    let path = uri.path().to_owned();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+
+    jar.iter().filter(move |cookie| {
+        let domain = cookie.domain.to_ascii_lowercase();
+        let domain_matches = host == domain
+            || (cookie.include_subdomains
+                && host
+                    .strip_suffix(&*domain)
+                    .is_some_and(|sub| sub.ends_with('.')));
+        let path_matches = path.starts_with(&*cookie.path)
+            && (cookie.path.ends_with('/')
+                || path.len() == cookie.path.len()
+                || path[cookie.path.len()..].starts_with('/'));
+
+        domain_matches
+            && path_matches
+            && (https || !cookie.secure)
+            && (cookie.expires == 0 || cookie.expires > now)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JAR: &str = "# Netscape HTTP Cookie File
+.x.com\tTRUE\t/\tTRUE\t0\tauth_token\tabc
+#HttpOnly_.x.com\tTRUE\t/\tTRUE\t9999999999\tct0\tcsrf
+www.reddit.com\tFALSE\t/r\tFALSE\t1\texpired\tgone
+www.reddit.com\tFALSE\t/r\tFALSE\t0\tover18\t1
+not a cookie
+";
+
+    fn names<'a>(jar: &'a [Cookie], url: &str) -> Vec<&'a str> {
+        matching_in(jar, &url.parse().unwrap())
+            .map(|cookie| &*cookie.name)
+            .collect()
+    }
+
+    #[test]
+    fn parses_netscape_jar() {
+        let jar = parse(JAR);
+
+        assert_eq!(jar.len(), 4);
+        assert_eq!(&*jar[0].domain, "x.com");
+        assert!(jar[0].include_subdomains && jar[0].secure);
+        assert_eq!(&*jar[1].name, "ct0");
+        assert_eq!(&*jar[1].value, "csrf");
+        assert!(!jar[3].include_subdomains);
+    }
+
+    #[test]
+    fn matches_domain_path_secure_expiry() {
+        let jar = parse(JAR);
+
+        assert_eq!(names(&jar, "https://x.com/i/api"), ["auth_token", "ct0"]);
+        assert_eq!(names(&jar, "https://api.x.com/"), ["auth_token", "ct0"]);
+        assert!(names(&jar, "http://x.com/").is_empty());
+        assert!(names(&jar, "https://evilx.com/").is_empty());
+        assert_eq!(names(&jar, "https://www.reddit.com/r/videos"), ["over18"]);
+        assert_eq!(names(&jar, "https://www.reddit.com/r"), ["over18"]);
+        assert!(names(&jar, "https://www.reddit.com/rx").is_empty());
+        assert!(names(&jar, "https://old.reddit.com/r/videos").is_empty());
+    }
+}
@@ -0,0 +1,568 @@
+//! MPEG-DASH manifests (`.mpd`)
+//!
+//! Segmented representations are listed as formats with a `manifest.mpd#ID` link,
+//! which the downloader resolves back to the representation.
+
+use crate::{
+    helpers::or_fail::OrFail,
+    hls::{resolve, split_codecs, ByteRange},
+    Error, Format, FormatKind,
+};
+use roxmltree::{Document, Node};
+use url::Url;
+
+const EXTRACTOR: &str = "dash";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Manifest {
+    /// Seconds
+    pub duration: Option<f64>,
+    pub periods: Vec<Period>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Period {
+    pub id: Option<Box<str>>,
+    /// Seconds
+    pub duration: Option<f64>,
+    pub adaptation_sets: Vec<AdaptationSet>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AdaptationSet {
+    /// video, audio, text
+    pub content_type: Option<Box<str>>,
+    pub language: Option<Box<str>>,
+    pub representations: Vec<Representation>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Representation {
+    pub id: Box<str>,
+    /// Inherited from the adaptation set if missing
+    pub mime_type: Option<Box<str>>,
+    pub codecs: Option<Box<str>>,
+    pub bandwidth: Option<u64>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub frame_rate: Option<f32>,
+    /// Resolved BaseURL, the whole file unless there are segments
+    pub url: Box<str>,
+    pub initialization: Option<SegmentRef>,
+    pub segments: Vec<SegmentRef>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SegmentRef {
+    pub url: Box<str>,
+    pub byte_range: Option<ByteRange>,
+}
+
+/// `SegmentTemplate` attributes, merged down from the adaptation set
+#[derive(Debug, Default, Clone)]
+struct Template {
+    media: Option<Box<str>>,
+    initialization: Option<Box<str>>,
+    start_number: Option<u64>,
+    timescale: Option<u64>,
+    duration: Option<u64>,
+    /// `(t, d, r)` of every `<S>`
+    timeline: Option<Vec<(Option<u64>, u64, i64)>>,
+}
+
+/// Link to a `.mpd` file, ignoring the query & fragment
+pub fn is_manifest_link(link: &str) -> bool {
+    match Url::parse(link) {
+        Ok(url) => url.path().ends_with(".mpd"),
+        Err(_) => link
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .ends_with(".mpd"),
+    }
+}
+
+pub fn parse(manifest: &str, manifest_url: &str) -> Result<Manifest, Error> {
+    let doc = Document::parse(manifest).or_fail(EXTRACTOR, "manifest")?;
+    let mpd = doc.root_element();
+
+    let mut parsed = Manifest {
+        duration: mpd
+            .attribute("mediaPresentationDuration")
+            .and_then(parse_duration),
+        ..Default::default()
+    };
+
+    let mpd_base = base_url(mpd, manifest_url);
+
+    for period in children(mpd, "Period") {
+        let period_base = base_url(period, &mpd_base);
+        let period_duration = period
+            .attribute("duration")
+            .and_then(parse_duration)
+            .or(parsed.duration);
+
+        let period_template = children(period, "SegmentTemplate")
+            .next()
+            .map(|node| Template::default().merge(node))
+            .unwrap_or_default();
+
+        let mut adaptation_sets = Vec::new();
+
+        for set in children(period, "AdaptationSet") {
+            let set_base = base_url(set, &period_base);
+            let set_template = children(set, "SegmentTemplate")
+                .next()
+                .map(|node| period_template.clone().merge(node))
+                .unwrap_or_else(|| period_template.clone());
+
+            let mut representations = Vec::new();
+
+            for rep in children(set, "Representation") {
+                let inherited = |name| rep.attribute(name).or_else(|| set.attribute(name));
+                let id = rep.attribute("id").unwrap_or_default();
+                let bandwidth = rep.attribute("bandwidth").and_then(|b| b.parse().ok());
+
+                let mut representation = Representation {
+                    id: id.into(),
+                    mime_type: inherited("mimeType").map(Into::into),
+                    codecs: inherited("codecs").map(Into::into),
+                    bandwidth,
+                    width: inherited("width").and_then(|w| w.parse().ok()),
+                    height: inherited("height").and_then(|h| h.parse().ok()),
+                    frame_rate: inherited("frameRate").and_then(parse_frame_rate),
+                    url: base_url(rep, &set_base),
+                    ..Default::default()
+                };
+
+                let template = children(rep, "SegmentTemplate")
+                    .next()
+                    .map(|node| set_template.clone().merge(node))
+                    .unwrap_or_else(|| set_template.clone());
+
+                if let Some(list) = children(rep, "SegmentList")
+                    .next()
+                    .or_else(|| children(set, "SegmentList").next())
+                {
+                    segment_list(list, &mut representation);
+                } else if template.media.is_some() {
+                    template.expand(&mut representation, bandwidth, period_duration);
+                } else if let Some(base) = children(rep, "SegmentBase")
+                    .next()
+                    .or_else(|| children(set, "SegmentBase").next())
+                {
+                    // single file, the init range is only needed for seeking
+                    representation.initialization = children(base, "Initialization")
+                        .next()
+                        .and_then(|init| init.attribute("range"))
+                        .and_then(parse_range)
+                        .map(|range| SegmentRef {
+                            url: representation.url.clone(),
+                            byte_range: Some(range),
+                        });
+                }
+
+                representations.push(representation);
+            }
+
+            adaptation_sets.push(AdaptationSet {
+                content_type: set.attribute("contentType").map(Into::into),
+                language: set.attribute("lang").map(Into::into),
+                representations,
+            });
+        }
+
+        parsed.periods.push(Period {
+            id: period.attribute("id").map(Into::into),
+            duration: period_duration,
+            adaptation_sets,
+        });
+    }
+
+    Ok(parsed)
+}
+
+impl Manifest {
+    /// Formats of the first period, multi period manifests are mostly ads spliced in
+    pub fn formats(&self, manifest_url: &str) -> Vec<Format> {
+        let Some(period) = self.periods.first() else {
+            return Vec::new();
+        };
+
+        let mut formats = Vec::new();
+
+        for set in &period.adaptation_sets {
+            for rep in &set.representations {
+                let mime = rep.mime_type.as_deref().unwrap_or_default();
+                let (vid_codec, audio_codec) = split_codecs(rep.codecs.as_deref());
+
+                let kind = match set.content_type.as_deref() {
+                    Some("audio") => FormatKind::Audio,
+                    Some("video") if audio_codec.is_some() => FormatKind::Combined,
+                    Some("video") => FormatKind::Video,
+                    _ if mime.starts_with("audio") => FormatKind::Audio,
+                    _ if mime.starts_with("video") && audio_codec.is_none() => FormatKind::Video,
+                    _ if mime.starts_with("video") => FormatKind::Combined,
+                    // subtitles & such
+                    _ => continue,
+                };
+
+                formats.push(Format {
+                    id: rep.id.clone(),
+                    url: if rep.segments.is_empty() {
+                        rep.url.clone()
+                    } else {
+                        format!(
+                            "{}#{}",
+                            manifest_url.split('#').next().unwrap_or_default(),
+                            rep.id
+                        )
+                        .into()
+                    },
+                    kind,
+                    container: mime
+                        .split_once('/')
+                        .map(|(_, sub)| sub)
+                        .unwrap_or("mp4")
+                        .into(),
+                    vid_codec: vid_codec.filter(|_| kind != FormatKind::Audio),
+                    audio_codec: audio_codec.or_else(|| {
+                        // audio sets usually only say mp4a
+                        (kind == FormatKind::Audio)
+                            .then(|| rep.codecs.clone())
+                            .flatten()
+                    }),
+                    width: rep.width,
+                    height: rep.height,
+                    fps: rep.frame_rate.map(|fps| fps.round() as u16),
+                    bitrate: rep.bandwidth,
+                    language: set.language.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        formats
+    }
+
+    pub fn representation(&self, id: &str) -> Option<&Representation> {
+        self.representations().find(|rep| &*rep.id == id)
+    }
+
+    /// Highest video representation, for links without a `#ID`
+    pub fn best_video(&self) -> Option<&Representation> {
+        self.representations()
+            .filter(|rep| rep.height.is_some())
+            .max_by_key(|rep| (rep.height, rep.bandwidth))
+    }
+
+    fn representations(&self) -> impl Iterator<Item = &Representation> {
+        self.periods
+            .iter()
+            .take(1)
+            .flat_map(|period| &period.adaptation_sets)
+            .flat_map(|set| &set.representations)
+    }
+}
+
+impl Template {
+    fn merge(mut self, node: Node) -> Self {
+        let number = |name| node.attribute(name).and_then(|value| value.parse().ok());
+
+        if let Some(media) = node.attribute("media") {
+            self.media = Some(media.into());
+        }
+
+        if let Some(init) = node.attribute("initialization") {
+            self.initialization = Some(init.into());
+        }
+
+        self.start_number = number("startNumber").or(self.start_number);
+        self.timescale = number("timescale").or(self.timescale);
+        self.duration = number("duration").or(self.duration);
+
+        if let Some(timeline) = children(node, "SegmentTimeline").next() {
+            self.timeline = Some(
+                children(timeline, "S")
+                    .map(|s| {
+                        let attr = |name| s.attribute(name).and_then(|value| value.parse().ok());
+                        (
+                            attr("t"),
+                            attr("d").unwrap_or_default(),
+                            s.attribute("r")
+                                .and_then(|r| r.parse().ok())
+                                .unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+            );
+        }
+
+        self
+    }
+
+    fn expand(&self, rep: &mut Representation, bandwidth: Option<u64>, duration: Option<f64>) {
+        let Some(media) = self.media.as_deref() else {
+            return;
+        };
+
+        let fill = |template: &str, number: Option<u64>, time: Option<u64>| {
+            let url = fill_template(template, &rep.id, bandwidth, number, time);
+            resolve(&rep.url, &url)
+        };
+
+        rep.initialization = self.initialization.as_deref().map(|init| SegmentRef {
+            url: fill(init, None, None),
+            byte_range: None,
+        });
+
+        let start_number = self.start_number.unwrap_or(1);
+        let timescale = self.timescale.unwrap_or(1).max(1);
+
+        let mut segments = Vec::new();
+
+        if let Some(timeline) = &self.timeline {
+            let end = duration.map(|duration| (duration * timescale as f64) as u64);
+            let mut time = 0;
+
+            for (i, &(t, d, r)) in timeline.iter().enumerate() {
+                time = t.unwrap_or(time);
+
+                let repeat = match r {
+                    // repeat until the next S or the end of the period
+                    r if r < 0 => {
+                        let until = timeline.get(i + 1).and_then(|next| next.0).or(end);
+                        until.map_or(0, |until| {
+                            until
+                                .saturating_sub(time)
+                                .div_ceil(d.max(1))
+                                .saturating_sub(1)
+                        })
+                    }
+                    r => r as u64,
+                };
+
+                for _ in 0..=repeat {
+                    let number = start_number + segments.len() as u64;
+                    segments.push(fill(media, Some(number), Some(time)));
+                    time += d;
+                }
+            }
+        } else if let (Some(segment_duration), Some(duration)) = (self.duration, duration) {
+            let count =
+                (duration * timescale as f64 / segment_duration.max(1) as f64).ceil() as u64;
+
+            segments = (0..count)
+                .map(|i| {
+                    let number = start_number + i;
+                    fill(media, Some(number), Some(i * segment_duration))
+                })
+                .collect();
+        }
+
+        rep.segments = segments
+            .into_iter()
+            .map(|url| SegmentRef {
+                url,
+                byte_range: None,
+            })
+            .collect();
+    }
+}
+
+fn segment_list(list: Node, rep: &mut Representation) {
+    rep.initialization = children(list, "Initialization")
+        .next()
+        .map(|init| SegmentRef {
+            url: init
+                .attribute("sourceURL")
+                .map_or_else(|| rep.url.clone(), |url| resolve(&rep.url, url)),
+            byte_range: init.attribute("range").and_then(parse_range),
+        });
+
+    rep.segments = children(list, "SegmentURL")
+        .map(|segment| SegmentRef {
+            url: segment
+                .attribute("media")
+                .map_or_else(|| rep.url.clone(), |url| resolve(&rep.url, url)),
+            byte_range: segment.attribute("mediaRange").and_then(parse_range),
+        })
+        .collect();
+}
+
+/// `$RepresentationID$`, `$Number%05d$`, `$Bandwidth$`, `$Time$` & `$$`
+fn fill_template(
+    template: &str,
+    id: &str,
+    bandwidth: Option<u64>,
+    number: Option<u64>,
+    time: Option<u64>,
+) -> String {
+    let mut filled = String::new();
+    let mut parts = template.split('$');
+
+    if let Some(first) = parts.next() {
+        filled.push_str(first);
+    }
+
+    // identifiers sit between every pair of $
+    while let (Some(identifier), Some(text)) = (parts.next(), parts.next()) {
+        let (name, width) = match identifier.split_once('%') {
+            Some((name, format)) => (
+                name,
+                format
+                    .trim_start_matches('0')
+                    .trim_end_matches('d')
+                    .parse()
+                    .unwrap_or(0),
+            ),
+            None => (identifier, 0),
+        };
+
+        let value = match name {
+            "" => Some(String::from("$")),
+            "RepresentationID" => Some(id.to_owned()),
+            "Number" => number.map(|number| format!("{number:0width$}")),
+            "Time" => time.map(|time| format!("{time:0width$}")),
+            "Bandwidth" => bandwidth.map(|bandwidth| format!("{bandwidth:0width$}")),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => filled.push_str(&value),
+            None => {
+                filled.push('$');
+                filled.push_str(identifier);
+                filled.push('$');
+            }
+        }
+
+        filled.push_str(text);
+    }
+
+    filled
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn base_url(node: Node, parent: &str) -> Box<str> {
+    match children(node, "BaseURL")
+        .next()
+        .and_then(|base| base.text())
+    {
+        Some(base) => resolve(parent, base.trim()),
+        None => parent.into(),
+    }
+}
+
+/// `start-end`, both inclusive
+fn parse_range(range: &str) -> Option<ByteRange> {
+    let (start, end) = range.split_once('-')?;
+    let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+
+    Some(ByteRange {
+        length: end.checked_sub(start)? + 1,
+        offset: start,
+    })
+}
+
+/// `30`, `30000/1001`
+fn parse_frame_rate(rate: &str) -> Option<f32> {
+    match rate.split_once('/') {
+        Some((num, den)) => Some(num.parse::<f32>().ok()? / den.parse::<f32>().ok()?),
+        None => rate.parse().ok(),
+    }
+}
+
+/// ISO 8601 durations like `PT1H2M3.5S` or `P0Y0M0DT0H3M30.000S`, in seconds
+fn parse_duration(duration: &str) -> Option<f64> {
+    let (date, time) = duration
+        .strip_prefix('P')?
+        .split_once('T')
+        .unwrap_or((duration.strip_prefix('P')?, ""));
+
+    let mut seconds = 0.0;
+
+    for (part, units) in [
+        (
+            date,
+            [('Y', 31_536_000.0), ('M', 2_592_000.0), ('D', 86_400.0)],
+        ),
+        (time, [('H', 3600.0), ('M', 60.0), ('S', 1.0)]),
+    ] {
+        let mut number = String::new();
+
+        for char in part.chars() {
+            match units.iter().find(|(unit, _)| *unit == char) {
+                Some((_, multiplier)) => {
+                    seconds += number.parse::<f64>().ok()? * multiplier;
+                    number.clear();
+                }
+                None => number.push(char),
+            }
+        }
+    }
+
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(timeline: &str, duration: &str) -> Vec<Box<str>> {
+        let manifest = format!(
+            r#"<MPD mediaPresentationDuration="{duration}"><Period><AdaptationSet mimeType="video/mp4">
+<SegmentTemplate timescale="10" media="$Time$.m4s" initialization="init.mp4">
+<SegmentTimeline>{timeline}</SegmentTimeline></SegmentTemplate>
+<Representation id="v" bandwidth="1000" codecs="avc1.64001f"/>
+</AdaptationSet></Period></MPD>"#
+        );
+        let parsed = parse(&manifest, "https://cdn.test/a/manifest.mpd").unwrap();
+        let rep = &parsed.periods[0].adaptation_sets[0].representations[0];
+
+        assert_eq!(
+            rep.initialization.as_ref().map(|init| &*init.url),
+            Some("https://cdn.test/a/init.mp4")
+        );
+
+        rep.segments
+            .iter()
+            .map(|segment| segment.url.trim_start_matches("https://cdn.test/a/").into())
+            .collect()
+    }
+
+    #[test]
+    fn timeline_repeats() {
+        assert_eq!(
+            segments(r#"<S t="0" d="20" r="2"/><S d="10"/>"#, "PT10S"),
+            ["0.m4s", "20.m4s", "40.m4s", "60.m4s"].map(Box::from)
+        );
+    }
+
+    #[test]
+    fn negative_repeat_fills_until_the_next_s_or_the_end() {
+        assert_eq!(
+            segments(
+                r#"<S t="0" d="20" r="-1"/><S t="60" d="30" r="-1"/>"#,
+                "PT12S"
+            ),
+            ["0.m4s", "20.m4s", "40.m4s", "60.m4s", "90.m4s"].map(Box::from)
+        );
+    }
+
+    #[test]
+    fn negative_repeat_past_the_end_doesnt_underflow() {
+        // the last S starts after the period ends, one segment & no endless loop
+        assert_eq!(
+            segments(r#"<S t="0" d="20"/><S t="200" d="20" r="-1"/>"#, "PT5S"),
+            ["0.m4s", "200.m4s"].map(Box::from)
+        );
+    }
+}
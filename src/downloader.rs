@@ -0,0 +1,1090 @@
+//! Built-in downloader, splits the file in ranged segments & fetches them in parallel
+//!
+//! Every segment is written to its own `.partN` file next to the output, so an
+//! interrupted download continues from where each segment stopped.
+//! HLS playlists & DASH manifests get their segments saved in a `.part` dir instead.
+
+use crate::{
+    cookies, dash,
+    helpers::reqwests::get,
+    hls::{self, ByteRange, Key, Map, MediaPlaylist},
+    Error,
+};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use isahc::{
+    config::{RedirectPolicy::Follow, VersionNegotiation},
+    http::Request,
+    prelude::Configurable,
+    HttpClient, ReadResponseExt,
+};
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, remove_dir_all, remove_file, rename, write, File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::Relaxed},
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAX_SEGMENTS: u64 = 16;
+const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+const RETRIES: u8 = 5;
+const SEGMENT_WORKERS: usize = 8;
+/// Polls without a new segment before a live stream counts as over
+const LIVE_IDLE_POLLS: u32 = 15;
+
+/// Download `link` to `path`, returns the size in bytes
+///
+/// `resolution` picks the variant of HLS master playlists, 0 for best
+pub fn download(
+    link: &str,
+    path: &Path,
+    user_agent: &str,
+    referrer: &str,
+    resolution: u16,
+) -> Result<u64, Error> {
+    if hls::is_playlist_link(link) {
+        return download_hls(link, path, user_agent, referrer, resolution);
+    }
+
+    if dash::is_manifest_link(link) {
+        return download_dash(link, path, user_agent, referrer);
+    }
+
+    let client = http_client(user_agent, referrer)?;
+
+    match content_length(&client, link)? {
+        Some(size) => download_ranged(&client, link, path, size),
+        None => download_single(&client, link, path),
+    }
+}
+
+/// Media playlist of the variant of `link` not above `resolution` (0 for best) &
+/// its separate audio rendition if it has one, `link` itself if it isn't a master
+pub fn resolve_hls(
+    link: &str,
+    user_agent: &str,
+    referrer: &str,
+    resolution: u16,
+) -> Result<(Box<str>, Option<Box<str>>), Error> {
+    let client = http_client(user_agent, referrer)?;
+    let playlist = fetch_text(&client, link)?;
+
+    if !hls::is_master(&playlist) {
+        return Ok((link.into(), None));
+    }
+
+    let master = hls::parse_master(&playlist, link);
+    let variant = master.pick_variant(resolution).ok_or(Error::NoFormats)?;
+    let audio = master
+        .audio_rendition(variant)
+        .and_then(|rendition| rendition.uri.clone());
+
+    Ok((variant.uri.clone(), audio))
+}
+
+/// Download a HLS playlist as one TS or fragmented mp4 file, returns the size in bytes
+///
+/// Master playlists are resolved to the best variant not above `resolution` (0 for best),
+/// variants with separate audio need [`resolve_hls`] & a merge instead
+pub fn download_hls(
+    link: &str,
+    path: &Path,
+    user_agent: &str,
+    referrer: &str,
+    resolution: u16,
+) -> Result<u64, Error> {
+    let client = http_client(user_agent, referrer)?;
+
+    let mut playlist_url = Box::from(link);
+    let mut playlist = fetch_text(&client, link)?;
+
+    if hls::is_master(&playlist) {
+        let master = hls::parse_master(&playlist, link);
+        let variant = master.pick_variant(resolution).ok_or(Error::NoFormats)?;
+
+        // only the video would be saved, without a sound
+        if master.audio_rendition(variant).is_some() {
+            return Err(io::Error::other(format!(
+                "{} has its audio in a separate rendition",
+                variant.uri
+            ))
+            .into());
+        }
+
+        playlist_url = variant.uri.clone();
+        playlist = fetch_text(&client, &playlist_url)?;
+    }
+
+    let media = hls::parse_media(&playlist, &playlist_url);
+    let segments = &media.segments;
+
+    if segments.is_empty() {
+        return Err(Error::NoFormats);
+    }
+
+    if !media.end_list {
+        return record_hls(&client, &playlist_url, media, path);
+    }
+
+    let mut keys = HashMap::new();
+
+    for key in segments.iter().filter_map(|segment| segment.key.as_ref()) {
+        fetch_key(&client, key, &mut keys)?;
+    }
+
+    let mut maps: Vec<(&Map, Vec<u8>)> = Vec::new();
+
+    for map in segments.iter().filter_map(|segment| segment.map.as_ref()) {
+        if !maps.iter().any(|(fetched, _)| *fetched == map) {
+            maps.push((map, fetch(&client, &map.uri, map.byte_range)?));
+        }
+    }
+
+    download_segments(
+        path,
+        segments.len(),
+        |i| {
+            let segment = &segments[i];
+            let bytes = fetch(&client, &segment.uri, segment.byte_range)?;
+
+            match (&segment.key, segment.iv()) {
+                (Some(key), Some(iv)) => {
+                    decrypt(bytes, &keys[key.uri.as_deref().unwrap_or_default()], iv)
+                }
+                _ => Ok(bytes),
+            }
+        },
+        |i| {
+            // init section goes before the first segment & again whenever it changes
+            let map = segments[i].map.as_ref()?;
+
+            if i > 0 && segments[i - 1].map.as_ref() == Some(map) {
+                return None;
+            }
+
+            maps.iter()
+                .find(|(fetched, _)| *fetched == map)
+                .map(|(_, init)| init.as_slice())
+        },
+    )
+}
+
+/// Keep appending the new segments of a live playlist until it ends
+fn record_hls(
+    client: &HttpClient,
+    playlist_url: &str,
+    mut media: MediaPlaylist,
+    path: &Path,
+) -> Result<u64, Error> {
+    let mut output = File::create(path)?;
+    let mut keys = HashMap::new();
+    let mut last_map: Option<Map> = None;
+    let mut next_sequence = 0;
+    let mut idle = 0;
+    let done = AtomicU64::new(0);
+    let finished = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        scope.spawn(|| progress(&done, &AtomicU64::new(0), &finished));
+
+        let result = (|| loop {
+            let new = media
+                .segments
+                .iter()
+                .filter(|segment| segment.sequence >= next_sequence)
+                .collect::<Vec<_>>();
+
+            idle = if new.is_empty() { idle + 1 } else { 0 };
+
+            for segment in new {
+                if let Some(map) = segment.map.as_ref() {
+                    if last_map.as_ref() != Some(map) {
+                        output.write_all(&fetch(client, &map.uri, map.byte_range)?)?;
+                        last_map = Some(map.clone());
+                    }
+                }
+
+                let mut bytes = fetch(client, &segment.uri, segment.byte_range)?;
+
+                if let (Some(key), Some(iv)) = (&segment.key, segment.iv()) {
+                    bytes = decrypt(bytes, &fetch_key(client, key, &mut keys)?, iv)?;
+                }
+
+                output.write_all(&bytes)?;
+                done.fetch_add(bytes.len() as u64, Relaxed);
+                next_sequence = segment.sequence + 1;
+            }
+
+            // a playlist which stopped moving without the end tag is a stream that died
+            if media.end_list || idle > LIVE_IDLE_POLLS {
+                return Ok::<_, Error>(());
+            }
+
+            thread::sleep(Duration::from_secs(
+                media.target_duration.unwrap_or(5).max(1).into(),
+            ));
+            media = hls::parse_media(&fetch_text(client, playlist_url)?, playlist_url);
+        })();
+
+        finished.store(true, Relaxed);
+        result
+    })?;
+
+    output.flush()?;
+    Ok(output.metadata()?.len())
+}
+
+/// Record a youtube live format from the start of its DVR window until the stream ends,
+/// segment N of these links is at `&sq=N`
+pub fn download_live(
+    link: &str,
+    path: &Path,
+    user_agent: &str,
+    referrer: &str,
+) -> Result<u64, Error> {
+    let client = http_client(user_agent, referrer)?;
+
+    // body if the segment is there & the newest sequence number
+    let segment = |sq: u64| -> Result<(Option<Vec<u8>>, Option<u64>), Error> {
+        let mut resp = get(&client, &format!("{link}&sq={sq}"))?;
+        let head = resp
+            .headers()
+            .get("x-head-seqnum")
+            .and_then(|head| head.to_str().ok()?.parse().ok());
+
+        if !resp.status().is_success() {
+            resp.consume().ok();
+            return Ok((None, head));
+        }
+
+        let mut bytes = Vec::new();
+        resp.body_mut().read_to_end(&mut bytes)?;
+
+        Ok((Some(bytes), head))
+    };
+
+    let mut sq = 0;
+
+    // older segments fall out of the DVR window, find the first one still there
+    if let (None, Some(head)) = segment(0)? {
+        let (mut low, mut high) = (0, head);
+
+        while low < high {
+            let mid = (low + high) / 2;
+
+            if segment(mid)?.0.is_some() {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        sq = low;
+    }
+
+    let mut output = File::create(path)?;
+    let mut idle = 0;
+    let done = AtomicU64::new(0);
+    let finished = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        scope.spawn(|| progress(&done, &AtomicU64::new(0), &finished));
+
+        loop {
+            match segment(sq) {
+                Ok((Some(bytes), _)) => {
+                    if let Err(err) = output.write_all(&bytes) {
+                        finished.store(true, Relaxed);
+                        return Err(err.into());
+                    }
+
+                    done.fetch_add(bytes.len() as u64, Relaxed);
+                    sq += 1;
+                    idle = 0;
+                }
+                // caught up with the stream or a hiccup, wait for the next segment
+                _ if idle < LIVE_IDLE_POLLS => {
+                    idle += 1;
+                    thread::sleep(Duration::from_secs(2));
+                }
+                _ => break,
+            }
+        }
+
+        finished.store(true, Relaxed);
+        Ok::<_, Error>(())
+    })?;
+
+    output.flush()?;
+
+    match output.metadata()?.len() {
+        0 => Err(Error::NoFormats),
+        size => Ok(size),
+    }
+}
+
+/// AES-128 key of a segment, fetched once per uri
+fn fetch_key(
+    client: &HttpClient,
+    key: &Key,
+    keys: &mut HashMap<Box<str>, [u8; 16]>,
+) -> Result<[u8; 16], Error> {
+    let uri = match (&*key.method, &key.uri) {
+        ("AES-128", Some(uri)) => uri,
+        (method, _) => {
+            return Err(io::Error::other(format!("Unsupported HLS encryption: {method}")).into())
+        }
+    };
+
+    if let Some(key) = keys.get(uri) {
+        return Ok(*key);
+    }
+
+    let key: [u8; 16] = fetch(client, uri, None)?
+        .try_into()
+        .map_err(|_| io::Error::other("HLS key isn't 16 bytes"))?;
+
+    keys.insert(uri.clone(), key);
+    Ok(key)
+}
+
+/// Download a representation of a DASH manifest, `manifest.mpd#ID` picks the
+/// representation otherwise the best video is used
+pub fn download_dash(
+    link: &str,
+    path: &Path,
+    user_agent: &str,
+    referrer: &str,
+) -> Result<u64, Error> {
+    let client = http_client(user_agent, referrer)?;
+    let (manifest_url, id) = link.split_once('#').unwrap_or((link, ""));
+    let manifest = dash::parse(&fetch_text(&client, manifest_url)?, manifest_url)?;
+
+    let rep = if id.is_empty() {
+        manifest.best_video()
+    } else {
+        manifest.representation(id)
+    }
+    .ok_or_else(|| Error::UnknownFormat(id.into()))?;
+
+    if rep.segments.is_empty() {
+        return match content_length(&client, &rep.url)? {
+            Some(size) => download_ranged(&client, &rep.url, path, size),
+            None => download_single(&client, &rep.url, path),
+        };
+    }
+
+    let init = rep
+        .initialization
+        .as_ref()
+        .map(|init| fetch(&client, &init.url, init.byte_range))
+        .transpose()?;
+
+    download_segments(
+        path,
+        rep.segments.len(),
+        |i| fetch(&client, &rep.segments[i].url, rep.segments[i].byte_range),
+        |i| init.as_deref().filter(|_| i == 0),
+    )
+}
+
+/// Fetch `count` segments in parallel into a `.part` dir & concatenate them,
+/// `header` is written before a segment (init sections)
+fn download_segments<'a>(
+    path: &Path,
+    count: usize,
+    fetch_segment: impl Fn(usize) -> Result<Vec<u8>, Error> + Sync,
+    header: impl Fn(usize) -> Option<&'a [u8]>,
+) -> Result<u64, Error> {
+    let part_dir = part_path(path, "");
+    create_dir_all(&part_dir)?;
+
+    let segment_path = |i: usize| part_dir.join(format!("{i:05}.seg"));
+    let next = AtomicUsize::new(0);
+    let completed = AtomicU64::new(0);
+    let done = AtomicU64::new(0);
+    let size = AtomicU64::new(0);
+    let finished = AtomicBool::new(false);
+    let failed = AtomicBool::new(false);
+
+    let results = thread::scope(|scope| {
+        scope.spawn(|| progress(&done, &size, &finished));
+
+        let results = (0..SEGMENT_WORKERS.min(count))
+            .map(|_| {
+                scope.spawn(|| -> Result<(), Error> {
+                    loop {
+                        let i = next.fetch_add(1, Relaxed);
+
+                        if i >= count || failed.load(Relaxed) {
+                            return Ok(());
+                        }
+
+                        let path = segment_path(i);
+
+                        let len = match path.metadata() {
+                            // finished in an earlier run
+                            Ok(metadata) => metadata.len(),
+                            Err(_) => {
+                                let result = fetch_segment(i).and_then(|bytes| {
+                                    // rename only once complete, so resume never sees half a segment
+                                    let tmp = path.with_extension("tmp");
+                                    write(&tmp, &bytes)?;
+                                    rename(&tmp, &path)?;
+                                    Ok(bytes.len() as u64)
+                                });
+
+                                if result.is_err() {
+                                    failed.store(true, Relaxed);
+                                }
+
+                                result?
+                            }
+                        };
+
+                        let bytes = done.fetch_add(len, Relaxed) + len;
+                        let completed = completed.fetch_add(1, Relaxed) + 1;
+                        // estimated from the average segment size
+                        size.store(bytes * count as u64 / completed, Relaxed);
+                    }
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("thread panicked").into()))
+            })
+            .collect::<Vec<_>>();
+
+        finished.store(true, Relaxed);
+        results
+    });
+
+    results.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let mut output = File::create(path)?;
+
+    for i in 0..count {
+        if let Some(header) = header(i) {
+            output.write_all(header)?;
+        }
+
+        io::copy(&mut File::open(segment_path(i))?, &mut output)?;
+    }
+
+    output.flush()?;
+    remove_dir_all(&part_dir).ok();
+
+    Ok(output.metadata()?.len())
+}
+
+fn http_client(user_agent: &str, referrer: &str) -> Result<HttpClient, Error> {
+    // the extractors' client insists on http2 which not every media server speaks
+    HttpClient::builder()
+        .version_negotiation(VersionNegotiation::latest_compatible())
+        .redirect_policy(Follow)
+        .default_headers(&[("user-agent", user_agent), ("referer", referrer)])
+        .build()
+        .map_err(Error::from)
+}
+
+/// Whole body of `link` or the byte range of it, retried on failure
+fn fetch(client: &HttpClient, link: &str, range: Option<ByteRange>) -> Result<Vec<u8>, Error> {
+    let mut attempt = 0;
+
+    loop {
+        let mut request = Request::get(link);
+
+        if let Some(ByteRange { length, offset }) = range {
+            request = request.header("range", format!("bytes={}-{}", offset, offset + length - 1));
+        }
+
+        let result = client
+            .send(cookies::attach(request.body(())?))
+            .map_err(Error::from)
+            .and_then(|mut resp| {
+                if !resp.status().is_success() {
+                    return Err(io::Error::other(format!(
+                        "Server responded with {}",
+                        resp.status()
+                    ))
+                    .into());
+                }
+
+                let mut bytes = Vec::new();
+                resp.body_mut().read_to_end(&mut bytes)?;
+
+                match resp.body().len() {
+                    Some(length) if length != bytes.len() as u64 => {
+                        Err(size_mismatch(length, bytes.len() as u64))
+                    }
+                    _ => Ok(bytes),
+                }
+            });
+
+        match result {
+            Err(_) if attempt < RETRIES => {
+                attempt += 1;
+                thread::sleep(Duration::from_secs(attempt.into()));
+            }
+            result => return result,
+        }
+    }
+}
+
+fn fetch_text(client: &HttpClient, link: &str) -> Result<String, Error> {
+    Ok(String::from_utf8_lossy(&fetch(client, link, None)?).into_owned())
+}
+
+fn decrypt(mut bytes: Vec<u8>, key: &[u8; 16], iv: [u8; 16]) -> Result<Vec<u8>, Error> {
+    let len = cbc::Decryptor::<aes::Aes128>::new(key.into(), &iv.into())
+        .decrypt_padded_mut::<Pkcs7>(&mut bytes)
+        .map_err(|_| io::Error::other("Failed to decrypt HLS segment"))?
+        .len();
+
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+/// Total size if the server supports byte ranges
+fn content_length(client: &HttpClient, link: &str) -> Result<Option<u64>, Error> {
+    let mut resp = client.send(cookies::attach(
+        Request::get(link).header("range", "bytes=0-0").body(())?,
+    ))?;
+    // don't keep the connection busy with the rest of the body
+    resp.consume().ok();
+
+    if resp.status().as_u16() != 206 {
+        return Ok(None);
+    }
+
+    Ok(resp
+        .headers()
+        .get("content-range")
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.rsplit_once('/'))
+        .and_then(|(_, size)| size.parse().ok()))
+}
+
+fn download_ranged(client: &HttpClient, link: &str, path: &Path, size: u64) -> Result<u64, Error> {
+    let segments = ranges(size)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start, end))| (part_path(path, i), start, end))
+        .collect::<Vec<_>>();
+
+    let done = AtomicU64::new(0);
+    let finished = AtomicBool::new(false);
+
+    let results = thread::scope(|scope| {
+        scope.spawn(|| progress(&done, &AtomicU64::new(size), &finished));
+
+        let results = segments
+            .iter()
+            .map(|(part, start, end)| {
+                let done = &done;
+                scope.spawn(move || fetch_segment(client, link, part, *start, *end, done))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("thread panicked").into()))
+            })
+            .collect::<Vec<_>>();
+
+        finished.store(true, Relaxed);
+        results
+    });
+
+    results.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let mut output = File::create(path)?;
+
+    for (part, _, _) in &segments {
+        io::copy(&mut File::open(part)?, &mut output)?;
+    }
+
+    let written = output.metadata()?.len();
+
+    if written != size {
+        return Err(size_mismatch(size, written));
+    }
+
+    for (part, _, _) in &segments {
+        remove_file(part).ok();
+    }
+
+    Ok(size)
+}
+
+/// `start..end` of every connection, at least a MiB each & no more than 16 of them
+fn ranges(size: u64) -> Vec<(u64, u64)> {
+    let count = size.div_ceil(MIN_SEGMENT_SIZE).clamp(1, MAX_SEGMENTS);
+    let segment_size = size.div_ceil(count);
+
+    (0..count)
+        .map(|i| (i * segment_size, ((i + 1) * segment_size).min(size)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// Fetch `start..end` into `part`, restarting from the bytes already on disk
+fn fetch_segment(
+    client: &HttpClient,
+    link: &str,
+    part: &Path,
+    start: u64,
+    end: u64,
+    done: &AtomicU64,
+) -> Result<(), Error> {
+    let expected = end - start;
+    let mut file = OpenOptions::new().create(true).append(true).open(part)?;
+    let mut have = file.metadata()?.len();
+
+    // leftover from a download with a different size
+    if have > expected {
+        file.set_len(0)?;
+        have = 0;
+    }
+
+    done.fetch_add(have, Relaxed);
+
+    let mut attempt = 0;
+
+    while have < expected {
+        let range = format!("bytes={}-{}", start + have, end - 1);
+
+        let result = client
+            .send(cookies::attach(
+                Request::get(link).header("range", range).body(())?,
+            ))
+            .map_err(Error::from)
+            .and_then(|mut resp| {
+                let remaining = expected - have;
+
+                if resp.status().as_u16() != 206 {
+                    return Err(io::Error::other(format!(
+                        "Server ignored the range ({})",
+                        resp.status()
+                    ))
+                    .into());
+                }
+
+                if let Some(length) = resp.body().len() {
+                    if length != remaining {
+                        return Err(size_mismatch(remaining, length));
+                    }
+                }
+
+                copy_counting(resp.body_mut(), &mut file, done)
+            });
+
+        have = file.metadata()?.len();
+
+        // a response that ends early without saying how long it is still counts as failed
+        let result = result.and_then(|_| {
+            if have < expected {
+                Err(size_mismatch(expected, have))
+            } else {
+                Ok(())
+            }
+        });
+
+        if let Err(err) = result {
+            attempt += 1;
+
+            if attempt > RETRIES {
+                return Err(err);
+            }
+
+            thread::sleep(Duration::from_secs(attempt.into()));
+        }
+    }
+
+    if have != expected {
+        return Err(size_mismatch(expected, have));
+    }
+
+    Ok(())
+}
+
+/// Servers without range support only get one connection & no resume
+fn download_single(client: &HttpClient, link: &str, path: &Path) -> Result<u64, Error> {
+    let part = part_path(path, 0);
+    let mut resp = get(client, link)?;
+
+    if !resp.status().is_success() {
+        return Err(io::Error::other(format!("Server responded with {}", resp.status())).into());
+    }
+
+    let size = resp.body().len();
+    let done = AtomicU64::new(0);
+    let finished = AtomicBool::new(false);
+
+    let written = thread::scope(|scope| {
+        scope.spawn(|| progress(&done, &AtomicU64::new(size.unwrap_or_default()), &finished));
+
+        let written = File::create(&part)
+            .map_err(Error::from)
+            .and_then(|mut file| copy_counting(resp.body_mut(), &mut file, &done))
+            .map(|_| done.load(Relaxed));
+
+        finished.store(true, Relaxed);
+        written
+    })?;
+
+    if let Some(size) = size.filter(|&size| size != written) {
+        return Err(size_mismatch(size, written));
+    }
+
+    rename(&part, path)?;
+
+    Ok(written)
+}
+
+fn copy_counting(reader: &mut impl Read, file: &mut File, done: &AtomicU64) -> Result<(), Error> {
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(file.flush()?),
+            Ok(n) => {
+                file.write_all(&buf[..n])?;
+                done.fetch_add(n as u64, Relaxed);
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+fn progress(done: &AtomicU64, size: &AtomicU64, finished: &AtomicBool) {
+    const WIDTH: u64 = 30;
+
+    let started = Instant::now();
+    let initial = done.load(Relaxed);
+    let mut stderr = io::stderr();
+
+    // redrawing the bar only fills logs & pipes with escape codes
+    if !stderr.is_terminal() {
+        return;
+    }
+
+    loop {
+        let is_finished = finished.load(Relaxed);
+        let (bytes, size) = (done.load(Relaxed), size.load(Relaxed));
+        let speed = (bytes - initial) as f64 / started.elapsed().as_secs_f64().max(0.001);
+
+        let line = match (bytes.min(size) * WIDTH).checked_div(size) {
+            Some(filled) => format!(
+                "[{}{}] {:5.1}% {}/{} {}/s",
+                "#".repeat(filled as usize),
+                " ".repeat((WIDTH - filled) as usize),
+                bytes as f64 * 100.0 / size as f64,
+                human_size(bytes),
+                human_size(size),
+                human_size(speed as u64),
+            ),
+            // size is unknown
+            None => format!("{} {}/s", human_size(bytes), human_size(speed as u64)),
+        };
+
+        write!(stderr, "\r\x1b[2K{line}").ok();
+
+        if is_finished {
+            writeln!(stderr).ok();
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.2}{}", size, UNITS[unit])
+}
+
+fn part_path(path: &Path, suffix: impl std::fmt::Display) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(format!(".part{suffix}"));
+    part.into()
+}
+
+fn size_mismatch(expected: u64, got: u64) -> Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("Expected {expected} bytes but got {got}"),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cbc::cipher::BlockEncryptMut;
+    use std::{
+        io::{BufRead, BufReader},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+    };
+
+    /// Tiny http server for `files`, honours single byte ranges, returns its base url
+    fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/", listener.local_addr().unwrap());
+        let files = Arc::new(files);
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let files = files.clone();
+                thread::spawn(move || respond(stream, &files));
+            }
+        });
+
+        base
+    }
+
+    fn respond(mut stream: TcpStream, files: &[(&str, Vec<u8>)]) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut lines = Vec::new();
+
+        loop {
+            let mut line = String::new();
+
+            if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                break;
+            }
+
+            lines.push(line.trim_end().to_owned());
+        }
+
+        let path = lines
+            .first()
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap_or_default()
+            .trim_start_matches('/');
+        let range = lines.iter().find_map(|line| {
+            let (name, value) = line.split_once(": ")?;
+            name.eq_ignore_ascii_case("range")
+                .then(|| value.strip_prefix("bytes=")?.split_once('-'))
+                .flatten()
+                .map(|(start, end)| (start.to_owned(), end.to_owned()))
+        });
+
+        let Some((_, body)) = files.iter().find(|(name, _)| *name == path) else {
+            stream
+                .write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .ok();
+            return;
+        };
+
+        let (status, extra, body) = match range {
+            Some((start, end)) => {
+                let start = start.parse::<usize>().unwrap();
+                let end = end
+                    .parse()
+                    .map_or(body.len() - 1, |end: usize| end.min(body.len() - 1));
+                (
+                    "206 Partial Content",
+                    format!("Content-Range: bytes {start}-{end}/{}\r\n", body.len()),
+                    &body[start..=end],
+                )
+            }
+            None => ("200 OK", String::new(), &body[..]),
+        };
+
+        let head = format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n{extra}Connection: close\r\n\r\n",
+            body.len()
+        );
+
+        stream.write_all(head.as_bytes()).ok();
+        stream.write_all(body).ok();
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("titans-test-{}-{name}", std::process::id()))
+    }
+
+    fn encrypt(plain: &[u8], key: &[u8; 16], iv: [u8; 16]) -> Vec<u8> {
+        let mut buf = plain.to_vec();
+        buf.resize(plain.len().next_multiple_of(16) + 16, 0);
+
+        cbc::Encryptor::<aes::Aes128>::new(key.into(), &iv.into())
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, plain.len())
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn decrypts_aes_128_segments() {
+        let key = *b"0123456789abcdef";
+        let media = hls::parse_media(
+            "#EXT-X-MEDIA-SEQUENCE:42\n#EXT-X-KEY:METHOD=AES-128,URI=\"k\"\n#EXTINF:4,\ns.ts\n",
+            "https://cdn.test/",
+        );
+        let iv = media.segments[0].iv().unwrap();
+        let plain = b"not a multiple of the block size".repeat(3);
+
+        let encrypted = encrypt(&plain, &key, iv);
+
+        assert_ne!(encrypted, plain);
+        assert_eq!(decrypt(encrypted.clone(), &key, iv).unwrap(), plain);
+        // the wrong IV garbles the first block
+        assert_ne!(decrypt(encrypted, &key, [0; 16]).ok(), Some(plain));
+    }
+
+    #[test]
+    fn downloads_encrypted_hls() {
+        let key = *b"fedcba9876543210";
+        let iv = [7; 16];
+        let segments: [&[u8]; 3] = [
+            b"first plain segment",
+            b"second, sequence iv",
+            b"third, explicit iv",
+        ];
+
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXT-X-MEDIA-SEQUENCE:3
+#EXTINF:4,
+0.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"key\"
+#EXTINF:4,
+1.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"key\",IV=0x07070707070707070707070707070707
+#EXTINF:4,
+2.ts
+#EXT-X-ENDLIST
+";
+        let master = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=100,RESOLUTION=2x2
+low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=900,RESOLUTION=4x4
+media.m3u8
+";
+
+        let base = serve(vec![
+            ("master.m3u8", master.into()),
+            ("media.m3u8", playlist.into()),
+            ("key", key.to_vec()),
+            ("0.ts", segments[0].to_vec()),
+            ("1.ts", encrypt(segments[1], &key, 4u128.to_be_bytes())),
+            ("2.ts", encrypt(segments[2], &key, iv)),
+        ]);
+
+        let path = temp_path("hls.ts");
+        let size = download_hls(&format!("{base}master.m3u8"), &path, "test", &base, 0).unwrap();
+        let downloaded = std::fs::read(&path).unwrap();
+        remove_file(&path).ok();
+
+        assert_eq!(downloaded, segments.concat());
+        assert_eq!(size, downloaded.len() as u64);
+    }
+
+    #[test]
+    fn resolves_separate_hls_audio() {
+        let master = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=YES,URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=100,RESOLUTION=640x360,AUDIO=\"aac\"
+360.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=900,RESOLUTION=1280x720,AUDIO=\"aac\"
+720.m3u8
+";
+        let media = "#EXTM3U\n#EXTINF:4,\n0.ts\n#EXT-X-ENDLIST\n";
+        let base = serve(vec![
+            ("master.m3u8", master.into()),
+            ("media.m3u8", media.into()),
+        ]);
+        let link = format!("{base}master.m3u8");
+
+        let (video, audio) = resolve_hls(&link, "test", &base, 480).unwrap();
+        assert_eq!(*video, format!("{base}360.m3u8"));
+        assert_eq!(audio.as_deref(), Some(&*format!("{base}audio.m3u8")));
+
+        let (video, _) = resolve_hls(&link, "test", &base, 0).unwrap();
+        assert_eq!(*video, format!("{base}720.m3u8"));
+
+        let media_link = format!("{base}media.m3u8");
+        assert_eq!(
+            resolve_hls(&media_link, "test", &base, 0).unwrap(),
+            (media_link.into(), None)
+        );
+
+        // rather than a file without sound
+        let path = temp_path("no-audio.ts");
+        assert!(download_hls(&link, &path, "test", &base, 0).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn splits_ranges() {
+        const MIB: u64 = MIN_SEGMENT_SIZE;
+
+        assert_eq!(ranges(10), [(0, 10)]);
+        assert_eq!(ranges(MIB + 1), [(0, MIB / 2 + 1), (MIB / 2 + 1, MIB + 1)]);
+
+        for size in [1, MIB - 1, 3 * MIB + 7, 100 * MIB + 3] {
+            let ranges = ranges(size);
+
+            assert!(ranges.len() as u64 <= MAX_SEGMENTS);
+            assert_eq!(ranges.first().map(|range| range.0), Some(0));
+            assert_eq!(ranges.last().map(|range| range.1), Some(size));
+            // no gaps or overlaps
+            assert!(ranges.windows(2).all(|pair| pair[0].1 == pair[1].0));
+        }
+
+        assert_eq!(ranges(100 * MIB).len(), 16);
+    }
+
+    #[test]
+    fn resumes_from_part_files() {
+        let body = (0..3 * MIN_SEGMENT_SIZE + 5)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let base = serve(vec![("file.bin", body.clone())]);
+        let path = temp_path("ranged.bin");
+        let ranges = ranges(body.len() as u64);
+        assert_eq!(ranges.len(), 4);
+
+        // half done, bytes that aren't the server's so resuming is visible
+        std::fs::write(part_path(&path, 0), [0xAA; 1000]).unwrap();
+        // already complete
+        let (start, end) = ranges[1];
+        std::fs::write(part_path(&path, 1), &body[start as usize..end as usize]).unwrap();
+        // leftover of a bigger download, starts over
+        std::fs::write(
+            part_path(&path, 2),
+            vec![0xBB; 2 * MIN_SEGMENT_SIZE as usize],
+        )
+        .unwrap();
+
+        let size = download(&format!("{base}file.bin"), &path, "test", &base, 0).unwrap();
+        let downloaded = std::fs::read(&path).unwrap();
+        remove_file(&path).ok();
+
+        assert_eq!(size, body.len() as u64);
+        assert_eq!(downloaded.len(), body.len());
+        assert_eq!(downloaded[..1000], [0xAA; 1000]);
+        assert_eq!(downloaded[1000..], body[1000..]);
+        assert!((0..4).all(|i| !part_path(&path, i).exists()));
+    }
+}
@@ -0,0 +1,110 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    UnsupportedUrl(Box<str>),
+    AmbiguousUrl {
+        url: Box<str>,
+        extractors: Box<[&'static str]>,
+    },
+    Network(isahc::Error),
+    /// The site responded with something the extractor doesn't understand,
+    /// usually bcz its markup or api changed
+    ParseFailure {
+        extractor: &'static str,
+        field: &'static str,
+    },
+    Json(serde_json::Error),
+    GeoBlocked(Box<str>),
+    NeedsLogin(Box<str>),
+    /// Removed, private or otherwise gone for good
+    Unavailable(Box<str>),
+    /// Premiere or stream that hasn't started, worth retrying later
+    Upcoming(Box<str>),
+    NoFormats,
+    UnknownFormat(Box<str>),
+    InvalidSelector(Box<str>),
+    Io(io::Error),
+    /// The built-in muxer can't handle these files, ffmpeg can
+    UnsupportedMedia(Box<str>),
+}
+
+impl Error {
+    /// Exit code of the binary, so scripts can tell the failures apart
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::UnsupportedUrl(_) | Self::AmbiguousUrl { .. } => 2,
+            Self::Network(_) => 3,
+            Self::ParseFailure { .. } | Self::Json(_) => 4,
+            Self::GeoBlocked(_) => 5,
+            Self::NeedsLogin(_) => 6,
+            Self::NoFormats | Self::UnknownFormat(_) | Self::InvalidSelector(_) => 7,
+            Self::Io(_) => 8,
+            Self::UnsupportedMedia(_) => 9,
+            Self::Unavailable(_) => 10,
+            Self::Upcoming(_) => 11,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedUrl(url) => write!(f, "Unsupported link: {url}"),
+            Self::AmbiguousUrl { url, extractors } => write!(
+                f,
+                "Link matches multiple extractors ({}): {url}",
+                extractors.join(", ")
+            ),
+            Self::Network(err) => write!(f, "Network error: {err}"),
+            Self::ParseFailure { extractor, field } => {
+                write!(f, "{extractor}: Failed to get {field}")
+            }
+            Self::Json(err) => write!(f, "Invalid json response: {err}"),
+            Self::GeoBlocked(reason) => write!(f, "Geo blocked: {reason}"),
+            Self::NeedsLogin(reason) => write!(f, "Login required: {reason}"),
+            Self::Unavailable(reason) => write!(f, "Unavailable: {reason}"),
+            Self::Upcoming(reason) => write!(f, "Not available yet: {reason}"),
+            Self::NoFormats => write!(f, "No result"),
+            Self::UnknownFormat(id) => write!(f, "Requested format is not available: {id}"),
+            Self::InvalidSelector(selector) => write!(f, "Invalid format selector: {selector}"),
+            Self::Io(err) => err.fmt(f),
+            Self::UnsupportedMedia(what) => write!(f, "Unsupported by the built-in muxer: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(err) => Some(err),
+            Self::Json(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<isahc::Error> for Error {
+    fn from(err: isahc::Error) -> Self {
+        Self::Network(err)
+    }
+}
+
+impl From<isahc::http::Error> for Error {
+    fn from(err: isahc::http::Error) -> Self {
+        Self::Network(err.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
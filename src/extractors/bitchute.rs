@@ -1,14 +1,32 @@
+use super::Extractor;
 use crate::{
-    helpers::{reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
-    Vid,
+    helpers::{or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
+    Error, Options, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::error::Error;
 
-pub fn bitchute(url: &str) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "bitchute";
+
+pub struct Bitchute;
+
+impl Extractor for Bitchute {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["bitchute.com/"]
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        bitchute(url)
+    }
+}
+
+pub fn bitchute(url: &str) -> Result<Vid, Error> {
     let mut vid = {
-        let id = url.rsplit_once('/').unwrap().1;
+        let id = url.rsplit_once('/').or_fail(EXTRACTOR, "id")?.1;
 
         Vid {
             referrer: format!("https://www.bitchute.com/api/beta9/embed/{}", id).into(),
@@ -21,7 +39,7 @@ pub fn bitchute(url: &str) -> Result<Vid, Box<dyn Error>> {
     static RE: Lazy<Regex> = Lazy::new(|| {
         Regex::new(r#" +var video_name = "(.+)";[\s\S]+ +var media_url = '([^']+)"#).unwrap()
     });
-    let cap = RE.captures(&resp).expect("Failed to get link");
+    let cap = RE.captures(&resp).or_fail(EXTRACTOR, "link")?;
 
     vid.title = unescape_html_chars(&cap[1]);
     vid.vid_link = cap[2].into();
@@ -1,22 +1,53 @@
+use super::Extractor;
 use crate::{
     helpers::{
+        or_fail::OrFail,
         reqwests::{client, get_isahc_client},
         unescape_html_chars::unescape_html_chars,
     },
-    Vid,
+    Error, Options, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::{error::Error, time::SystemTime};
+use std::time::SystemTime;
 
-pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 7] = [
+    "doodstream.com/",
+    "dood.",
+    "d0o0d.com/",
+    "d0000d.com/",
+    "ds2play.com/",
+    "dooood.com/",
+    "doods.pro/",
+];
+
+const EXTRACTOR: &str = "doodstream";
+
+pub struct Doodstream;
+
+impl Extractor for Doodstream {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        doodstream(url, options.streaming_link)
+    }
+}
+
+pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Error> {
     const BASE_URL: &str = "https://dood.to";
 
     let mut vid = {
         url = url.trim_end_matches('/');
         let mut path = url
             .rsplit_once("/e/")
-            .unwrap_or(url.rsplit_once("/d/").expect("Imvalid doodstream link"))
+            .or_else(|| url.rsplit_once("/d/"))
+            .or_fail(EXTRACTOR, "id")?
             .1;
 
         path = path.split_once('/').map_or(path, |(id, _)| id);
@@ -34,7 +65,7 @@ pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn
         static RE_TITLE: Lazy<Regex> =
             Lazy::new(|| Regex::new(r"<title>(.+?) - DoodStream</title>").unwrap());
 
-        unescape_html_chars(&RE_TITLE.captures(&resp).expect("Failed to get title")[1])
+        unescape_html_chars(&RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1])
     };
 
     if is_streaming_link {
@@ -42,7 +73,7 @@ pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn
             static RE_TOKEN: Lazy<Regex> =
                 Lazy::new(|| Regex::new(r"(token=[^&]+)&expiry=").unwrap());
 
-            RE_TOKEN.captures(&resp).expect("Failed to get token")[1].into()
+            RE_TOKEN.captures(&resp).or_fail(EXTRACTOR, "token")?[1].into()
         };
 
         let link = {
@@ -52,7 +83,7 @@ pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn
             format!(
                 "{}{}",
                 BASE_URL,
-                &RE_MD5.captures(&resp).expect("Failed to get pass md5")[1]
+                &RE_MD5.captures(&resp).or_fail(EXTRACTOR, "pass md5")?[1]
             )
             .into_boxed_str()
         };
@@ -66,7 +97,7 @@ pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn
             token,
             SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
+                .unwrap_or_default()
                 .as_millis()
         )
         .into();
@@ -75,7 +106,7 @@ pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn
             static RE: Lazy<Regex> =
                 Lazy::new(|| Regex::new(r"get\('/pass_md5/([^/]+)/([^']+)").unwrap());
 
-            let captures = RE.captures(&resp).expect("Failed to get video link");
+            let captures = RE.captures(&resp).or_fail(EXTRACTOR, "video link")?;
 
             format!("{}/download/{}/n/{}", BASE_URL, &captures[2], &captures[1]).into_boxed_str()
         };
@@ -87,7 +118,7 @@ pub fn doodstream(mut url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn
             Lazy::new(|| Regex::new(r#"(https://[^.]+\.cloudatacdn\.com/[^"']+)"#).unwrap());
         vid.vid_link = RE_LINK
             .captures(&resp)
-            .expect("Failed to get download link")[1]
+            .or_fail(EXTRACTOR, "download link")?[1]
             .into();
     }
 
@@ -1,13 +1,36 @@
-use crate::{helpers::reqwests::get_isahc, Vid};
+use super::Extractor;
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::error::Error;
 
-pub fn libsyn(url: &str) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 2] = ["play.libsyn.com/", "html5-player.libsyn.com/"];
+
+const EXTRACTOR: &str = "libsyn";
+
+pub struct Libsyn;
+
+impl Extractor for Libsyn {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        libsyn(url)
+    }
+}
+
+pub fn libsyn(url: &str) -> Result<Vid, Error> {
     let mut vid = {
         let mut id = url
             .split_once("/episode/id/")
-            .expect("Invalid Libsyn url")
+            .or_fail(EXTRACTOR, "id")?
             .1
             .trim_end_matches('/');
         id = id.split_once('/').unwrap_or((id, "")).0;
@@ -21,12 +44,12 @@ pub fn libsyn(url: &str) -> Result<Vid, Box<dyn Error>> {
     let resp = get_isahc(&vid.referrer, vid.user_agent, &vid.referrer)?;
 
     static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#""media_url":"([^"]*)"#).unwrap());
-    vid.vid_link = RE.captures(&resp).expect("Failed to get link")[1]
+    vid.vid_link = RE.captures(&resp).or_fail(EXTRACTOR, "link")?[1]
         .replace("\\/", "/")
         .into();
 
     static RE_TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r#""item_title":"([^"]*)"#).unwrap());
-    vid.title = RE_TITLE.captures(&resp).expect("Failed to get link")[1].into();
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1].into();
 
     Ok(vid)
 }
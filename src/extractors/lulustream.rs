@@ -1,11 +1,34 @@
-use crate::{helpers::reqwests::get_isahc, Vid};
+use super::Extractor;
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::error::Error;
 
-pub fn lulustream(url: &str) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 4] = ["lulu.st/", "lulustream.com/", "luluvdo.com/", "cdn1.site/"];
+
+const EXTRACTOR: &str = "lulustream";
+
+pub struct Lulustream;
+
+impl Extractor for Lulustream {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        lulustream(url)
+    }
+}
+
+pub fn lulustream(url: &str) -> Result<Vid, Error> {
     let mut vid = {
-        let id = url.rsplit_once('/').unwrap().1;
+        let id = url.rsplit_once('/').or_fail(EXTRACTOR, "id")?.1;
 
         Vid {
             referrer: format!("https://cdn1.site/e/{}", id).into(),
@@ -16,14 +39,14 @@ pub fn lulustream(url: &str) -> Result<Vid, Box<dyn Error>> {
     let resp = get_isahc(&vid.referrer, vid.user_agent, &vid.referrer)?;
 
     static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"sources: \[\{file:"([^"]+)"#).unwrap());
-    vid.vid_link = RE.captures(&resp).expect("Failed to get link")[1]
+    vid.vid_link = RE.captures(&resp).or_fail(EXTRACTOR, "link")?[1]
         .replacen("/master.m3u8?t=", "/index-v1-a1.m3u8?t=", 1)
         .into();
 
     static RE_TITLE: Lazy<Regex> = Lazy::new(|| {
         Regex::new(r"<title>(.+?)( *- Lulustream\.mp4)? *- LuluStream</title>").unwrap()
     });
-    vid.title = RE_TITLE.captures(&resp).expect("Failed to get link")[1].into();
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1].into();
 
     Ok(vid)
 }
@@ -17,3 +17,173 @@ pub mod twatter;
 pub mod vtube;
 pub mod wolfstream;
 pub mod youtube;
+
+use crate::{Error, Options, Vid};
+
+pub trait Extractor: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Url prefixes (without the scheme & `www.`) handled by the extractor
+    fn prefixes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Patterns which can occur anywhere in the url
+    fn patterns(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn matches(&self, url: &str) -> bool {
+        self.prefixes()
+            .iter()
+            .any(|&prefix| url.starts_with(prefix))
+            || self.patterns().iter().any(|&pattern| url.contains(pattern))
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error>;
+}
+
+pub static EXTRACTORS: [&dyn Extractor; 19] = [
+    &bitchute::Bitchute,
+    &doodstream::Doodstream,
+    &libsyn::Libsyn,
+    &lulustream::Lulustream,
+    &mp4upload::Mp4upload,
+    &odysee::Odysee,
+    &reddit::Reddit,
+    &rokfin::Rokfin,
+    &rumble::Rumble,
+    &spotify::Spotify,
+    &streamdav::Streamdav,
+    &streamhub::Streamhub,
+    &streamtape::Streamtape,
+    &streamvid::Streamvid,
+    &substack::Substack,
+    &twatter::Twatter,
+    &vtube::Vtube,
+    &wolfstream::Wolfstream,
+    &youtube::Youtube,
+];
+
+/// Resolve the url (without the scheme & `www.`) to exactly one extractor
+pub fn find_extractor(url: &str) -> Result<&'static dyn Extractor, Error> {
+    find_in(&EXTRACTORS, url)
+}
+
+fn find_in(
+    extractors: &[&'static dyn Extractor],
+    url: &str,
+) -> Result<&'static dyn Extractor, Error> {
+    let mut matched = extractors
+        .iter()
+        .copied()
+        .filter(|extractor| extractor.matches(url));
+
+    match (matched.next(), matched.next()) {
+        (Some(extractor), None) => Ok(extractor),
+        (None, _) => Err(Error::UnsupportedUrl(format!("https://{url}").into())),
+        (Some(first), Some(second)) => Err(Error::AmbiguousUrl {
+            url: format!("https://{url}").into(),
+            extractors: [first, second]
+                .into_iter()
+                .chain(matched)
+                .map(|extractor| extractor.name())
+                .collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(url: &str) -> Result<&'static str, Error> {
+        find_extractor(url).map(|extractor| extractor.name())
+    }
+
+    struct Fake(&'static str, &'static [&'static str]);
+
+    impl Extractor for Fake {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn prefixes(&self) -> &'static [&'static str] {
+            self.1
+        }
+
+        fn extract(&self, _: &str, _: &Options) -> Result<Vid, Error> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn finds_the_extractor() {
+        assert_eq!(name("youtube.com/watch?v=dQw4w9WgXcQ").unwrap(), "youtube");
+        assert_eq!(name("youtu.be/dQw4w9WgXcQ").unwrap(), "youtube");
+        assert_eq!(
+            name("music.youtube.com/watch?v=dQw4w9WgXcQ").unwrap(),
+            "youtube"
+        );
+        assert_eq!(name("x.com/user/status/1").unwrap(), "twatter");
+        assert_eq!(name("redd.it/abc").unwrap(), "reddit");
+        assert_eq!(name("streamtape.com/v/abc").unwrap(), "streamtape");
+        assert_eq!(name("streamvid.net/abc").unwrap(), "streamvid");
+    }
+
+    #[test]
+    fn unsupported_hosts() {
+        let err = name("vimeo.com/76979871").unwrap_err();
+
+        assert!(
+            matches!(&err, Error::UnsupportedUrl(url) if &**url == "https://vimeo.com/76979871")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Unsupported link: https://vimeo.com/76979871"
+        );
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[test]
+    fn lookalike_hosts_dont_match() {
+        for url in [
+            "xx.com/user/status/1",
+            "doodle.com/poll/abc",
+            "redditmedia.com/abc",
+            "rokfin.com/stream/1",
+            "streamtapes.example/v/abc",
+            "open.spotify.com/track/abc",
+        ] {
+            assert!(
+                matches!(name(url), Err(Error::UnsupportedUrl(_))),
+                "{url}: {:?}",
+                name(url)
+            );
+        }
+    }
+
+    #[test]
+    fn ambiguous_urls_name_every_match() {
+        static A: Fake = Fake("a", &["video."]);
+        static B: Fake = Fake("b", &["video.site/"]);
+        static C: Fake = Fake("c", &["other.site/"]);
+        let extractors: [&'static dyn Extractor; 3] = [&A, &B, &C];
+
+        let err = find_in(&extractors, "video.site/1")
+            .map(|extractor| extractor.name())
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, Error::AmbiguousUrl { extractors, .. } if **extractors == ["a", "b"])
+        );
+        assert_eq!(
+            err.to_string(),
+            "Link matches multiple extractors (a, b): https://video.site/1"
+        );
+        assert_eq!(err.exit_code(), 2);
+
+        assert_eq!(find_in(&extractors, "video.other/1").unwrap().name(), "a");
+        assert_eq!(find_in(&extractors, "other.site/1").unwrap().name(), "c");
+    }
+}
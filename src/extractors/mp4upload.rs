@@ -1,12 +1,37 @@
-use std::error::Error;
+use super::Extractor;
 
-use crate::{helpers::reqwests::get_isahc, Vid};
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-pub fn mp4upload(url: &str) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "mp4upload";
+
+pub struct Mp4upload;
+
+impl Extractor for Mp4upload {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["mp4upload.com/"]
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        mp4upload(url)
+    }
+}
+
+pub fn mp4upload(url: &str) -> Result<Vid, Error> {
     let mut vid = {
-        let id = url.rsplit_once('/').unwrap().1.trim_start_matches("embed-");
+        let id = url
+            .rsplit_once('/')
+            .or_fail(EXTRACTOR, "id")?
+            .1
+            .trim_start_matches("embed-");
 
         Vid {
             referrer: format!("https://www.mp4upload.com/embed-{}", id).into(),
@@ -18,7 +43,7 @@ pub fn mp4upload(url: &str) -> Result<Vid, Box<dyn Error>> {
 
     static RE: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"src: "(https://[^.]*\.mp4upload\.com/files/[^"]*)"#).unwrap());
-    vid.vid_link = RE.captures(&resp).expect("Failed to get link")[1].into();
+    vid.vid_link = RE.captures(&resp).or_fail(EXTRACTOR, "link")?[1].into();
 
     Ok(vid)
 }
@@ -1,16 +1,42 @@
+use super::Extractor;
 use crate::{
-    helpers::{reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
-    Vid,
+    helpers::{or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
+    Error, Options, Thumbnail, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::error::Error;
 
-pub fn odysee(url: &str) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 4] = [
+    "odysee.com/",
+    // Librarian
+    "lbry.",
+    "librarian.",
+    "odysee.076.ne.jp/",
+];
+
+const EXTRACTOR: &str = "odysee";
+
+pub struct Odysee;
+
+impl Extractor for Odysee {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        odysee(url)
+    }
+}
+
+pub fn odysee(url: &str) -> Result<Vid, Error> {
     let mut vid = {
         let path = url
             .split_once('/')
-            .unwrap()
+            .or_fail(EXTRACTOR, "path")?
             .1
             .trim_start_matches("$/embed/");
 
@@ -23,10 +49,20 @@ pub fn odysee(url: &str) -> Result<Vid, Box<dyn Error>> {
     let resp = get_isahc(&vid.referrer, vid.user_agent, &vid.referrer)?;
 
     static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#""contentUrl": "([^"]*)"#).unwrap());
-    vid.vid_link = RE.captures(&resp).expect("Failed to get link")[1].into();
+    vid.vid_link = RE.captures(&resp).or_fail(EXTRACTOR, "link")?[1].into();
 
     static RE_TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<title>(.*?)</title>").unwrap());
-    vid.title = unescape_html_chars(&RE_TITLE.captures(&resp).expect("Failed to get link")[1]);
+    vid.title = unescape_html_chars(&RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1]);
+
+    static RE_IMAGE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"<meta property="og:image" content="([^"]*)""#).unwrap());
+    if let Some(image) = RE_IMAGE.captures(&resp) {
+        vid.thumbnails.push(Thumbnail {
+            url: unescape_html_chars(&image[1]),
+            width: None,
+            height: None,
+        });
+    }
 
     Ok(vid)
 }
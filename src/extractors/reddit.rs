@@ -1,14 +1,63 @@
+use super::Extractor;
 use crate::{
-    helpers::reqwests::{client, get_isahc_client},
-    Vid,
+    dash,
+    helpers::{
+        or_fail::OrFail,
+        reqwests::{client, get_isahc_client},
+        unescape_html_chars::unescape_html_chars,
+    },
+    Error, FormatKind, Options, Thumbnail, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::error::Error;
 
-pub fn reddit(url: &str) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 16] = [
+    // Reddit
+    "old.reddit.com/",
+    "redd.it/",
+    "reddit.", // bcz some libreddit & teddit instances start with reddit.
+    // Libreddit
+    "libreddit.",
+    "lr.",
+    "safereddit.com/",
+    "r.walkx.fyi/",
+    "l.opnxng.com/",
+    "snoo.habedieeh.re/",
+    // Teddit
+    "teddit.",
+    "snoo.ioens.is/",
+    "incogsnoo.com/",
+    "rdt.trom.tf/",
+    "i.opnxng.com/",
+    "td.vern.cc/",
+    "t.sneed.network/",
+];
+
+const EXTRACTOR: &str = "reddit";
+
+pub struct Reddit;
+
+impl Extractor for Reddit {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        reddit(url)
+    }
+}
+
+pub fn reddit(url: &str) -> Result<Vid, Error> {
     let mut vid = Vid {
-        referrer: format!("https://www.reddit.com/{}", url.split_once('/').unwrap().1).into(),
+        referrer: format!(
+            "https://www.reddit.com/{}",
+            url.split_once('/').or_fail(EXTRACTOR, "path")?.1
+        )
+        .into(),
         ..Default::default()
     };
 
@@ -19,65 +68,51 @@ pub fn reddit(url: &str) -> Result<Vid, Box<dyn Error>> {
     };
 
     static RE_TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r#""title": "(.+?)", ""#).unwrap());
-    vid.title = RE_TITLE.captures(&resp).expect("Failed to get title")[1]
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1]
         .replace(r#"\""#, "")
         .into();
 
+    // the full size preview, its variants (gif etc) come later
+    static RE_PREVIEW: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#""source": \{"url": "([^"]+)", "width": ([0-9]+), "height": ([0-9]+)\}"#)
+            .unwrap()
+    });
+    vid.thumbnails = RE_PREVIEW
+        .captures(&resp)
+        .map(|preview| Thumbnail {
+            url: unescape_html_chars(&preview[1]),
+            width: preview[2].parse().ok(),
+            height: preview[3].parse().ok(),
+        })
+        .into_iter()
+        .collect();
+
     static DASH_LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r#""dash_url": "([^"]+)"#).unwrap());
-    let dash_link: Box<str> = DASH_LINK.captures(&resp).expect("Failed to get link")[1].into();
-
-    static VID_URL: Lazy<Regex> = Lazy::new(|| Regex::new(r#""fallback_url": "([^"]*)"#).unwrap());
-
-    vid.vid_link = if let Some(link) = VID_URL.captures(&resp) {
-        link[1].into()
-    } else {
-        static DASH_VID: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"<BaseURL>(DASH_[0-9]+(\.mp4)?)</BaseURL>").unwrap());
-
-        let best_video = &DASH_VID
-            .captures_iter(&resp)
-            .max_by_key(|resolution| {
-                resolution[1]
-                    .trim_start_matches("DASH_")
-                    .trim_end_matches(".mp4")
-                    .parse::<u16>()
-                    .expect("Dash video quality not a number")
-            })
-            .expect("Failed to get dash video")[1];
-
-        dash_link.replacen("DASHPlaylist.mpd", best_video, 1).into()
-    };
+    let dash_link: Box<str> = DASH_LINK.captures(&resp).or_fail(EXTRACTOR, "dash link")?[1].into();
 
     drop(resp);
-    let resp = get_isahc_client(client, &dash_link)?;
+    let manifest = get_isahc_client(client, &dash_link)?;
+    let formats = dash::parse(&manifest, &dash_link)?.formats(&dash_link);
 
-    vid.audio_link = if resp.contains("<BaseURL>DASH_audio.mp4</BaseURL>") {
-        Some(
-            dash_link
-                .replacen("DASHPlaylist.mpd", "DASH_audio.mp4", 1)
-                .into(),
-        )
-    } else if resp.contains("<BaseURL>audio</BaseURL>") {
-        Some(dash_link.replacen("DASHPlaylist.mpd", "audio", 1).into())
-    } else {
-        static RE_DASH_AUDIO: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"<BaseURL>(DASH_AUDIO_[0-9]*(\.mp4)?)</BaseURL>").unwrap());
-
-        RE_DASH_AUDIO
-            .captures_iter(&resp)
-            .max_by_key(|resolution| {
-                resolution[1]
-                    .trim_start_matches("DASH_AUDIO_")
-                    .trim_end_matches(".mp4")
-                    .parse::<u16>()
-                    .expect("Dash audio bitrate not a number")
-            })
-            .map(|audio_link| {
-                dash_link
-                    .replacen("DASHPlaylist.mpd", &audio_link[1], 1)
-                    .into()
-            })
+    let best = |kind| {
+        formats
+            .iter()
+            .filter(|format| format.kind == kind)
+            .max_by_key(|format| (format.height, format.bitrate))
     };
 
+    let best_video = best(FormatKind::Video).or_fail(EXTRACTOR, "dash video")?;
+    vid.resolution = best_video.height;
+    vid.vid_codec = best_video.vid_codec.clone();
+    // not the fallback_url, which can be a lower resolution than the one reported
+    vid.vid_link = best_video.url.clone();
+
+    if let Some(audio) = best(FormatKind::Audio) {
+        vid.audio_link = Some(audio.url.clone());
+        vid.audio_codec = audio.audio_codec.clone();
+    }
+
+    vid.formats = formats;
+
     Ok(vid)
 }
@@ -1,13 +1,34 @@
+use super::Extractor;
 use crate::{
-    helpers::reqwests::{client, get_isahc_client},
-    Vid,
+    helpers::{
+        or_fail::OrFail,
+        reqwests::{client, get_isahc_client},
+    },
+    Error, Options, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::{from_str, Value};
-use std::error::Error;
 
-pub fn rokfin(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "rokfin";
+
+pub struct Rokfin;
+
+impl Extractor for Rokfin {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["rokfin.com/post/"]
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        rokfin(url, options.resolution)
+    }
+}
+
+pub fn rokfin(url: &str, resolution: u16) -> Result<Vid, Error> {
     let mut vid = Vid {
         referrer: format!("https://{}", url).into(),
         ..Default::default()
@@ -18,8 +39,7 @@ pub fn rokfin(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
         let id = url
             .trim_start_matches("rokfin.com/post/")
             .split_once('/')
-            .unwrap()
-            .0;
+            .map_or(url, |(id, _)| id);
 
         let api = format!("https://prod-api-v2.production.rokfin.com/api/v2/public/post/{id}")
             .into_boxed_str();
@@ -27,16 +47,16 @@ pub fn rokfin(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
         get_isahc_client(client, &api)?
     };
 
-    let data: Value = from_str(&resp).expect("Failed to serialize json");
+    let data: Value = from_str(&resp)?;
 
     vid.title = data["content"]["contentTitle"]
         .as_str()
-        .expect("Failed to get title")
+        .or_fail(EXTRACTOR, "title")?
         .into();
 
     let m3u8: Box<str> = data["content"]["contentUrl"]
         .as_str()
-        .expect("Failed to get ")
+        .or_fail(EXTRACTOR, "m3u8")?
         .into();
 
     drop(resp);
@@ -45,18 +65,21 @@ pub fn rokfin(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
     let resp = get_isahc_client(client, &m3u8)?;
 
     if resolution != 0 {
-        let re = Regex::new(&format!(
-            r"#EXT-X-STREAM-INF:.*?,RESOLUTION=[0-9]*x{resolution}[\s\S]*?(https://.*?\.rokfin\.com/.*/rendition\.m3u8.*)"
-        ))?;
-        if let Some(vid_link) = re.captures(&resp) {
-            vid.vid_link = vid_link[1].into();
+        static RE_RES: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"#EXT-X-STREAM-INF:.*?,RESOLUTION=[0-9]*x([0-9]+)[\s\S]*?(https://.*?\.rokfin\.com/.*/rendition\.m3u8.*)").unwrap()
+        });
+
+        let res = resolution.to_string();
+
+        if let Some(vid_link) = RE_RES.captures_iter(&resp).find(|cap| cap[1] == res) {
+            vid.vid_link = vid_link[2].into();
         }
     }
 
     if vid.vid_link.is_empty() {
         static RE: Lazy<Regex> =
             Lazy::new(|| Regex::new(r"(https://.*?\.rokfin\.com/.*/rendition\.m3u8.*)").unwrap());
-        vid.vid_link = RE.captures(&resp).expect("Failed to get link")[1].into();
+        vid.vid_link = RE.captures(&resp).or_fail(EXTRACTOR, "link")?[1].into();
     }
 
     Ok(vid)
@@ -1,13 +1,32 @@
+use super::Extractor;
 use crate::{
-    helpers::{reqwests::*, unescape_html_chars::unescape_html_chars},
-    Vid, RED, RESET, YELLOW,
+    helpers::{or_fail::OrFail, reqwests::*, unescape_html_chars::unescape_html_chars},
+    hls::parse_master,
+    Error, Format, Options, Thumbnail, Vid, RED, RESET, YELLOW,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::{Map, Value};
-use std::error::Error;
 
-pub fn rumble(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "rumble";
+
+pub struct Rumble;
+
+impl Extractor for Rumble {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["rumble.com/"]
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        rumble(url, options.resolution)
+    }
+}
+
+pub fn rumble(url: &str, resolution: u16) -> Result<Vid, Error> {
     let mut vid = Vid {
         user_agent: "Mozilla/5.0 FurryFox",
         referrer: format!("https://{}", url).into(),
@@ -23,7 +42,7 @@ pub fn rumble(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
         });
         let id_link = format!(
             "https://rumble.com/embedJS/u3/?request=video&ver=2&v={}",
-            &RE_ID.captures(&resp).expect("Failed to get id")[1]
+            &RE_ID.captures(&resp).or_fail(EXTRACTOR, "id")?[1]
         )
         .into_boxed_str();
         drop(resp);
@@ -31,17 +50,49 @@ pub fn rumble(url: &str, resolution: u16) -> Result<Vid, Box<dyn Error>> {
         get_isahc_json(client, &id_link)?
     };
 
-    vid.title = unescape_html_chars(data["title"].as_str().expect("Failed to get title"));
+    vid.title = unescape_html_chars(data["title"].as_str().or_fail(EXTRACTOR, "title")?);
+
+    if let Some(url) = data["i"].as_str() {
+        vid.thumbnails.push(Thumbnail {
+            url: url.into(),
+            width: None,
+            height: None,
+        });
+    }
+
+    for container in ["mp4", "webm"] {
+        for (quality, stream) in data["ua"][container].as_object().into_iter().flatten() {
+            let meta = &stream["meta"];
+
+            if let Some(url) = stream["url"].as_str() {
+                vid.formats.push(Format {
+                    id: format!("{container}-{quality}").into(),
+                    url: url.into(),
+                    container: container.into(),
+                    width: meta["w"].as_u64().map(|width| width as u16),
+                    height: meta["h"]
+                        .as_u64()
+                        .map(|height| height as u16)
+                        .or(quality.parse().ok()),
+                    bitrate: meta["bitrate"].as_u64().map(|bitrate| bitrate * 1000),
+                    filesize: meta["size"].as_u64(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
 
     if let Some(qualities) = data["ua"]["mp4"].as_object() {
-        (vid.vid_link, vid.resolution) = get_vid_url(&data, qualities, resolution, "mp4");
+        (vid.vid_link, vid.resolution) = get_vid_url(&data, qualities, resolution, "mp4")?;
     } else if let Some(qualities) = data["ua"]["webm"].as_object() {
-        (vid.vid_link, vid.resolution) = get_vid_url(&data, qualities, resolution, "webm");
+        (vid.vid_link, vid.resolution) = get_vid_url(&data, qualities, resolution, "webm")?;
     } else if let Some(url) = data["u"]["hls"]["url"].as_str() {
         let url: Box<str> = url.into();
         drop(data);
 
         let resp = get_isahc_client(client, &url)?;
+        vid.formats = parse_master(&resp, &url).formats();
+
         let mut last_line = String::new();
 
         for line in resp.lines() {
@@ -65,7 +116,7 @@ fn get_vid_url(
     qualities: &Map<String, Value>,
     resolution: u16,
     vid_format: &str,
-) -> (Box<str>, Option<u16>) {
+) -> Result<(Box<str>, Option<u16>), Error> {
     let mut vid_quality: u16 = 0;
 
     for (quality, _) in qualities {
@@ -84,8 +135,8 @@ fn get_vid_url(
 
     let vid_link = data["ua"][vid_format][vid_quality.to_string()]["url"]
         .as_str()
-        .expect("Couldn't get vid url")
+        .or_fail(EXTRACTOR, "video link")?
         .into();
 
-    (vid_link, Some(vid_quality))
+    Ok((vid_link, Some(vid_quality)))
 }
@@ -1,14 +1,40 @@
-use std::error::Error;
+use super::Extractor;
 
-use crate::{helpers::reqwests::get_isahc, Vid};
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::{from_str, Value};
 
-pub fn spotify(url: &str) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 2] = [
+    "open.spotify.com/episode/",
+    "open.spotify.com/embed/episode/",
+];
+
+const EXTRACTOR: &str = "spotify";
+
+pub struct Spotify;
+
+impl Extractor for Spotify {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        spotify(url)
+    }
+}
+
+pub fn spotify(url: &str) -> Result<Vid, Error> {
     static RE_ID: Lazy<Regex> =
         Lazy::new(|| Regex::new(r"open\.spotify\.com/(embed/)?episode/([^?&]*)").unwrap());
-    let id = &RE_ID.captures(url).expect("Invalid url: id wasn't found")[2];
+    let id = &RE_ID.captures(url).or_fail(EXTRACTOR, "id")?[2];
 
     let mut vid = Vid {
         referrer: format!("https://{}", url).into(),
@@ -25,7 +51,7 @@ pub fn spotify(url: &str) -> Result<Vid, Box<dyn Error>> {
         )?
         .replace("\\u003d", "=");
 
-        from_str(&resp).expect("Failed to derive json")
+        from_str(&resp)?
     };
 
     if let Some(url) = json_resp["passthroughUrl"].as_str() {
@@ -33,9 +59,9 @@ pub fn spotify(url: &str) -> Result<Vid, Box<dyn Error>> {
     } else if let Some(urls) = json_resp["url"].as_array() {
         vid.audio_link = Some(
             urls.iter()
-                .map(|url| url.as_str().unwrap())
+                .filter_map(|url| url.as_str())
                 .find(|url| url.contains(".scdn.co/"))
-                .expect("Failed to get audio link")
+                .or_fail(EXTRACTOR, "audio link")?
                 .into(),
         );
     }
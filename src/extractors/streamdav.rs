@@ -1,13 +1,31 @@
-use std::error::Error;
+use super::Extractor;
 
 use crate::{
-    helpers::{reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
-    Vid,
+    helpers::{or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
+    Error, Options, Thumbnail, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-pub fn streamdav(url: &str) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "streamdav";
+
+pub struct Streamdav;
+
+impl Extractor for Streamdav {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["streamdav.com/"]
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        streamdav(url)
+    }
+}
+
+pub fn streamdav(url: &str) -> Result<Vid, Error> {
     let mut vid = Vid {
         referrer: url
             .replacen("streamdav.com/v/", "streamdav.com/e/", 1)
@@ -19,13 +37,23 @@ pub fn streamdav(url: &str) -> Result<Vid, Box<dyn Error>> {
 
     static RE_TITLE: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"<meta name="og:title" content="(.*)">"#).unwrap());
-    vid.title = RE_TITLE.captures(&resp).expect("Failed to get title")[1].into();
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1].into();
+
+    static RE_IMAGE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"<meta name="og:image" content="([^"]*)">"#).unwrap());
+    if let Some(image) = RE_IMAGE.captures(&resp) {
+        vid.thumbnails.push(Thumbnail {
+            url: unescape_html_chars(&image[1]),
+            width: None,
+            height: None,
+        });
+    }
 
     static RE_VID: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"<source src="(.*?)" res="([0-9]*)""#).unwrap());
-    vid.vid_link =
-        unescape_html_chars(&RE_VID.captures(&resp).expect("Failed to get video link")[1]);
-    vid.resolution = Some(RE_VID.captures(&resp).expect("Failed to get resolution")[2].parse()?);
+    let captures = RE_VID.captures(&resp).or_fail(EXTRACTOR, "video link")?;
+    vid.vid_link = unescape_html_chars(&captures[1]);
+    vid.resolution = Some(captures[2].parse().or_fail(EXTRACTOR, "resolution")?);
 
     Ok(vid)
 }
@@ -1,15 +1,40 @@
-use std::error::Error;
+use super::Extractor;
 
-use crate::{helpers::reqwests::get_isahc, Vid};
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-pub fn streamhub(url: &str, _streaming_link: bool) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "streamhub";
+
+pub struct Streamhub;
+
+impl Extractor for Streamhub {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["streamhub."]
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        streamhub(url, options.streaming_link)
+    }
+}
+
+pub fn streamhub(url: &str, _streaming_link: bool) -> Result<Vid, Error> {
     const BASE_URL: &str = "streamhub.to";
     const STREAM_URL: &str = "streamhub.top";
 
     let mut vid = {
-        let path = url.trim_end_matches('/').rsplit_once('/').unwrap().1;
+        let path = url
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .or_fail(EXTRACTOR, "id")?
+            .1;
 
         Vid {
             referrer: format!("https://{}/{}", BASE_URL, path).into(),
@@ -20,12 +45,12 @@ pub fn streamhub(url: &str, _streaming_link: bool) -> Result<Vid, Box<dyn Error>
     let resp = get_isahc(&vid.referrer, vid.user_agent, &vid.referrer)?;
 
     static RE_TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(" *<h4>(.*?)</h4>").unwrap());
-    vid.title = RE_TITLE.captures(&resp).unwrap()[1].into();
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1].into();
 
     static RE: Lazy<Regex> = Lazy::new(|| {
         Regex::new(r"\|vjsplayer\|data\|(.*?)\|(.*?)\|.*?\|chromecast\|(.*?)\|(.*?)\|(.*?)\|.*?\|sp\|(.*?)\|(.*?)\|m3u8\|master\|(.*?)\|(.*?)\|").unwrap()
     });
-    let captures = RE.captures(&resp).expect("Failed to get video link");
+    let captures = RE.captures(&resp).or_fail(EXTRACTOR, "video link")?;
 
     let t = match &captures[7].split_once('|') {
         Some(val) => format!("{}-{}", val.1, val.0),
@@ -1,14 +1,36 @@
+use super::Extractor;
 use crate::{
-    helpers::{reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
-    Vid,
+    helpers::{or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
+    Error, Options, Thumbnail, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::error::Error;
 
-pub fn streamtape(url: &str, streaming_link: bool) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "streamtape";
+
+pub struct Streamtape;
+
+impl Extractor for Streamtape {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["streamtape."]
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        streamtape(url, options.streaming_link)
+    }
+}
+
+pub fn streamtape(url: &str, streaming_link: bool) -> Result<Vid, Error> {
     let mut vid = Vid {
-        referrer: format!("https://streamtape.net/{}", url.split_once('/').unwrap().1).into(),
+        referrer: format!(
+            "https://streamtape.net/{}",
+            url.split_once('/').or_fail(EXTRACTOR, "path")?.1
+        )
+        .into(),
         ..Default::default()
     };
 
@@ -16,7 +38,17 @@ pub fn streamtape(url: &str, streaming_link: bool) -> Result<Vid, Box<dyn Error>
 
     static RE_TITLE: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"<meta name="og:title" content="([^"]*)"#).unwrap());
-    vid.title = unescape_html_chars(&RE_TITLE.captures(&resp).unwrap()[1]);
+    vid.title = unescape_html_chars(&RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1]);
+
+    static RE_IMAGE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"<meta name="og:image" content="([^"]*)"#).unwrap());
+    if let Some(image) = RE_IMAGE.captures(&resp) {
+        vid.thumbnails.push(Thumbnail {
+            url: unescape_html_chars(&image[1]),
+            width: None,
+            height: None,
+        });
+    }
 
     static RE: Lazy<Regex> = Lazy::new(|| {
         Regex::new(
@@ -25,7 +57,7 @@ pub fn streamtape(url: &str, streaming_link: bool) -> Result<Vid, Box<dyn Error>
         .unwrap()
     });
 
-    let captures = RE.captures(&resp).expect("Failed to get video link");
+    let captures = RE.captures(&resp).or_fail(EXTRACTOR, "video link")?;
     vid.vid_link = {
         if streaming_link {
             format!("https://{}{}&stream=1", &captures[2], &captures[3])
@@ -1,13 +1,31 @@
-use std::error::Error;
+use super::Extractor;
 
 use crate::{
-    helpers::{reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
-    Vid,
+    helpers::{or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
+    Error, Options, Vid,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-pub fn streamvid(url: &str, streaming_link: bool) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "streamvid";
+
+pub struct Streamvid;
+
+impl Extractor for Streamvid {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["streamvid."]
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        streamvid(url, options.streaming_link)
+    }
+}
+
+pub fn streamvid(url: &str, streaming_link: bool) -> Result<Vid, Error> {
     let mut vid = Vid {
         // referrer: url.replacen("streamvid.net/", "streamvid.media/", 1).into(),
         referrer: format!("https://{}", url).into(),
@@ -18,7 +36,7 @@ pub fn streamvid(url: &str, streaming_link: bool) -> Result<Vid, Box<dyn Error>>
 
     static RE_TITLE: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"<h6 class="card-title">(.*?)</h6>"#).unwrap());
-    vid.title = unescape_html_chars(&RE_TITLE.captures(&resp).unwrap()[1]);
+    vid.title = unescape_html_chars(&RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1]);
 
     static RE: Lazy<Regex> = Lazy::new(|| {
         Regex::new(
@@ -27,7 +45,7 @@ pub fn streamvid(url: &str, streaming_link: bool) -> Result<Vid, Box<dyn Error>>
         .unwrap()
     });
 
-    let captures = RE.captures(&resp).expect("Failed to get video link");
+    let captures = RE.captures(&resp).or_fail(EXTRACTOR, "video link")?;
     let mut subdomain = &captures[5];
     let mut tld = &captures[3];
 
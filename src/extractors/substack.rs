@@ -1,10 +1,28 @@
+use super::Extractor;
 use crate::{
-    helpers::{reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
-    Vid,
+    helpers::{or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars},
+    Error, Options, Vid,
 };
-use std::error::Error;
 
-pub fn substack(url: &str) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "substack";
+
+pub struct Substack;
+
+impl Extractor for Substack {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn patterns(&self) -> &'static [&'static str] {
+        &[".substack.com/p/"]
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        substack(url)
+    }
+}
+
+pub fn substack(url: &str) -> Result<Vid, Error> {
     let mut vid = Vid {
         referrer: format!("https://{}", url).into(),
         ..Default::default()
@@ -13,12 +31,19 @@ pub fn substack(url: &str) -> Result<Vid, Box<dyn Error>> {
     let resp = get_isahc(&vid.referrer, vid.user_agent, &vid.referrer)?;
 
     vid.title =
-        unescape_html_chars(&splitter(&resp, r#"\"title\":\""#, "title").replace(r#"\\\""#, "\""));
+        unescape_html_chars(&splitter(&resp, r#"\"title\":\""#, "title")?.replace(r#"\\\""#, "\""));
 
     if let Some(audio_split) = resp.split_once("<audio src=\"") {
-        vid.audio_link = Some(audio_split.1.split_once('"').unwrap().0.into());
+        vid.audio_link = Some(
+            audio_split
+                .1
+                .split_once('"')
+                .or_fail(EXTRACTOR, "audio link")?
+                .0
+                .into(),
+        );
     } else {
-        let video_upload_id = splitter(&resp, r#"\"video_upload_id\":\""#, "video or audio link");
+        let video_upload_id = splitter(&resp, r#"\"video_upload_id\":\""#, "video or audio link")?;
 
         vid.vid_link = format!(
             "https://corbettreport.substack.com/api/v1/video/upload/{}/src",
@@ -30,11 +55,10 @@ pub fn substack(url: &str) -> Result<Vid, Box<dyn Error>> {
     Ok(vid)
 }
 
-fn splitter<'a>(resp: &'a str, first: &'a str, msg: &'a str) -> &'a str {
-    resp.split_once(first)
-        .unwrap_or_else(|| panic!("Failed to get {}", msg))
-        .1
-        .split_once(r#"\","#)
-        .unwrap()
-        .0
+fn splitter<'a>(resp: &'a str, first: &str, field: &'static str) -> Result<&'a str, Error> {
+    Ok(resp
+        .split_once(first)
+        .and_then(|(_, rest)| rest.split_once(r#"\","#))
+        .or_fail(EXTRACTOR, field)?
+        .0)
 }
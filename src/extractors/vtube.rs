@@ -1,10 +1,33 @@
-use std::error::Error;
+use super::Extractor;
 
-use crate::{helpers::reqwests::get_isahc, Vid};
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-pub fn vtube(url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn Error>> {
+const PREFIXES: [&str; 2] = ["vtbe.to/", "vtube.network/"];
+
+const EXTRACTOR: &str = "vtube";
+
+pub struct Vtube;
+
+impl Extractor for Vtube {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        vtube(url, options.streaming_link)
+    }
+}
+
+pub fn vtube(url: &str, is_streaming_link: bool) -> Result<Vid, Error> {
     const BASE_URL: &str = "vtube.network/";
 
     let mut vid = Vid {
@@ -18,11 +41,11 @@ pub fn vtube(url: &str, is_streaming_link: bool) -> Result<Vid, Box<dyn Error>>
 
     static RE_TITLE: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"<h3 class="h4 mb-4 text-center">(.*)</h3>"#).unwrap());
-    vid.title = RE_TITLE.captures(&resp).unwrap()[1].into();
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1].into();
 
     static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r".*\|(.*?)\|(.*?)\|hls\|(.*?)\|").unwrap());
 
-    let captures = RE.captures(&resp).expect("Failed to get video link");
+    let captures = RE.captures(&resp).or_fail(EXTRACTOR, "video link")?;
 
     let mut seg = &captures[1];
     if seg == "urlset" {
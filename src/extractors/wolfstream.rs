@@ -1,10 +1,31 @@
-use std::error::Error;
+use super::Extractor;
 
-use crate::{helpers::reqwests::get_isahc, Vid};
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Options, Vid,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-pub fn wolfstream(url: &str) -> Result<Vid, Box<dyn Error>> {
+const EXTRACTOR: &str = "wolfstream";
+
+pub struct Wolfstream;
+
+impl Extractor for Wolfstream {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &["wolfstream.tv/"]
+    }
+
+    fn extract(&self, url: &str, _options: &Options) -> Result<Vid, Error> {
+        wolfstream(url)
+    }
+}
+
+pub fn wolfstream(url: &str) -> Result<Vid, Error> {
     let mut vid = Vid {
         referrer: format!("https://{url}").replacen("embed-", "", 1).into(),
         ..Default::default()
@@ -13,19 +34,16 @@ pub fn wolfstream(url: &str) -> Result<Vid, Box<dyn Error>> {
     let resp = get_isahc(&vid.referrer, vid.user_agent, &vid.referrer)?;
 
     static RE_TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(.*)\[/URL\]").unwrap());
-    vid.title = RE_TITLE.captures(&resp).expect("Failed to get title")[1].into();
+    vid.title = RE_TITLE.captures(&resp).or_fail(EXTRACTOR, "title")?[1].into();
 
     static RE_VID: Lazy<Regex> =
         Lazy::new(|| Regex::new(r#"    sources: \[\{file:"([^"]*)"#).unwrap());
-    let m3u8 = &RE_VID.captures(&resp).expect("Failed to get vid link")[1];
+    let m3u8 = &RE_VID.captures(&resp).or_fail(EXTRACTOR, "video link")?[1];
 
-    let stream_base = m3u8
-        .split_once(',')
-        .expect("Failed to ',' for spliting urlset")
-        .0;
+    let stream_base = m3u8.split_once(',').or_fail(EXTRACTOR, "stream base")?.0;
     let params = m3u8
         .split_once(".urlset/master.m3u8?")
-        .expect("Failed to get urlset/master.m3u8 in m3u8 master url")
+        .or_fail(EXTRACTOR, "stream params")?
         .1;
 
     if m3u8.contains("x,") {
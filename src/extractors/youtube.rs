@@ -1,99 +1,121 @@
+use super::Extractor;
 use crate::{
-    helpers::{tmp_path::tmp_path, unescape_html_chars::unescape_html_chars},
-    Vid, RED, RESET,
+    cache, cookies,
+    format::language_matches,
+    helpers::{
+        or_fail::OrFail, reqwests::get_isahc, unescape_html_chars::unescape_html_chars, utc::utc,
+    },
+    hls::parse_master,
+    sponsorblock, Chapter, Entry, Error, Format, FormatKind, MediaKind, Options, Subtitle,
+    Thumbnail, Vid, RESET, YELLOW,
 };
 use fastrand::Rng;
 use isahc::{
     config::{Configurable, VersionNegotiation},
+    http::HeaderValue,
     ReadResponseExt, Request, RequestExt,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::{json, to_string, Value};
-use std::{error::Error, fs::File, io::Write, process::exit};
+use sha1::{Digest, Sha1};
+use std::{
+    fmt::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use url::{form_urlencoded, Url};
+
+const EXTRACTOR: &str = "youtube";
+const ORIGIN: &str = "https://www.youtube.com";
+
+const PREFIXES: [&str; 17] = [
+    "youtu.be/",
+    // Hyperpipe
+    "hyperpipe.",
+    "music.",
+    "listen.",
+    "hp.",
+    // Piped
+    "piped.",
+    "watch.leptons.xyz/",
+    "pi.ggtyler.dev/",
+    // Invidious instances generally start with invidious, inv, etc
+    "invidious.",
+    "inv.",
+    "iv.",
+    "yt.",
+    "yewtu.be/",
+    "vid.puffyan.us/",
+    "vid.priv.au/",
+    "onion.tube/",
+    "anontube.lvkaszus.pl/",
+];
+
+pub struct Youtube;
+
+impl Extractor for Youtube {
+    fn name(&self) -> &'static str {
+        EXTRACTOR
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        &PREFIXES
+    }
+
+    fn patterns(&self) -> &'static [&'static str] {
+        &["youtube.com/"]
+    }
 
-pub struct Chapter {
-    start: u32,
-    title: Box<str>,
+    fn extract(&self, url: &str, options: &Options) -> Result<Vid, Error> {
+        if let Some(target) = browse_target(url) {
+            // no need to load pages past the last requested item
+            let last = options
+                .playlist_items
+                .iter()
+                .map(|items| *items.end())
+                .max()
+                .unwrap_or(usize::MAX);
+
+            return playlist(target, last);
+        }
+
+        youtube(url, options)
+    }
 }
 
-pub fn youtube(
-    url: &str,
-    resolution: u16,
-    mut vid_codec: &str,
-    mut audio_codec: &str,
-    is_dash: bool,
-) -> Result<Vid, Box<dyn Error>> {
+pub fn youtube(url: &str, options: &Options) -> Result<Vid, Error> {
+    let resolution = options.resolution;
+    let mut vid_codec = &*options.vid_codec;
+    let mut audio_codec = &*options.audio_codec;
+
     let id = url
         .rsplit_once("v=")
-        .unwrap_or(url.rsplit_once('/').expect("Invalid Youtube url"))
+        .or_else(|| url.rsplit_once('/'))
+        .or_fail(EXTRACTOR, "video id")?
         .1
         .split(['?', '&'])
         .next()
-        .unwrap();
+        .unwrap_or_default();
+
+    let (client, data) = player_response(id, &options.yt_clients)?;
 
     let mut vid = Vid {
-        user_agent: "com.google.android.apps.youtube.vr.oculus/1.57.29 (Linux; U; Android 12L; eureka-user Build/SQ3A.220605.009.A1) gzip",
+        user_agent: client.user_agent,
         referrer: format!("https://www.youtube.com/watch?v={}", id).into(),
         ..Default::default()
     };
+    // println!("{:?}", data);
 
-    let data: Value = {
-        const CLIENT_VERSION: &str = "1.57.29";
-
-        let json = {
-            let rnd = Rng::new();
-            let cpn: String = (0..16).map(|_| rnd.alphanumeric()).collect();
-
-            let json_value = json!({
-            "context": {
-                "client": {
-                    "clientName": "ANDROID_VR",
-                    "clientVersion": CLIENT_VERSION,
-                    "clientScreen": "WATCH",
-                    "gl": "IN",
-                    "hl": "en",
-                    "utcOffsetMinutes": 0,
-                    "deviceMake": "Oculus",
-                    "deviceModel": "Quest 3",
-                    "osName": "Android",
-                    "osVersion": "12L",
-                    "androidSdkVersion": 32,
-                    "platform": "MOBILE"
-                },
-                "request": {
-                    "internalExperimentFlags": [],
-                    "useSsl": true
-                },
-                "user": {
-                    "lockedSafetyMode": false
-                },
-                "thirdParty": {
-                    "embedUrl": "https://www.youtube.com/"
-                }
-            },
-            "videoId": id,
-            "cpn": cpn,
-            "racyCheckOk": true,
-            "contentCheckOk": true,
-            "params": "2AMB"
-            });
-
-            to_string(&json_value)?.into_boxed_str()
-        };
+    vid.formats = formats(&data);
+    vid.subtitles = subtitles(&data);
 
-        Request::post("https://www.youtube.com/youtubei/v1/player?prettyPrint=false")
-            .header("user-agent", vid.user_agent)
-            .header("referer", &*vid.referrer)
-            .header("content-type", "application/json")
-            .header("x-youtube-client-name", 28)
-            .header("x-youtube-client-version", CLIENT_VERSION)
-            .version_negotiation(VersionNegotiation::http2())
-            .body(&*json)?
-            .send()?
-            .json()?
+    // only multi audio videos have languages to pick from
+    let audio_langs = if vid.formats.iter().any(|format| format.language.is_some()) {
+        &options.audio_langs[..]
+    } else {
+        &[]
     };
-    // println!("{:?}", data);
+    let audio_lang = audio_langs.first().map(|lang| &**lang);
 
     vid_codec = match vid_codec {
         "h264" | "libx264" => "avc",
@@ -105,7 +127,16 @@ pub fn youtube(
         audio_codec = "mp4a";
     }
 
-    if !is_dash && vid_codec == "avc" {
+    let details = &data["videoDetails"];
+    // isLiveContent alone is a finished stream, which is a normal video by now
+    vid.live = details["isLive"] == true || details["isPostLiveDvr"] == true;
+
+    // adaptive formats of live streams are segmented, the hls manifest plays & records fine
+    if vid.live && !options.live_from_start {
+        live_link(&data, resolution, &mut vid)?;
+    }
+
+    if vid.vid_link.is_empty() && !options.is_dash && vid_codec == "avc" {
         not_dash_link(&data, resolution, &mut vid)?;
     }
 
@@ -115,15 +146,23 @@ pub fn youtube(
 
     if vid.vid_link.is_empty() {
         if let Some(formats) = data["streamingData"]["adaptiveFormats"].as_array() {
-            exit_if_empty(formats);
+            if formats.is_empty() {
+                return Err(no_formats(&data));
+            }
+
             let mut bt_audio = 0;
             let mut bt_video = 0;
 
             let (mut v_codec, mut a_codec, mut v_link, mut a_link, mut res) = Default::default();
 
             for format in formats {
-                let (codec, quality, url, bitrate) =
-                    vid_data(format, Some(vid_codec), Some(audio_codec))?;
+                // one format without a usable url (say its signature didn't decipher)
+                // shouldn't cost the others
+                let Ok((codec, quality, url, bitrate)) =
+                    vid_data(format, Some(vid_codec), Some(audio_codec), audio_lang)
+                else {
+                    continue;
+                };
 
                 if quality > 0 && (bitrate > bt_video || quality == resolution) {
                     v_link = url;
@@ -139,7 +178,12 @@ pub fn youtube(
 
             if a_link.is_empty() {
                 for format in formats {
-                    let (codec, quality, url, bitrate) = vid_data(format, None, Some("mp4a"))?;
+                    let Ok((codec, quality, url, bitrate)) =
+                        vid_data(format, None, Some("mp4a"), audio_lang)
+                    else {
+                        continue;
+                    };
+
                     if quality == 0 && bitrate > bt_audio {
                         a_link = url;
                         a_codec = codec;
@@ -150,7 +194,11 @@ pub fn youtube(
 
             if v_link.is_empty() {
                 for format in formats {
-                    let (codec, quality, url, bitrate) = vid_data(format, Some("avc"), None)?;
+                    let Ok((codec, quality, url, bitrate)) =
+                        vid_data(format, Some("avc"), None, None)
+                    else {
+                        continue;
+                    };
 
                     if quality > 0 && (bitrate > bt_video || quality == resolution) {
                         v_link = url;
@@ -161,6 +209,16 @@ pub fn youtube(
                 }
             }
 
+            if v_link.is_empty() && a_link.is_empty() {
+                return Err(no_formats(&data));
+            }
+
+            if let (Some(lang), true) = (audio_lang, a_link.is_empty()) {
+                return Err(Error::UnknownFormat(
+                    format!("audio language {lang}").into(),
+                ));
+            }
+
             vid.vid_link = v_link.into();
             vid.audio_codec = Some(a_codec.into());
             vid.audio_link = Some(a_link.into());
@@ -169,16 +227,53 @@ pub fn youtube(
         }
     }
 
+    for lang in audio_langs.iter().skip(1) {
+        let audio = vid
+            .formats
+            .iter()
+            .filter(|format| format.kind == FormatKind::Audio && format.matches_language(lang))
+            .max_by_key(|format| {
+                let codec = format.audio_codec.as_deref().unwrap_or_default();
+                (codec.starts_with(audio_codec), format.bitrate)
+            })
+            .ok_or_else(|| Error::UnknownFormat(format!("audio language {lang}").into()))?;
+
+        vid.extra_audio.push(audio.clone());
+    }
+
     vid.title = unescape_html_chars(
         data["videoDetails"]["title"]
             .as_str()
-            .expect("Failed to get title"),
+            .or_fail(EXTRACTOR, "title")?,
     );
 
-    {
-        let mut chapters = Vec::new();
+    vid.thumbnails = details["thumbnail"]["thumbnails"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|thumbnail| {
+            Some(Thumbnail {
+                url: thumbnail["url"].as_str()?.into(),
+                width: thumbnail["width"].as_u64().map(|width| width as u16),
+                height: thumbnail["height"].as_u64().map(|height| height as u16),
+            })
+        })
+        .collect();
+
+    vid.uploader = details["author"].as_str().map(unescape_html_chars);
 
-        if let Some(description) = data["videoDetails"]["shortDescription"].as_str() {
+    vid.duration = details["lengthSeconds"]
+        .as_str()
+        .and_then(|seconds| seconds.parse::<u64>().ok())
+        .map(|seconds| seconds * 1000);
+
+    // the watch page has proper chapters, the description is only a fallback
+    if !vid.live {
+        vid.chapters = structured_chapters(id).unwrap_or_default();
+    }
+
+    if vid.chapters.is_empty() {
+        if let Some(description) = details["shortDescription"].as_str() {
             static CHAPTER_RE: Lazy<Regex> = Lazy::new(|| {
                 Regex::new(r"\n[^\p{L}\p{N}\p{P}]*[[:space:]]*[(|{|\[]?([0-6]?[0-9]:)?([0-6]?[0-9]:[0-6]?[0-9])[)|}|\]]?([[:space:]]*[:|–|-])?[[:space:]]+(.+)")
                     .unwrap()
@@ -191,19 +286,22 @@ pub fn youtube(
                     ""
                 };
 
-                chapters.push(Chapter {
-                    start: timestamp_to_ms(&format!("{}{}", hour, &chapter[2]))?,
+                vid.chapters.push(Chapter {
+                    start_ms: timestamp_to_ms(&format!("{}{}", hour, &chapter[2]))?.into(),
+                    end_ms: 0,
                     title: chapter[4].into(),
+                    category: None,
                 });
             }
         }
+    }
 
-        if !chapters.is_empty() {
-            let file_path = format!("{}{}.txt", tmp_path(false)?, vid.title.replace('/', "\\"))
-                .into_boxed_str();
-            create_chapter_file(&chapters, &file_path)?;
-
-            vid.chapter_file = Some(file_path)
+    if !options.sponsorblock.is_empty() && !vid.live {
+        match sponsorblock::segments(&options.sponsorblock_api, id, &options.sponsorblock) {
+            Ok(segments) => {
+                sponsorblock::mark(&mut vid.chapters, &segments, vid.duration, &vid.title)
+            }
+            Err(err) => eprintln!("{YELLOW}SponsorBlock:{RESET} {err}"),
         }
     }
 
@@ -214,19 +312,652 @@ pub fn youtube(
     Ok(vid)
 }
 
-fn not_dash_link(data: &Value, resolution: u16, vid: &mut Vid) -> Result<(), Box<dyn Error>> {
+/// Innertube client to ask for the player response as
+pub struct Client {
+    /// Name for `--yt-client=`
+    pub name: &'static str,
+    client_name: &'static str,
+    version: &'static str,
+    /// x-youtube-client-name
+    id: u16,
+    /// Links only work with the user agent of the client which got them
+    user_agent: &'static str,
+    /// Extra fields of `context.client`
+    device: &'static str,
+    params: Option<&'static str>,
+    /// Links come with ciphered signatures, which need the player js
+    ciphered: bool,
+    /// Sends the account cookies, the app clients don't take them
+    logged_in: bool,
+}
+
+/// Tried in order until one of them gets formats
+pub static CLIENTS: [Client; 4] = [
+    Client {
+        name: "android_vr",
+        client_name: "ANDROID_VR",
+        version: "1.57.29",
+        id: 28,
+        user_agent: "com.google.android.apps.youtube.vr.oculus/1.57.29 (Linux; U; Android 12L; eureka-user Build/SQ3A.220605.009.A1) gzip",
+        device: r#"{"clientScreen":"WATCH","deviceMake":"Oculus","deviceModel":"Quest 3","osName":"Android","osVersion":"12L","androidSdkVersion":32,"platform":"MOBILE"}"#,
+        params: Some("2AMB"),
+        ciphered: false,
+        logged_in: false,
+    },
+    Client {
+        name: "ios",
+        client_name: "IOS",
+        version: "19.45.4",
+        id: 5,
+        user_agent: "com.google.ios.youtube/19.45.4 (iPhone16,2; U; CPU iOS 18_1_0 like Mac OS X;)",
+        device: r#"{"deviceMake":"Apple","deviceModel":"iPhone16,2","osName":"iPhone","osVersion":"18.1.0.22B83","platform":"MOBILE"}"#,
+        params: None,
+        ciphered: false,
+        logged_in: false,
+    },
+    // plays age restricted videos as long as they can be embedded
+    Client {
+        name: "tv_embedded",
+        client_name: "TVHTML5_SIMPLY_EMBEDDED_PLAYER",
+        version: "2.0",
+        id: 85,
+        user_agent: "Mozilla/5.0 (ChromiumStylePlatform) Cobalt/Version",
+        device: r#"{"clientScreen":"EMBED","platform":"TV"}"#,
+        params: None,
+        ciphered: true,
+        logged_in: false,
+    },
+    Client {
+        name: "web",
+        client_name: "WEB",
+        version: "2.20241010.00.00",
+        id: 1,
+        user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:128.0) Gecko/20100101 Firefox/128.0",
+        device: r#"{"platform":"DESKTOP"}"#,
+        params: None,
+        ciphered: true,
+        logged_in: true,
+    },
+];
+
+/// Player response of the first client (of `names`, or all of them) that gets formats
+fn player_response(id: &str, names: &[Box<str>]) -> Result<(&'static Client, Value), Error> {
+    let picked = names
+        .iter()
+        .filter_map(|name| CLIENTS.iter().find(|client| client.name == &**name))
+        .collect::<Vec<_>>();
+
+    let chain = if !picked.is_empty() {
+        picked
+    } else if sapisid().is_some() {
+        // only the web client is logged in, so it goes first
+        CLIENTS
+            .iter()
+            .filter(|client| client.logged_in)
+            .chain(CLIENTS.iter().filter(|client| !client.logged_in))
+            .collect()
+    } else {
+        CLIENTS.iter().collect()
+    };
+
+    let mut signature = None;
+    // the first client's reason is the one worth reporting, network errors only if there's nothing else
+    let mut err: Option<Error> = None;
+
+    for client in chain {
+        let data = match player(id, client, &mut signature) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("{YELLOW}{}:{RESET} {e}", client.name);
+                err = err.or(Some(e));
+                continue;
+            }
+        };
+
+        let status = &data["playabilityStatus"];
+        let has_formats = ["formats", "adaptiveFormats"].iter().any(|key| {
+            data["streamingData"][key]
+                .as_array()
+                .is_some_and(|formats| !formats.is_empty())
+        });
+
+        match status["status"].as_str() {
+            Some("OK") if has_formats => return Ok((client, data)),
+            // gone or not live yet, no other client will do better
+            Some("ERROR" | "LIVE_STREAM_OFFLINE") => return Err(no_formats(&data)),
+            _ => {
+                eprintln!(
+                    "{YELLOW}{}:{RESET} {}",
+                    client.name,
+                    playability_reason(status)
+                );
+
+                if matches!(err, None | Some(Error::Network(_))) {
+                    err = Some(no_formats(&data));
+                }
+            }
+        }
+    }
+
+    Err(err.unwrap_or(Error::NoFormats))
+}
+
+fn player(id: &str, client: &Client, signature: &mut Option<Signature>) -> Result<Value, Error> {
+    let rnd = Rng::new();
+    let cpn: String = (0..16).map(|_| rnd.alphanumeric()).collect();
+
+    let mut context = json!({
+        "clientName": client.client_name,
+        "clientVersion": client.version,
+        "gl": "IN",
+        "hl": "en",
+        "utcOffsetMinutes": 0
+    });
+
+    if let (Some(context), Value::Object(device)) = (
+        context.as_object_mut(),
+        serde_json::from_str(client.device)?,
+    ) {
+        context.extend(device);
+    }
+
+    let mut body = json!({
+        "context": {
+            "client": context,
+            "request": {
+                "internalExperimentFlags": [],
+                "useSsl": true
+            },
+            "user": {
+                "lockedSafetyMode": false
+            },
+            "thirdParty": {
+                "embedUrl": "https://www.youtube.com/"
+            }
+        },
+        "videoId": id,
+        "cpn": cpn,
+        "racyCheckOk": true,
+        "contentCheckOk": true
+    });
+
+    if let Some(params) = client.params {
+        body["params"] = params.into();
+    }
+
+    if client.ciphered {
+        if signature.is_none() {
+            *signature = Some(Signature::fetch(client.user_agent)?);
+        }
+
+        if let Some(signature) = signature {
+            body["playbackContext"] = json!({
+                "contentPlaybackContext": {
+                    "signatureTimestamp": signature.timestamp
+                }
+            });
+        }
+    }
+
+    let request = Request::post("https://www.youtube.com/youtubei/v1/player?prettyPrint=false")
+        .header("user-agent", client.user_agent)
+        .header("referer", format!("https://www.youtube.com/watch?v={id}"))
+        .header("content-type", "application/json")
+        .header("x-youtube-client-name", client.id)
+        .header("x-youtube-client-version", client.version)
+        .version_negotiation(VersionNegotiation::http2())
+        .body(to_string(&body)?)?;
+
+    let mut data: Value = if client.logged_in {
+        logged_in(request)
+    } else {
+        request
+    }
+    .send()?
+    .json()?;
+
+    if let Some(signature) = signature.as_ref().filter(|_| client.ciphered) {
+        for key in ["formats", "adaptiveFormats"] {
+            for format in data["streamingData"][key]
+                .as_array_mut()
+                .into_iter()
+                .flatten()
+            {
+                if let Some(url) = format["signatureCipher"]
+                    .as_str()
+                    .and_then(|cipher| signature.decipher(cipher))
+                {
+                    format["url"] = url.into();
+                }
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Signature steps of the player js, the web & tv clients need them for their links
+///
+/// Only the plain reverse/splice/swap function is understood, the n param
+/// isn't touched so those links can be throttled
+struct Signature {
+    timestamp: u64,
+    steps: Vec<Step>,
+}
+
+enum Step {
+    Reverse,
+    Splice(usize),
+    Swap(usize),
+}
+
+impl Signature {
+    fn fetch(user_agent: &str) -> Result<Self, Error> {
+        static PLAYER_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"player\\?/([0-9a-fA-F]{8})\\?/").unwrap());
+        static TIMESTAMP_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"(?:signatureTimestamp|sts):(\d{5})").unwrap());
+        static FUNCTION_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r#"=function\(a\)\{a=a\.split\(""\);([^}]+?);return a\.join\(""\)\}"#)
+                .unwrap()
+        });
+        static CALL_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r#"([\w$]+)(?:\.|\[")([\w$]+)"?\]?\(a,(\d+)\)"#).unwrap());
+        static METHOD_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"([\w$]+):function\(a(?:,b)?\)\{([^}]*)\}").unwrap());
+
+        let referrer = "https://www.youtube.com/";
+        let api = get_isahc("https://www.youtube.com/iframe_api", user_agent, referrer)?;
+        let player = &PLAYER_RE.captures(&api).or_fail(EXTRACTOR, "player id")?[1];
+
+        // a player js never changes, only which one is current, so its steps are
+        // kept for as long as it's likely to stay around
+        let cached = cache::get_or_fetch(
+            EXTRACTOR,
+            &format!("signature_{player}"),
+            Duration::from_secs(7 * 24 * 60 * 60),
+            || {
+                let js = get_isahc(
+                    &format!(
+                        "https://www.youtube.com/s/player/{player}/player_ias.vflset/en_US/base.js"
+                    ),
+                    user_agent,
+                    referrer,
+                )?;
+
+                let timestamp = TIMESTAMP_RE
+                    .captures(&js)
+                    .and_then(|sts| sts[1].parse().ok())
+                    .or_fail(EXTRACTOR, "signature timestamp")?;
+
+                let function = FUNCTION_RE
+                    .captures(&js)
+                    .or_fail(EXTRACTOR, "signature function")?;
+                let calls = CALL_RE.captures_iter(&function[1]).collect::<Vec<_>>();
+                let object = calls.first().or_fail(EXTRACTOR, "signature steps")?;
+
+                // var Xy={ab:function(a){a.reverse()},cd:function(a,b){a.splice(0,b)},..};
+                let object = js
+                    .find(&format!("var {}={{", &object[1]))
+                    .and_then(|start| js[start..].find("};").map(|end| &js[start..start + end]))
+                    .or_fail(EXTRACTOR, "signature steps")?;
+
+                let methods = METHOD_RE
+                    .captures_iter(object)
+                    .map(|method| {
+                        let step: fn(usize) -> Step = if method[2].contains("reverse") {
+                            |_| Step::Reverse
+                        } else if method[2].contains("splice") {
+                            Step::Splice
+                        } else {
+                            Step::Swap
+                        };
+
+                        (method.get(1).map_or("", |name| name.as_str()), step)
+                    })
+                    .collect::<Vec<_>>();
+
+                let steps = calls
+                    .iter()
+                    .map(|call| {
+                        let (_, step) = methods.iter().find(|(name, _)| *name == &call[2])?;
+                        Some(step(call[3].parse().ok()?))
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .or_fail(EXTRACTOR, "signature steps")?;
+
+                Ok(Self { timestamp, steps }.encode())
+            },
+        )?;
+
+        Self::decode(&cached).or_fail(EXTRACTOR, "cached signature steps")
+    }
+
+    /// `20000 r s3 w7`, the timestamp & every step, for the cache
+    fn encode(&self) -> String {
+        let mut encoded = self.timestamp.to_string();
+
+        for step in &self.steps {
+            match step {
+                Step::Reverse => write!(encoded, " r"),
+                Step::Splice(n) => write!(encoded, " s{n}"),
+                Step::Swap(n) => write!(encoded, " w{n}"),
+            }
+            .ok();
+        }
+
+        encoded
+    }
+
+    fn decode(encoded: &str) -> Option<Self> {
+        let mut fields = encoded.split(' ');
+        let timestamp = fields.next()?.parse().ok()?;
+        let steps = fields
+            .map(|step| match step.split_at_checked(1)? {
+                ("r", "") => Some(Step::Reverse),
+                ("s", n) => Some(Step::Splice(n.parse().ok()?)),
+                ("w", n) => Some(Step::Swap(n.parse().ok()?)),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self { timestamp, steps })
+    }
+
+    /// `signatureCipher` (s, sp & url params) to a working link
+    fn decipher(&self, cipher: &str) -> Option<String> {
+        let params = form_urlencoded::parse(cipher.as_bytes()).collect::<Vec<_>>();
+        let param = |key: &str| params.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+
+        let mut sig = param("s")?.chars().collect::<Vec<_>>();
+
+        for step in &self.steps {
+            match *step {
+                Step::Reverse => sig.reverse(),
+                Step::Splice(n) => {
+                    sig.drain(..n.min(sig.len()));
+                }
+                Step::Swap(n) if !sig.is_empty() => {
+                    let n = n % sig.len();
+                    sig.swap(0, n);
+                }
+                Step::Swap(_) => (),
+            }
+        }
+
+        let mut url = Url::parse(param("url")?).ok()?;
+        url.query_pairs_mut().append_pair(
+            param("sp").map_or("signature", |sp| sp),
+            &sig.into_iter().collect::<String>(),
+        );
+
+        Some(url.into())
+    }
+}
+
+/// What a playlist or channel url points to
+#[derive(Debug, PartialEq)]
+enum BrowseTarget<'a> {
+    Playlist(&'a str),
+    /// `@handle`, `channel/UC..`, `c/name` or `user/name`
+    Channel {
+        path: &'a str,
+        shorts: bool,
+    },
+}
+
+fn browse_target(url: &str) -> Option<BrowseTarget<'_>> {
+    let rest = url.split_once('/')?.1;
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    // watch?v=..&list=.. stays a single video
+    if !query.split('&').any(|param| param.starts_with("v=")) {
+        if let Some(list) = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("list="))
+        {
+            return Some(BrowseTarget::Playlist(list));
+        }
+    }
+
+    let mut parts = path.trim_end_matches('/').split('/');
+    let first = parts.next()?;
+
+    let path = if first.starts_with('@') {
+        first
+    } else if matches!(first, "channel" | "c" | "user") {
+        let name = parts.next()?;
+        path.get(..first.len() + 1 + name.len())?
+    } else {
+        return None;
+    };
+
+    Some(BrowseTarget::Channel {
+        path,
+        shorts: parts.next() == Some("shorts"),
+    })
+}
+
+/// Entries of a playlist or of the uploads of a channel, up to `last`
+fn playlist(target: BrowseTarget, last: usize) -> Result<Vid, Error> {
+    let list_id: Box<str> = match target {
+        BrowseTarget::Playlist(id) => id.into(),
+        BrowseTarget::Channel { path, shorts } => {
+            let channel_id: Box<str> = match path.strip_prefix("channel/") {
+                Some(id) => id.into(),
+                None => innertube(
+                    "navigation/resolve_url",
+                    json!({ "url": format!("https://www.youtube.com/{path}") }),
+                )?["endpoint"]["browseEndpoint"]["browseId"]
+                    .as_str()
+                    .or_fail(EXTRACTOR, "channel id")?
+                    .into(),
+            };
+
+            // uploads & shorts of UCxx are the playlists UUxx & UUSHxx
+            let id = channel_id
+                .strip_prefix("UC")
+                .or_fail(EXTRACTOR, "channel id")?;
+
+            if shorts {
+                format!("UUSH{id}").into()
+            } else {
+                format!("UU{id}").into()
+            }
+        }
+    };
+
+    let mut data = innertube("browse", json!({ "browseId": format!("VL{list_id}") }))?;
+
+    let title = data["metadata"]["playlistMetadataRenderer"]["title"]
+        .as_str()
+        .or_else(|| data["header"]["playlistHeaderRenderer"]["title"]["simpleText"].as_str())
+        .map(unescape_html_chars)
+        .or_fail(EXTRACTOR, "playlist title")?;
+
+    let mut entries = Vec::new();
+
+    loop {
+        let mut continuation = None;
+        let found = entries.len();
+
+        collect_entries(&data, &mut entries, &mut continuation);
+
+        if entries.is_empty() {
+            return Err(no_formats(&data));
+        }
+
+        match continuation {
+            Some(token) if entries.len() < last && entries.len() > found => {
+                data = innertube("browse", json!({ "continuation": token }))?;
+            }
+            _ => break,
+        }
+    }
+
+    entries.truncate(last);
+
+    Ok(Vid {
+        referrer: format!("https://www.youtube.com/playlist?list={list_id}").into(),
+        title,
+        entries,
+        ..Default::default()
+    })
+}
+
+/// Videos & the continuation token anywhere in a browse response,
+/// walking the whole tree bcz the renderer layout keeps changing
+fn collect_entries(value: &Value, entries: &mut Vec<Entry>, continuation: &mut Option<Box<str>>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let entry = match key.as_str() {
+                    "playlistVideoRenderer" | "videoRenderer" | "gridVideoRenderer" => {
+                        value["videoId"].as_str().zip(text(&value["title"]))
+                    }
+                    "reelItemRenderer" => value["videoId"].as_str().zip(text(&value["headline"])),
+                    "continuationCommand" => {
+                        *continuation = value["token"].as_str().map(Box::from);
+                        continue;
+                    }
+                    _ => {
+                        collect_entries(value, entries, continuation);
+                        continue;
+                    }
+                };
+
+                if let Some((id, title)) = entry {
+                    entries.push(Entry {
+                        index: entries.len() + 1,
+                        id: id.into(),
+                        title: unescape_html_chars(&title),
+                        url: format!("https://www.youtube.com/watch?v={id}").into(),
+                        kind: MediaKind::Video,
+                    });
+                }
+            }
+        }
+        Value::Array(array) => {
+            for value in array {
+                collect_entries(value, entries, continuation);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// `simpleText` or the joined `runs` of a text object
+fn text(value: &Value) -> Option<String> {
+    value["simpleText"].as_str().map(String::from).or_else(|| {
+        value["runs"]
+            .as_array()
+            .map(|runs| runs.iter().filter_map(|run| run["text"].as_str()).collect())
+    })
+}
+
+/// POST to an innertube endpoint as the web client
+fn innertube(endpoint: &str, mut body: Value) -> Result<Value, Error> {
+    const CLIENT_VERSION: &str = "2.20241010.00.00";
+
+    body["context"] = json!({
+        "client": {
+            "clientName": "WEB",
+            "clientVersion": CLIENT_VERSION,
+            "gl": "US",
+            "hl": "en"
+        }
+    });
+
+    Ok(logged_in(
+        Request::post(format!(
+            "https://www.youtube.com/youtubei/v1/{endpoint}?prettyPrint=false"
+        ))
+        .header("content-type", "application/json")
+        .header("x-youtube-client-name", 1)
+        .header("x-youtube-client-version", CLIENT_VERSION)
+        .version_negotiation(VersionNegotiation::http2())
+        .body(to_string(&body)?)?,
+    )
+    .send()?
+    .json()?)
+}
+
+/// SAPISID cookie of the account, if cookies for youtube were loaded
+fn sapisid() -> Option<&'static str> {
+    ["SAPISID", "__Secure-3PAPISID"]
+        .into_iter()
+        .find_map(|name| cookies::get("https://www.youtube.com/", name))
+}
+
+/// Account cookies & the SAPISIDHASH innertube wants along with them
+fn logged_in<B>(request: Request<B>) -> Request<B> {
+    let mut request = cookies::attach(request);
+
+    if let Some(sapisid) = sapisid() {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let hash = Sha1::digest(format!("{time} {sapisid} {ORIGIN}"));
+        let hash = hash.iter().fold(String::new(), |mut hex, byte| {
+            write!(hex, "{byte:02x}").ok();
+            hex
+        });
+
+        for (name, value) in [
+            ("authorization", format!("SAPISIDHASH {time}_{hash}")),
+            ("x-origin", ORIGIN.into()),
+            ("x-goog-authuser", "0".into()),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                request.headers_mut().insert(name, value);
+            }
+        }
+    }
+
+    request
+}
+
+/// Variant of the hls manifest of a live stream, not above `resolution`
+fn live_link(data: &Value, resolution: u16, vid: &mut Vid) -> Result<(), Error> {
+    let manifest = data["streamingData"]["hlsManifestUrl"]
+        .as_str()
+        .or_fail(EXTRACTOR, "hls manifest")?;
+
+    let master = parse_master(
+        &get_isahc(manifest, vid.user_agent, &vid.referrer)?,
+        manifest,
+    );
+    let variant = master.pick_variant(resolution).ok_or(Error::NoFormats)?;
+    let codecs = variant.codecs.as_deref().unwrap_or_default();
+    let (v_codec, a_codec) = codecs.split_once(',').unwrap_or((codecs, ""));
+
+    vid.vid_link = variant.uri.clone();
+    vid.vid_codec = Some(v_codec.trim().into());
+    vid.audio_codec = Some(a_codec.trim().into());
+    vid.audio_link = None;
+    vid.resolution = variant.height;
+    vid.formats = master.formats();
+
+    Ok(())
+}
+
+fn not_dash_link(data: &Value, resolution: u16, vid: &mut Vid) -> Result<(), Error> {
     if let Some(formats) = data["streamingData"]["formats"].as_array() {
-        exit_if_empty(formats);
+        if formats.is_empty() {
+            return Err(no_formats(data));
+        }
 
         let (mut v_codec, mut a_codec, mut v_link, mut res) = Default::default();
 
         for format in formats {
-            let (codec, quality, url, _) = vid_data(format, None, None)?;
+            let Ok((codec, quality, url, _)) = vid_data(format, None, None, None) else {
+                continue;
+            };
 
             if quality > res {
                 let (vid_codec, audio_codec) = codec
                     .split_once(", ")
-                    .expect(r#"Failed to find ", " which separates video & audio codec"#);
+                    .or_fail(EXTRACTOR, "combined codecs")?;
 
                 v_link = url;
                 v_codec = vid_codec;
@@ -255,20 +986,27 @@ fn vid_data<'a>(
     format: &'a Value,
     vid_codec: Option<&'a str>,
     audio_codec: Option<&'a str>,
-) -> Result<(&'a str, u16, &'a str, u64), Box<dyn Error>> {
+    audio_lang: Option<&str>,
+) -> Result<(&'a str, u16, &'a str, u64), Error> {
     let codec = format["mimeType"]
         .as_str()
-        .expect("Failed to get mimeType")
-        .split_once(r#"codecs=""#)
-        .expect("Failed to get codec")
+        .and_then(|mime_type| mime_type.split_once(r#"codecs=""#))
+        .or_fail(EXTRACTOR, "codec")?
         .1
         .trim_end_matches('"');
 
+    let audio_track = &format["audioTrack"];
     let default_audio = codec.starts_with(audio_codec.unwrap_or("false lmao"))
-        && format["audioTrack"]
-            .as_object()
-            .and_then(|audio_track| audio_track["audioIsDefault"].as_bool())
-            .unwrap_or(true);
+        && match audio_lang {
+            Some(lang) => language_matches(
+                audio_track["id"]
+                    .as_str()
+                    .and_then(|id| id.split('.').next()),
+                audio_track["displayName"].as_str(),
+                lang,
+            ),
+            None => audio_track["audioIsDefault"].as_bool().unwrap_or(true),
+        };
 
     if default_audio
         || codec.starts_with(vid_codec.unwrap_or("false lmao"))
@@ -283,11 +1021,9 @@ fn vid_data<'a>(
             .parse()
             .unwrap_or_default();
 
-        let url = format["url"].as_str().expect("Failed to get url");
+        let url = format["url"].as_str().or_fail(EXTRACTOR, "url")?;
 
-        let bitrate = format["bitrate"]
-            .as_u64()
-            .expect("Failed to convert bitrate into a number");
+        let bitrate = format["bitrate"].as_u64().or_fail(EXTRACTOR, "bitrate")?;
 
         // println!("{codec}\t{bitrate}");
 
@@ -297,14 +1033,194 @@ fn vid_data<'a>(
     }
 }
 
-fn exit_if_empty<T>(formats: &[T]) {
-    if formats.is_empty() {
-        eprintln!("{}No result{}", RED, RESET);
-        exit(1);
+fn formats(data: &Value) -> Vec<Format> {
+    let streaming_data = &data["streamingData"];
+    let combined = streaming_data["formats"].as_array().into_iter().flatten();
+    let adaptive = streaming_data["adaptiveFormats"]
+        .as_array()
+        .into_iter()
+        .flatten();
+
+    combined
+        .map(|format| (format, true))
+        .chain(adaptive.map(|format| (format, false)))
+        .filter_map(|(format, is_combined)| {
+            let (mime_type, codecs) = format["mimeType"].as_str()?.split_once("; codecs=\"")?;
+            let (media, container) = mime_type.split_once('/')?;
+            let codecs = codecs.trim_end_matches('"');
+
+            let (kind, vid_codec, audio_codec) = if is_combined {
+                let (vid_codec, audio_codec) = codecs.split_once(", ").unwrap_or((codecs, ""));
+                (
+                    FormatKind::Combined,
+                    Some(vid_codec.into()),
+                    Some(audio_codec.into()),
+                )
+            } else if media == "audio" {
+                (FormatKind::Audio, None, Some(codecs.into()))
+            } else {
+                (FormatKind::Video, Some(codecs.into()), None)
+            };
+
+            Some(Format {
+                id: format["itag"].as_u64()?.to_string().into(),
+                url: format["url"].as_str()?.into(),
+                kind,
+                container: container.into(),
+                vid_codec,
+                audio_codec,
+                width: format["width"].as_u64().map(|width| width as u16),
+                height: format["height"].as_u64().map(|height| height as u16),
+                fps: format["fps"].as_u64().map(|fps| fps as u16),
+                bitrate: format["bitrate"].as_u64(),
+                filesize: format["contentLength"]
+                    .as_str()
+                    .and_then(|size| size.parse().ok()),
+                // id looks like en.4 or es-US.3
+                language: format["audioTrack"]["id"]
+                    .as_str()
+                    .and_then(|id| id.split('.').next())
+                    .map(Box::from),
+                language_name: format["audioTrack"]["displayName"].as_str().map(Box::from),
+            })
+        })
+        .collect()
+}
+
+fn subtitles(data: &Value) -> Vec<Subtitle> {
+    data["captions"]["playerCaptionsTracklistRenderer"]["captionTracks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|track| {
+            let url = track["baseUrl"].as_str()?;
+            let language = track["languageCode"].as_str()?;
+
+            Some(Subtitle {
+                language: language.into(),
+                name: text(&track["name"]).map_or_else(|| language.into(), Box::from),
+                auto: track["kind"] == "asr",
+                url: if url.starts_with('/') {
+                    format!("https://www.youtube.com{url}").into()
+                } else {
+                    url.into()
+                },
+            })
+        })
+        .collect()
+}
+
+/// Chapters (or else key moments) of the watch page
+fn structured_chapters(id: &str) -> Result<Vec<Chapter>, Error> {
+    let data = innertube("next", json!({ "videoId": id }))?;
+    let mut chapters = Vec::new();
+
+    for renderer in find_all(&data, "chapterRenderer") {
+        if let (Some(title), Some(start)) = (
+            text(&renderer["title"]),
+            renderer["timeRangeStartMillis"].as_u64(),
+        ) {
+            chapters.push(Chapter {
+                start_ms: start,
+                end_ms: 0,
+                title: title.into(),
+                category: None,
+            });
+        }
+    }
+
+    if chapters.is_empty() {
+        for renderer in find_all(&data, "macroMarkersListItemRenderer") {
+            if let (Some(title), Some(start)) = (
+                text(&renderer["title"]),
+                renderer["onTap"]["watchEndpoint"]["startTimeSeconds"].as_u64(),
+            ) {
+                chapters.push(Chapter {
+                    start_ms: start * 1000,
+                    end_ms: 0,
+                    title: title.into(),
+                    category: None,
+                });
+            }
+        }
+    }
+
+    // the same chapters show up in the player bar & the engagement panel
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+    chapters.dedup_by_key(|chapter| chapter.start_ms);
+
+    Ok(chapters)
+}
+
+/// Every value under `key` anywhere in the tree
+fn find_all<'a>(value: &'a Value, key: &str) -> Vec<&'a Value> {
+    match value {
+        Value::Object(object) => object
+            .iter()
+            .flat_map(|(k, value)| {
+                if k == key {
+                    vec![value]
+                } else {
+                    find_all(value, key)
+                }
+            })
+            .collect(),
+        Value::Array(array) => array
+            .iter()
+            .flat_map(|value| find_all(value, key))
+            .collect(),
+        _ => Vec::new(),
     }
 }
 
-fn timestamp_to_ms(timestamp: &str) -> Result<u32, Box<dyn Error>> {
+/// Typed error out of `playabilityStatus`, `NoFormats` when it says the video is fine
+fn no_formats(data: &Value) -> Error {
+    let status = &data["playabilityStatus"];
+    let reason = playability_reason(status);
+    let lowercase = reason.to_lowercase();
+    let mentions = |words: &[&str]| words.iter().any(|word| lowercase.contains(word));
+
+    match status["status"].as_str().unwrap_or("OK") {
+        "OK" => Error::NoFormats,
+        // premieres & scheduled streams
+        "LIVE_STREAM_OFFLINE" => match status["liveStreamability"]["liveStreamabilityRenderer"]
+            ["offlineSlate"]["liveStreamOfflineSlateRenderer"]["scheduledStartTime"]
+            .as_str()
+            .and_then(|time| time.parse().ok())
+        {
+            Some(time) => Error::Upcoming(format!("{reason}, starts at {}", utc(time)).into()),
+            None => Error::Upcoming(reason),
+        },
+        "LOGIN_REQUIRED" if mentions(&["private"]) => Error::Unavailable(reason),
+        "LOGIN_REQUIRED" | "AGE_CHECK_REQUIRED" | "CONTENT_CHECK_REQUIRED" => {
+            Error::NeedsLogin(reason)
+        }
+        _ if mentions(&["country", "region"]) => Error::GeoBlocked(reason),
+        _ if mentions(&["members", "sign in"]) => Error::NeedsLogin(reason),
+        _ => Error::Unavailable(reason),
+    }
+}
+
+/// `reason` of `playabilityStatus` or of its error screen, with the subreason if any
+fn playability_reason(status: &Value) -> Box<str> {
+    let screen = &status["errorScreen"]["playerErrorMessageRenderer"];
+
+    let reason = status["reason"]
+        .as_str()
+        .map(String::from)
+        .or_else(|| text(&screen["reason"]))
+        .or_else(|| status["messages"][0].as_str().map(String::from))
+        .unwrap_or_else(|| status["status"].as_str().unwrap_or("no formats").into());
+
+    match text(&screen["subreason"]) {
+        Some(subreason) if !subreason.is_empty() && subreason != reason => {
+            format!("{reason} ({subreason})").into()
+        }
+        _ => reason.into(),
+    }
+}
+
+fn timestamp_to_ms(timestamp: &str) -> Result<u32, Error> {
     let parts: Vec<&str> = timestamp.split(':').collect();
 
     let mut hours: u32 = 0;
@@ -313,13 +1229,13 @@ fn timestamp_to_ms(timestamp: &str) -> Result<u32, Box<dyn Error>> {
 
     match parts.len() {
         2 => {
-            minutes = parts[0].parse()?;
-            seconds = parts[1].parse()?;
+            minutes = parts[0].parse().or_fail(EXTRACTOR, "chapter timestamp")?;
+            seconds = parts[1].parse().or_fail(EXTRACTOR, "chapter timestamp")?;
         }
         3 => {
-            hours = parts[0].parse()?;
-            minutes = parts[1].parse()?;
-            seconds = parts[2].parse()?;
+            hours = parts[0].parse().or_fail(EXTRACTOR, "chapter timestamp")?;
+            minutes = parts[1].parse().or_fail(EXTRACTOR, "chapter timestamp")?;
+            seconds = parts[2].parse().or_fail(EXTRACTOR, "chapter timestamp")?;
         }
         _ => unreachable!(),
     }
@@ -328,24 +1244,243 @@ fn timestamp_to_ms(timestamp: &str) -> Result<u32, Box<dyn Error>> {
     Ok(milliseconds)
 }
 
-fn create_chapter_file(chapters: &[Chapter], file_path: &str) -> Result<(), Box<dyn Error>> {
-    let mut ffmpeg_chapter = String::from(";FFMETADATA1\n");
-
-    for chapter in chapters {
-        ffmpeg_chapter.push_str(&format!(
-            "[CHAPTER]
-TIMEBASE=1/1000
-START={}
-END=
-title={}\n",
-            chapter.start, chapter.title
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(status: Value) -> Error {
+        no_formats(&json!({ "playabilityStatus": status }))
+    }
+
+    #[test]
+    fn ok_without_formats_has_none() {
+        assert!(matches!(error(json!({"status": "OK"})), Error::NoFormats));
+        assert!(matches!(no_formats(&json!({})), Error::NoFormats));
+    }
+
+    #[test]
+    fn login_required() {
+        let err = error(json!({
+            "status": "LOGIN_REQUIRED",
+            "reason": "Sign in to confirm your age",
+        }));
+
+        assert!(
+            matches!(&err, Error::NeedsLogin(reason) if &**reason == "Sign in to confirm your age")
+        );
+        assert_eq!(err.exit_code(), 6);
+    }
+
+    #[test]
+    fn private_videos_are_unavailable() {
+        let err = error(json!({"status": "LOGIN_REQUIRED", "reason": "This video is private"}));
+
+        assert!(matches!(err, Error::Unavailable(_)));
+        assert_eq!(err.exit_code(), 10);
+    }
+
+    #[test]
+    fn age_and_content_checks_need_login() {
+        for status in ["AGE_CHECK_REQUIRED", "CONTENT_CHECK_REQUIRED"] {
+            let err = error(json!({
+                "status": status,
+                "errorScreen": {"playerErrorMessageRenderer": {
+                    "reason": {"simpleText": "Age-restricted video"},
+                    "subreason": {"runs": [{"text": "Sign in to confirm "}, {"text": "your age"}]},
+                }},
+            }));
+
+            assert!(
+                matches!(&err, Error::NeedsLogin(reason) if &**reason == "Age-restricted video (Sign in to confirm your age)"),
+                "{status}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn unplayable() {
+        let geo = error(json!({
+            "status": "UNPLAYABLE",
+            "reason": "The uploader has not made this video available in your country",
+        }));
+        assert!(matches!(geo, Error::GeoBlocked(_)));
+        assert_eq!(geo.exit_code(), 5);
+
+        let members = error(json!({
+            "status": "UNPLAYABLE",
+            "reason": "Join this channel to get access to members-only content",
+        }));
+        assert!(matches!(members, Error::NeedsLogin(_)));
+
+        let removed = error(json!({"status": "ERROR", "reason": "Video unavailable"}));
+        assert!(matches!(&removed, Error::Unavailable(reason) if &**reason == "Video unavailable"));
+
+        // nothing but the status to go by
+        assert!(matches!(
+            error(json!({"status": "UNPLAYABLE"})),
+            Error::Unavailable(reason) if &*reason == "UNPLAYABLE"
         ));
     }
 
-    match File::create(file_path) {
-        Ok(mut file) => file.write_all(ffmpeg_chapter.as_bytes())?,
-        Err(_) => eprintln!("{RED}Failed to create file{RESET}"),
+    #[test]
+    fn live_not_started() {
+        let scheduled = error(json!({
+            "status": "LIVE_STREAM_OFFLINE",
+            "reason": "Premieres in 2 hours",
+            "liveStreamability": {"liveStreamabilityRenderer": {"offlineSlate": {
+                "liveStreamOfflineSlateRenderer": {"scheduledStartTime": "1700000000"},
+            }}},
+        }));
+
+        assert!(matches!(
+            &scheduled,
+            Error::Upcoming(reason) if &**reason == "Premieres in 2 hours, starts at 2023-11-14 22:13 UTC"
+        ));
+        assert_eq!(scheduled.exit_code(), 11);
+
+        let unscheduled = error(json!({
+            "status": "LIVE_STREAM_OFFLINE",
+            "messages": ["This live event will begin in a few moments."],
+        }));
+        assert!(matches!(
+            unscheduled,
+            Error::Upcoming(reason) if &*reason == "This live event will begin in a few moments."
+        ));
     }
 
-    Ok(())
+    #[test]
+    fn browse_targets() {
+        let channel = |path, shorts| Some(BrowseTarget::Channel { path, shorts });
+
+        assert_eq!(
+            browse_target("youtube.com/playlist?list=PL0123abc"),
+            Some(BrowseTarget::Playlist("PL0123abc"))
+        );
+        assert_eq!(
+            browse_target("youtube.com/playlist?si=x&list=PL0123abc&index=2"),
+            Some(BrowseTarget::Playlist("PL0123abc"))
+        );
+        assert_eq!(
+            browse_target("youtube.com/@handle"),
+            channel("@handle", false)
+        );
+        assert_eq!(
+            browse_target("youtube.com/@handle/videos/"),
+            channel("@handle", false)
+        );
+        assert_eq!(
+            browse_target("youtube.com/@handle/shorts"),
+            channel("@handle", true)
+        );
+        assert_eq!(
+            browse_target("youtube.com/channel/UC0123abc"),
+            channel("channel/UC0123abc", false)
+        );
+        assert_eq!(
+            browse_target("youtube.com/c/name/shorts?view=0"),
+            channel("c/name", true)
+        );
+        assert_eq!(
+            browse_target("youtube.com/user/name"),
+            channel("user/name", false)
+        );
+    }
+
+    #[test]
+    fn videos_arent_browse_targets() {
+        // a video opened from a playlist is still that one video
+        assert_eq!(
+            browse_target("youtube.com/watch?v=dQw4w9WgXcQ&list=PL0123abc"),
+            None
+        );
+        assert_eq!(browse_target("youtube.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(browse_target("youtube.com/shorts/dQw4w9WgXcQ"), None);
+        assert_eq!(browse_target("youtube.com/channel"), None);
+        assert_eq!(browse_target("youtube.com"), None);
+    }
+
+    #[test]
+    fn collects_entries_and_continuation() {
+        let page = json!({
+            "contents": {"twoColumnBrowseResultsRenderer": {"tabs": [{"tabRenderer": {"content": {
+                "playlistVideoListRenderer": {"contents": [
+                    {"playlistVideoRenderer": {
+                        "videoId": "aaaaaaaaaaa",
+                        "title": {"runs": [{"text": "Tom &amp; "}, {"text": "Jerry"}]},
+                    }},
+                    {"playlistVideoRenderer": {"videoId": "no title"}},
+                    {"videoRenderer": {"videoId": "bbbbbbbbbbb", "title": {"simpleText": "Second"}}},
+                    {"reelItemRenderer": {"videoId": "ccccccccccc", "headline": {"simpleText": "Short"}}},
+                    {"continuationItemRenderer": {"continuationEndpoint": {
+                        "continuationCommand": {"token": "4qmFsgKP", "request": "CONTINUATION_REQUEST_TYPE_BROWSE"},
+                    }}},
+                ]},
+            }}}]}},
+        });
+        let mut entries = Vec::new();
+        let mut continuation = None;
+
+        collect_entries(&page, &mut entries, &mut continuation);
+
+        let ids = entries.iter().map(|entry| &*entry.id).collect::<Vec<_>>();
+        assert_eq!(ids, ["aaaaaaaaaaa", "bbbbbbbbbbb", "ccccccccccc"]);
+        assert_eq!(&*entries[0].title, "Tom & Jerry");
+        assert_eq!(
+            &*entries[2].url,
+            "https://www.youtube.com/watch?v=ccccccccccc"
+        );
+        assert_eq!(continuation.as_deref(), Some("4qmFsgKP"));
+
+        // the next page carries on numbering & is the last one
+        let next = json!({"onResponseReceivedActions": [{"appendContinuationItemsAction": {
+            "continuationItems": [
+                {"playlistVideoRenderer": {"videoId": "ddddddddddd", "title": {"simpleText": "Fourth"}}},
+            ],
+        }}]});
+        let mut continuation = None;
+
+        collect_entries(&next, &mut entries, &mut continuation);
+
+        assert_eq!(
+            entries.iter().map(|entry| entry.index).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(&*entries[3].title, "Fourth");
+        assert_eq!(continuation, None);
+    }
+
+    #[test]
+    fn signature_round_trips_through_the_cache() {
+        let signature = Signature {
+            timestamp: 20173,
+            steps: vec![
+                Step::Swap(47),
+                Step::Reverse,
+                Step::Splice(3),
+                Step::Swap(0),
+            ],
+        };
+        let encoded = signature.encode();
+
+        assert_eq!(encoded, "20173 w47 r s3 w0");
+
+        let decoded = Signature::decode(&encoded).unwrap();
+        let cipher = "s=abcdefghijklmnopqrstuvwxyz&sp=sig&url=https%3A%2F%2Fr1.googlevideo.com%2Fvideoplayback%3Fid%3D1";
+
+        assert_eq!(decoded.timestamp, 20173);
+        assert_eq!(decoded.decipher(cipher), signature.decipher(cipher));
+        assert_eq!(
+            decoded.decipher(cipher).as_deref(),
+            Some("https://r1.googlevideo.com/videoplayback?id=1&sig=wautsrqponmlkjihgfedcbv")
+        );
+    }
+
+    #[test]
+    fn rejects_a_corrupt_signature() {
+        assert!(Signature::decode("20173").is_some_and(|signature| signature.steps.is_empty()));
+        assert!(Signature::decode("").is_none());
+        assert!(Signature::decode("20173 r x1").is_none());
+        assert!(Signature::decode("20173 s").is_none());
+        assert!(Signature::decode("20173 rr").is_none());
+    }
 }
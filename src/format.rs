@@ -0,0 +1,296 @@
+use crate::{Error, Selector, Vid};
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatKind {
+    /// Video & audio in one stream
+    #[default]
+    Combined,
+    Video,
+    Audio,
+}
+
+/// One of the streams an extractor found
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Format {
+    pub id: Box<str>,
+    pub url: Box<str>,
+    pub kind: FormatKind,
+    /// mp4, webm, m3u8, etc
+    pub container: Box<str>,
+    pub vid_codec: Option<Box<str>>,
+    pub audio_codec: Option<Box<str>>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub fps: Option<u16>,
+    /// Bits per second
+    pub bitrate: Option<u64>,
+    /// Bytes
+    pub filesize: Option<u64>,
+    pub language: Option<Box<str>>,
+    /// Display name of the language, like `Spanish (dubbed)`
+    pub language_name: Option<Box<str>>,
+}
+
+/// Id of the link an extractor picked when it doesn't list formats
+pub const DEFAULT_FORMAT: &str = "default";
+
+impl Format {
+    pub fn has_video(&self) -> bool {
+        self.kind != FormatKind::Audio
+    }
+
+    pub fn has_audio(&self) -> bool {
+        self.kind != FormatKind::Video
+    }
+
+    /// `lang` is the language code (es also matches es-US) or part of its name
+    pub fn matches_language(&self, lang: &str) -> bool {
+        language_matches(
+            self.language.as_deref(),
+            self.language_name.as_deref(),
+            lang,
+        )
+    }
+}
+
+pub(crate) fn language_matches(language: Option<&str>, name: Option<&str>, lang: &str) -> bool {
+    language
+        .and_then(|language| language.strip_prefix(lang))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+        || name.is_some_and(|name| name.to_lowercase().contains(&lang.to_lowercase()))
+}
+
+impl Vid {
+    /// Pick formats with a selector expression, see [`Selector`]
+    ///
+    /// Without a list of formats the link the extractor picked is the only one,
+    /// [`DEFAULT_FORMAT`], so `best`, `worst` & filters still work on every site
+    pub fn select_format(&mut self, selector: &str) -> Result<(), Error> {
+        let parsed = Selector::parse(selector)?;
+        let unknown = || Error::UnknownFormat(selector.into());
+
+        if self.formats.is_empty() {
+            return parsed
+                .select(&[self.picked_format()])
+                .map(|_| ())
+                .ok_or_else(unknown);
+        }
+
+        let selection = parsed.select(&self.formats).ok_or_else(unknown)?;
+
+        match (selection.video, selection.audio) {
+            (Some(video), audio) => {
+                let has_audio = video.has_audio();
+                self.set_video(video);
+
+                match audio {
+                    Some(audio) => self.set_audio(audio),
+                    None if has_audio => self.audio_link = None,
+                    // the audio picked before went with another video
+                    None => match self.best_audio() {
+                        Some(audio) => self.set_audio(audio),
+                        None => {
+                            self.audio_link = None;
+                            self.audio_codec = None;
+                        }
+                    },
+                }
+            }
+            (None, Some(audio)) => {
+                self.vid_link = Box::from("");
+                self.vid_codec = None;
+                self.resolution = None;
+                self.set_audio(audio);
+            }
+            (None, None) => return Err(unknown()),
+        }
+
+        Ok(())
+    }
+
+    /// The link the extractor picked as a format, for the ones that list none
+    fn picked_format(&self) -> Format {
+        let (url, kind) = if self.vid_link.is_empty() {
+            (
+                self.audio_link.clone().unwrap_or_default(),
+                FormatKind::Audio,
+            )
+        } else {
+            (self.vid_link.clone(), FormatKind::Combined)
+        };
+
+        Format {
+            id: DEFAULT_FORMAT.into(),
+            url,
+            kind,
+            vid_codec: self.vid_codec.clone(),
+            audio_codec: self.audio_codec.clone(),
+            height: self.resolution,
+            ..Default::default()
+        }
+    }
+
+    /// Best audio only format, in the language of the current audio if it has one
+    fn best_audio(&self) -> Option<Format> {
+        let language = self
+            .formats
+            .iter()
+            .find(|format| Some(&format.url) == self.audio_link.as_ref())
+            .and_then(|format| format.language.as_ref());
+
+        self.formats
+            .iter()
+            .filter(|format| format.kind == FormatKind::Audio)
+            .max_by_key(|format| {
+                (
+                    language.is_none() || format.language.as_ref() == language,
+                    format.bitrate,
+                )
+            })
+            .cloned()
+    }
+
+    fn set_video(&mut self, format: Format) {
+        self.vid_link = format.url;
+        self.vid_codec = format.vid_codec;
+        self.resolution = format.height;
+
+        if format.kind == FormatKind::Combined {
+            self.audio_codec = format.audio_codec;
+        }
+    }
+
+    fn set_audio(&mut self, format: Format) {
+        self.audio_link = Some(format.url);
+        self.audio_codec = format.audio_codec;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(id: &str, kind: FormatKind, height: Option<u16>, bitrate: u64) -> Format {
+        Format {
+            id: id.into(),
+            url: format!("https://cdn.test/{id}").into(),
+            kind,
+            vid_codec: (kind != FormatKind::Audio).then(|| format!("{id}-v").into()),
+            audio_codec: (kind != FormatKind::Video).then(|| format!("{id}-a").into()),
+            height,
+            bitrate: Some(bitrate),
+            ..Default::default()
+        }
+    }
+
+    fn vid() -> Vid {
+        let mut dubbed = format("es", FormatKind::Audio, None, 300);
+        dubbed.language = Some("es".into());
+        let mut original = format("en", FormatKind::Audio, None, 100);
+        original.language = Some("en".into());
+
+        Vid {
+            formats: vec![
+                format("18", FormatKind::Combined, Some(360), 500),
+                format("137", FormatKind::Video, Some(1080), 4000),
+                format("136", FormatKind::Video, Some(720), 2000),
+                original,
+                dubbed,
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn video_only_pick_rederives_the_audio() {
+        let mut vid = vid();
+        vid.select_format("136+en").unwrap();
+        assert_eq!(vid.audio_link.as_deref(), Some("https://cdn.test/en"));
+
+        // keeps the language of the audio it replaces, not the louder dub
+        vid.select_format("137").unwrap();
+        assert_eq!(&*vid.vid_link, "https://cdn.test/137");
+        assert_eq!(vid.audio_link.as_deref(), Some("https://cdn.test/en"));
+        assert_eq!(vid.audio_codec.as_deref(), Some("en-a"));
+
+        // nothing to go along with it, no stale audio either
+        vid.audio_link = Some("https://cdn.test/gone".into());
+        vid.formats
+            .retain(|format| format.kind != FormatKind::Audio);
+        vid.select_format("136").unwrap();
+        assert_eq!(vid.audio_link, None);
+        assert_eq!(vid.audio_codec, None);
+    }
+
+    #[test]
+    fn combined_pick_drops_the_separate_audio() {
+        let mut vid = vid();
+        vid.select_format("bv+ba").unwrap();
+        assert_eq!(vid.audio_link.as_deref(), Some("https://cdn.test/es"));
+
+        vid.select_format("18").unwrap();
+        assert_eq!(vid.audio_link, None);
+        assert_eq!(vid.audio_codec.as_deref(), Some("18-a"));
+        assert_eq!(vid.resolution, Some(360));
+    }
+
+    #[test]
+    fn audio_pick_clears_the_video() {
+        let mut vid = vid();
+        vid.select_format("ba[language=en]").unwrap();
+
+        assert!(vid.vid_link.is_empty());
+        assert_eq!(vid.resolution, None);
+        assert_eq!(vid.audio_link.as_deref(), Some("https://cdn.test/en"));
+    }
+
+    #[test]
+    fn unlisted_formats_keep_the_picked_link() {
+        let picked = || Vid {
+            vid_link: "https://cdn.test/video".into(),
+            audio_link: Some("https://cdn.test/audio".into()),
+            resolution: Some(720),
+            ..Default::default()
+        };
+
+        for selector in [
+            "best",
+            "worst",
+            "b[height<=1080]",
+            "bv*+ba/b",
+            DEFAULT_FORMAT,
+        ] {
+            let mut vid = picked();
+            vid.select_format(selector).unwrap();
+
+            assert_eq!(&*vid.vid_link, "https://cdn.test/video", "{selector}");
+            assert_eq!(
+                vid.audio_link.as_deref(),
+                Some("https://cdn.test/audio"),
+                "{selector}"
+            );
+        }
+
+        for selector in ["b[height<=480]", "bestaudio", "137"] {
+            assert!(matches!(
+                picked().select_format(selector),
+                Err(Error::UnknownFormat(_))
+            ));
+        }
+
+        let space = || Vid {
+            audio_link: Some("https://cdn.test/space.m3u8".into()),
+            ..Default::default()
+        };
+        let mut vid = space();
+        vid.select_format("bestaudio").unwrap();
+
+        assert_eq!(
+            vid.audio_link.as_deref(),
+            Some("https://cdn.test/space.m3u8")
+        );
+        assert!(space().select_format("bestvideo").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod or_fail;
 pub mod reqwests;
-pub mod tmp_path;
 pub mod unescape_html_chars;
+pub mod utc;
@@ -0,0 +1,18 @@
+use crate::Error;
+
+/// Turn a missing or unparsable field into `Error::ParseFailure`
+pub trait OrFail<T> {
+    fn or_fail(self, extractor: &'static str, field: &'static str) -> Result<T, Error>;
+}
+
+impl<T> OrFail<T> for Option<T> {
+    fn or_fail(self, extractor: &'static str, field: &'static str) -> Result<T, Error> {
+        self.ok_or(Error::ParseFailure { extractor, field })
+    }
+}
+
+impl<T, E> OrFail<T> for Result<T, E> {
+    fn or_fail(self, extractor: &'static str, field: &'static str) -> Result<T, Error> {
+        self.ok().or_fail(extractor, field)
+    }
+}
@@ -1,8 +1,8 @@
+use crate::{cookies, Error};
 use isahc::{
     config::{RedirectPolicy::Follow, VersionNegotiation},
-    error::Error,
     prelude::Configurable,
-    HttpClient, ReadResponseExt,
+    HttpClient, ReadResponseExt, Request, Response,
 };
 use serde_json::Value;
 
@@ -35,14 +35,19 @@ pub fn get_isahc(link: &str, user_agent: &str, referrer: &str) -> Result<Box<str
 // }
 
 pub fn get_isahc_client(client: &HttpClient, link: &str) -> Result<Box<str>, Error> {
-    Ok(client.get(link)?.text()?.into())
+    Ok(get(client, link)?
+        .text()
+        .map_err(isahc::Error::from)?
+        .into())
 }
 
-pub fn get_isahc_json(
-    client: &HttpClient,
-    link: &str,
-) -> Result<Value, Box<dyn std::error::Error>> {
-    Ok(client.get(link)?.json()?)
+pub fn get_isahc_json(client: &HttpClient, link: &str) -> Result<Value, Error> {
+    Ok(get(client, link)?.json()?)
+}
+
+/// GET `link` with the cookies meant for it
+pub fn get(client: &HttpClient, link: &str) -> Result<Response<isahc::Body>, Error> {
+    Ok(client.send(cookies::attach(Request::get(link).body(())?))?)
 }
 
 pub fn client(user_agent: &str, referrer: &str) -> Result<HttpClient, Error> {
@@ -51,4 +56,5 @@ pub fn client(user_agent: &str, referrer: &str) -> Result<HttpClient, Error> {
         .redirect_policy(Follow)
         .default_headers(&[("user-agent", user_agent), ("referer", referrer)])
         .build()
+        .map_err(Error::from)
 }
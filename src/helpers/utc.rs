@@ -0,0 +1,47 @@
+/// Unix time to `YYYY-MM-DD HH:MM UTC`
+pub fn utc(timestamp: u64) -> String {
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let seconds = timestamp % 86400;
+
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch() {
+        assert_eq!(utc(0), "1970-01-01 00:00 UTC");
+        assert_eq!(utc(86_399), "1970-01-01 23:59 UTC");
+    }
+
+    #[test]
+    fn leap_days() {
+        assert_eq!(utc(1_709_251_140), "2024-02-29 23:59 UTC");
+        assert_eq!(utc(1_709_251_200), "2024-03-01 00:00 UTC");
+        // divisible by 400 is a leap year, by 100 only isn't
+        assert_eq!(utc(951_825_600), "2000-02-29 12:00 UTC");
+        assert_eq!(utc(4_107_542_400), "2100-03-01 00:00 UTC");
+    }
+
+    #[test]
+    fn end_of_year() {
+        assert_eq!(utc(1_704_067_140), "2023-12-31 23:59 UTC");
+        assert_eq!(utc(1_704_067_200), "2024-01-01 00:00 UTC");
+    }
+}
@@ -0,0 +1,557 @@
+use crate::{Format, FormatKind};
+use url::Url;
+
+/// `#EXT-X-STREAM-INF` entry of a master playlist
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Variant {
+    pub uri: Box<str>,
+    pub bandwidth: Option<u64>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub codecs: Option<Box<str>>,
+    pub frame_rate: Option<f32>,
+    /// GROUP-ID of the audio renditions
+    pub audio: Option<Box<str>>,
+}
+
+/// `#EXT-X-MEDIA` entry of a master playlist
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Rendition {
+    /// AUDIO, VIDEO, SUBTITLES or CLOSED-CAPTIONS
+    pub kind: Box<str>,
+    pub group_id: Box<str>,
+    pub name: Box<str>,
+    pub language: Option<Box<str>>,
+    pub uri: Option<Box<str>>,
+    pub default: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+}
+
+/// `#EXT-X-KEY`, only AES-128 is supported when downloading
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Key {
+    /// NONE, AES-128 or SAMPLE-AES
+    pub method: Box<str>,
+    pub uri: Option<Box<str>>,
+    pub iv: Option<[u8; 16]>,
+}
+
+/// Length & offset of an `#EXT-X-BYTERANGE`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub length: u64,
+    pub offset: u64,
+}
+
+/// `#EXT-X-MAP` init section of fragmented mp4 playlists
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Map {
+    pub uri: Box<str>,
+    pub byte_range: Option<ByteRange>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Segment {
+    pub uri: Box<str>,
+    /// Seconds
+    pub duration: f32,
+    pub sequence: u64,
+    pub byte_range: Option<ByteRange>,
+    pub key: Option<Key>,
+    pub map: Option<Map>,
+}
+
+impl Segment {
+    /// IV to decrypt it with, the media sequence number if the key has none
+    pub fn iv(&self) -> Option<[u8; 16]> {
+        let key = self.key.as_ref()?;
+
+        Some(key.iv.unwrap_or(u128::from(self.sequence).to_be_bytes()))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MediaPlaylist {
+    pub target_duration: Option<u32>,
+    pub media_sequence: u64,
+    pub segments: Vec<Segment>,
+    /// `#EXT-X-ENDLIST` is missing on live streams
+    pub end_list: bool,
+}
+
+/// Link to a `.m3u8` file, ignoring the query
+pub fn is_playlist_link(link: &str) -> bool {
+    match Url::parse(link) {
+        Ok(url) => url.path().ends_with(".m3u8"),
+        Err(_) => link
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .ends_with(".m3u8"),
+    }
+}
+
+pub fn is_master(playlist: &str) -> bool {
+    playlist.contains("#EXT-X-STREAM-INF")
+}
+
+pub fn parse_master(playlist: &str, base_url: &str) -> MasterPlaylist {
+    let mut master = MasterPlaylist::default();
+    let mut lines = playlist.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let mut variant = Variant::default();
+
+            for (key, value) in attributes(attrs) {
+                match key {
+                    "BANDWIDTH" => variant.bandwidth = value.parse().ok(),
+                    "RESOLUTION" => {
+                        if let Some((width, height)) = value.split_once('x') {
+                            variant.width = width.parse().ok();
+                            variant.height = height.parse().ok();
+                        }
+                    }
+                    "CODECS" => variant.codecs = Some(value.into()),
+                    "FRAME-RATE" => variant.frame_rate = value.parse().ok(),
+                    "AUDIO" => variant.audio = Some(value.into()),
+                    _ => (),
+                }
+            }
+
+            if let Some(uri) = lines.find(|line| !line.is_empty() && !line.starts_with('#')) {
+                variant.uri = resolve(base_url, uri);
+                master.variants.push(variant);
+            }
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let mut rendition = Rendition::default();
+
+            for (key, value) in attributes(attrs) {
+                match key {
+                    "TYPE" => rendition.kind = value.into(),
+                    "GROUP-ID" => rendition.group_id = value.into(),
+                    "NAME" => rendition.name = value.into(),
+                    "LANGUAGE" => rendition.language = Some(value.into()),
+                    "URI" => rendition.uri = Some(resolve(base_url, value)),
+                    "DEFAULT" => rendition.default = value == "YES",
+                    _ => (),
+                }
+            }
+
+            master.renditions.push(rendition);
+        }
+    }
+
+    master
+}
+
+pub fn parse_media(playlist: &str, base_url: &str) -> MediaPlaylist {
+    let mut media = MediaPlaylist::default();
+    let (mut duration, mut byte_range) = (0.0, None);
+    let (mut key, mut map): (Option<Key>, Option<Map>) = (None, None);
+    // end of the last byte range per uri, for ranges without an offset
+    let mut last_end: Option<(Box<str>, u64)> = None;
+
+    for line in playlist.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            media.target_duration = value.parse().ok();
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media.media_sequence = value.parse().unwrap_or_default();
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            duration = value
+                .split(',')
+                .next()
+                .unwrap_or_default()
+                .parse()
+                .unwrap_or_default();
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            byte_range = Some(value);
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-KEY:") {
+            let mut new_key = Key::default();
+
+            for (name, value) in attributes(attrs) {
+                match name {
+                    "METHOD" => new_key.method = value.into(),
+                    "URI" => new_key.uri = Some(resolve(base_url, value)),
+                    "IV" => new_key.iv = parse_iv(value),
+                    _ => (),
+                }
+            }
+
+            key = (&*new_key.method != "NONE").then_some(new_key);
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+            let mut new_map = Map::default();
+
+            for (name, value) in attributes(attrs) {
+                match name {
+                    "URI" => new_map.uri = resolve(base_url, value),
+                    "BYTERANGE" => new_map.byte_range = parse_byte_range(value, 0),
+                    _ => (),
+                }
+            }
+
+            map = Some(new_map);
+        } else if line == "#EXT-X-ENDLIST" {
+            media.end_list = true;
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let uri = resolve(base_url, line);
+
+            let byte_range = byte_range.take().and_then(|range| {
+                let previous_end = match &last_end {
+                    Some((last_uri, end)) if *last_uri == uri => *end,
+                    _ => 0,
+                };
+
+                parse_byte_range(range, previous_end)
+            });
+
+            if let Some(range) = byte_range {
+                last_end = Some((uri.clone(), range.offset + range.length));
+            }
+
+            media.segments.push(Segment {
+                uri,
+                duration,
+                sequence: media.media_sequence + media.segments.len() as u64,
+                byte_range,
+                key: key.clone(),
+                map: map.clone(),
+            });
+
+            duration = 0.0;
+        }
+    }
+
+    media
+}
+
+impl MasterPlaylist {
+    /// Highest variant not above `resolution`, 0 picks the best one
+    pub fn pick_variant(&self, resolution: u16) -> Option<&Variant> {
+        let quality = |variant: &&Variant| (variant.height, variant.bandwidth);
+
+        self.variants
+            .iter()
+            .filter(|variant| resolution == 0 || variant.height.is_none_or(|h| h <= resolution))
+            .max_by_key(quality)
+            .or_else(|| self.variants.iter().min_by_key(quality))
+    }
+
+    /// Separate audio of `variant`, the default rendition of its group or the first one
+    pub fn audio_rendition(&self, variant: &Variant) -> Option<&Rendition> {
+        let group = variant.audio.as_deref()?;
+        let mut audio = self.renditions.iter().filter(|rendition| {
+            &*rendition.kind == "AUDIO" && &*rendition.group_id == group && rendition.uri.is_some()
+        });
+        let first = audio.clone().next();
+
+        audio.find(|rendition| rendition.default).or(first)
+    }
+
+    pub fn formats(&self) -> Vec<Format> {
+        let mut formats = Vec::new();
+
+        for variant in &self.variants {
+            let (vid_codec, audio_codec) = split_codecs(variant.codecs.as_deref());
+
+            let separate_audio = variant.audio.as_deref().is_some_and(|group| {
+                self.renditions
+                    .iter()
+                    .any(|rendition| &*rendition.group_id == group && rendition.uri.is_some())
+            });
+
+            let kind = if separate_audio || (vid_codec.is_some() && audio_codec.is_none()) {
+                FormatKind::Video
+            } else {
+                FormatKind::Combined
+            };
+
+            formats.push(Format {
+                id: match (variant.height, variant.bandwidth) {
+                    (Some(height), _) => format!("hls-{height}p"),
+                    (None, Some(bandwidth)) => format!("hls-{}k", bandwidth / 1000),
+                    (None, None) => format!("hls-{}", formats.len()),
+                }
+                .into(),
+                url: variant.uri.clone(),
+                kind,
+                container: Box::from("m3u8"),
+                vid_codec,
+                audio_codec: if kind == FormatKind::Video {
+                    None
+                } else {
+                    audio_codec
+                },
+                width: variant.width,
+                height: variant.height,
+                fps: variant.frame_rate.map(|fps| fps.round() as u16),
+                bitrate: variant.bandwidth,
+                ..Default::default()
+            });
+        }
+
+        for rendition in &self.renditions {
+            if let (Some(uri), "AUDIO") = (&rendition.uri, &*rendition.kind) {
+                formats.push(Format {
+                    id: format!("hls-audio-{}", rendition.name.replace(' ', "_")).into(),
+                    url: uri.clone(),
+                    kind: FormatKind::Audio,
+                    container: Box::from("m3u8"),
+                    audio_codec: self
+                        .variants
+                        .iter()
+                        .find(|variant| variant.audio.as_deref() == Some(&rendition.group_id))
+                        .and_then(|variant| split_codecs(variant.codecs.as_deref()).1),
+                    language: rendition.language.clone(),
+                    language_name: Some(rendition.name.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+
+        // same resolution can appear more than once
+        for i in 1..formats.len() {
+            if formats[..i].iter().any(|format| format.id == formats[i].id) {
+                formats[i].id = format!("{}-{i}", formats[i].id).into();
+            }
+        }
+
+        formats
+    }
+}
+
+/// Parse `KEY=VALUE,KEY="VALUE, WITH COMMAS"` attribute lists
+pub fn attributes(attrs: &str) -> Vec<(&str, &str)> {
+    let mut list = Vec::new();
+    let mut rest = attrs.trim();
+
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, remaining) = quoted.split_once('"').unwrap_or((quoted, ""));
+                (value, remaining.trim_start_matches(','))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+
+        list.push((key.trim(), value));
+        rest = remaining.trim_start();
+    }
+
+    list
+}
+
+pub fn resolve(base_url: &str, uri: &str) -> Box<str> {
+    match Url::parse(base_url).and_then(|base| base.join(uri)) {
+        Ok(url) => url.as_str().into(),
+        Err(_) => uri.into(),
+    }
+}
+
+/// `LENGTH[@OFFSET]`
+fn parse_byte_range(range: &str, previous_end: u64) -> Option<ByteRange> {
+    let (length, offset) = match range.split_once('@') {
+        Some((length, offset)) => (length, offset.parse().ok()?),
+        None => (range, previous_end),
+    };
+
+    Some(ByteRange {
+        length: length.parse().ok()?,
+        offset,
+    })
+}
+
+/// `0x` prefixed 128 bit hex
+fn parse_iv(iv: &str) -> Option<[u8; 16]> {
+    let hex = iv.strip_prefix("0x").or_else(|| iv.strip_prefix("0X"))?;
+
+    u128::from_str_radix(hex, 16).ok().map(u128::to_be_bytes)
+}
+
+pub(crate) fn split_codecs(codecs: Option<&str>) -> (Option<Box<str>>, Option<Box<str>>) {
+    let (mut vid_codec, mut audio_codec) = (None, None);
+
+    for codec in codecs.unwrap_or_default().split(',').map(str::trim) {
+        if starts(&["avc", "hvc", "hev", "vp0", "vp8", "vp9", "av01"], codec) {
+            vid_codec.get_or_insert_with(|| codec.into());
+        } else if starts(&["mp4a", "opus", "ac-3", "ec-3", "mp3", "flac"], codec) {
+            audio_codec.get_or_insert_with(|| codec.into());
+        }
+    }
+
+    (vid_codec, audio_codec)
+}
+
+fn starts(prefixes: &[&str], codec: &str) -> bool {
+    prefixes.iter().any(|&prefix| codec.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = r#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aud",NAME="English",LANGUAGE="en",DEFAULT=YES,URI="audio/en.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS="avc1.4d401e,mp4a.40.2",AUDIO="aud"
+360/index.m3u8
+
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720,FRAME-RATE=29.970,CODECS="avc1.64001f,mp4a.40.2",AUDIO="aud"
+https://other.test/720.m3u8
+"#;
+
+    const MEDIA: &str = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXT-X-MEDIA-SEQUENCE:7
+#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"
+#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"
+#EXTINF:6.0,
+#EXT-X-BYTERANGE:1000@720
+media.mp4
+#EXTINF:5.5,
+#EXT-X-BYTERANGE:500
+media.mp4
+#EXT-X-KEY:METHOD=AES-128,URI=\"key2.bin\",IV=0x000102030405060708090a0b0c0d0e0f
+#EXTINF:4,
+seg3.ts
+#EXT-X-KEY:METHOD=NONE
+#EXTINF:4,
+seg4.ts
+#EXT-X-ENDLIST
+";
+
+    #[test]
+    fn master_variants_and_renditions() {
+        let master = parse_master(MASTER, "https://cdn.test/hls/master.m3u8");
+
+        assert_eq!(master.variants.len(), 2);
+        assert_eq!(
+            &*master.variants[0].uri,
+            "https://cdn.test/hls/360/index.m3u8"
+        );
+        assert_eq!(master.variants[0].height, Some(360));
+        assert_eq!(master.variants[0].audio.as_deref(), Some("aud"));
+        assert_eq!(&*master.variants[1].uri, "https://other.test/720.m3u8");
+        assert_eq!(master.variants[1].bandwidth, Some(2_500_000));
+        assert_eq!(
+            master.variants[1].codecs.as_deref(),
+            Some("avc1.64001f,mp4a.40.2")
+        );
+
+        let rendition = &master.renditions[0];
+        assert_eq!(
+            rendition.uri.as_deref(),
+            Some("https://cdn.test/hls/audio/en.m3u8")
+        );
+        assert_eq!(rendition.language.as_deref(), Some("en"));
+        assert!(rendition.default);
+
+        assert_eq!(master.pick_variant(480).and_then(|v| v.height), Some(360));
+        assert_eq!(master.pick_variant(0).and_then(|v| v.height), Some(720));
+        // nothing low enough, the smallest one
+        assert_eq!(master.pick_variant(144).and_then(|v| v.height), Some(360));
+
+        let audio = master.audio_rendition(&master.variants[0]);
+        assert_eq!(audio.map(|rendition| &*rendition.name), Some("English"));
+        assert!(master
+            .audio_rendition(&Variant {
+                audio: Some("other".into()),
+                ..Default::default()
+            })
+            .is_none());
+        assert!(master.audio_rendition(&Variant::default()).is_none());
+
+        let ids = master
+            .formats()
+            .into_iter()
+            .map(|f| f.id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            ["hls-360p", "hls-720p", "hls-audio-English"].map(Box::from)
+        );
+    }
+
+    #[test]
+    fn media_segments() {
+        let media = parse_media(MEDIA, "https://cdn.test/hls/v.m3u8");
+
+        assert_eq!(media.target_duration, Some(6));
+        assert!(media.end_list);
+        assert_eq!(media.segments.len(), 4);
+
+        let sequences = media
+            .segments
+            .iter()
+            .map(|s| s.sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, [7, 8, 9, 10]);
+        assert_eq!(media.segments[1].duration, 5.5);
+    }
+
+    #[test]
+    fn media_byte_ranges_and_map() {
+        let media = parse_media(MEDIA, "https://cdn.test/hls/v.m3u8");
+        let map = media.segments[0].map.as_ref().unwrap();
+
+        assert_eq!(&*map.uri, "https://cdn.test/hls/init.mp4");
+        assert_eq!(
+            map.byte_range,
+            Some(ByteRange {
+                length: 720,
+                offset: 0
+            })
+        );
+        assert_eq!(
+            media.segments[0].byte_range,
+            Some(ByteRange {
+                length: 1000,
+                offset: 720
+            })
+        );
+        // no offset, continues where the last range of the same uri ended
+        assert_eq!(
+            media.segments[1].byte_range,
+            Some(ByteRange {
+                length: 500,
+                offset: 1720
+            })
+        );
+        assert_eq!(media.segments[2].byte_range, None);
+        assert!(media.segments.iter().all(|s| s.map.as_ref() == Some(map)));
+    }
+
+    #[test]
+    fn media_keys_and_ivs() {
+        let media = parse_media(MEDIA, "https://cdn.test/hls/v.m3u8");
+        let key = |i: usize| media.segments[i].key.as_ref();
+
+        assert_eq!(
+            key(0).and_then(|k| k.uri.as_deref()),
+            Some("https://cdn.test/hls/key.bin")
+        );
+        assert_eq!(&*key(0).unwrap().method, "AES-128");
+        // no IV attribute, the media sequence number
+        assert_eq!(media.segments[0].iv(), Some(7u128.to_be_bytes()));
+        assert_eq!(media.segments[1].iv(), Some(8u128.to_be_bytes()));
+        assert_eq!(
+            media.segments[2].iv(),
+            Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+        );
+        // METHOD=NONE clears it
+        assert_eq!(key(3), None);
+        assert_eq!(media.segments[3].iv(), None);
+    }
+
+    #[test]
+    fn quoted_attributes_keep_commas() {
+        assert_eq!(
+            attributes(r#"CODECS="avc1,mp4a",BANDWIDTH=1"#),
+            [("CODECS", "avc1,mp4a"), ("BANDWIDTH", "1")]
+        );
+    }
+}
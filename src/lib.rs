@@ -0,0 +1,229 @@
+pub mod cache;
+pub mod chapters;
+pub mod cookies;
+pub mod dash;
+pub mod downloader;
+mod error;
+pub mod extractors;
+mod format;
+mod helpers;
+pub mod hls;
+pub mod mux;
+mod selector;
+pub mod sponsorblock;
+pub mod subtitles;
+pub mod thumbnails;
+
+pub use error::Error;
+use extractors::find_extractor;
+pub use format::{Format, FormatKind, DEFAULT_FORMAT};
+pub use selector::{Selection, Selector};
+use serde::Serialize;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Vid {
+    pub user_agent: &'static str,
+    pub referrer: Box<str>,
+    pub title: Box<str>,
+    /// Channel or account that posted it, the host of a Space
+    pub uploader: Option<Box<str>>,
+    pub vid_link: Box<str>,
+    pub vid_codec: Option<Box<str>>,
+    pub resolution: Option<u16>,
+    pub audio_link: Option<Box<str>>,
+    pub audio_codec: Option<Box<str>>,
+    /// Length in milliseconds
+    pub duration: Option<u64>,
+    pub chapters: Vec<Chapter>,
+    /// Every stream found, the links above are the picked ones
+    pub formats: Vec<Format>,
+    /// Videos of a playlist or channel, empty for a single video
+    pub entries: Vec<Entry>,
+    pub subtitles: Vec<Subtitle>,
+    pub thumbnails: Vec<Thumbnail>,
+    /// More audio tracks to get along with `audio_link`, see `Options::audio_langs`
+    pub extra_audio: Vec<Format>,
+    /// Live stream, its links keep growing until the stream ends
+    pub live: bool,
+    pub kind: MediaKind,
+}
+
+/// What `vid_link` points to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    #[default]
+    Video,
+    /// Silent looping mp4, what twitter turns gifs into
+    Gif,
+    /// Image, only downloaded when picked
+    Photo,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Subtitle {
+    /// Language code like en or pt-BR
+    pub language: Box<str>,
+    pub name: Box<str>,
+    /// Auto generated captions
+    pub auto: bool,
+    pub url: Box<str>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Thumbnail {
+    pub url: Box<str>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Entry {
+    /// Position in the playlist, starts at 1
+    pub index: usize,
+    pub id: Box<str>,
+    pub title: Box<str>,
+    pub url: Box<str>,
+    pub kind: MediaKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chapter {
+    pub start_ms: u64,
+    /// Filled in from the next chapter or the duration when the site doesn't say
+    pub end_ms: u64,
+    pub title: Box<str>,
+    /// SponsorBlock category of the segment this chapter is
+    pub category: Option<Box<str>>,
+}
+
+impl Default for Vid {
+    fn default() -> Self {
+        Self {
+            user_agent: "uwu",
+            referrer: Box::from(""),
+            title: Box::from(""),
+            uploader: None,
+            vid_link: Box::from(""),
+            vid_codec: None,
+            resolution: None,
+            audio_link: None,
+            audio_codec: None,
+            duration: None,
+            chapters: Vec::new(),
+            formats: Vec::new(),
+            entries: Vec::new(),
+            subtitles: Vec::new(),
+            thumbnails: Vec::new(),
+            extra_audio: Vec::new(),
+            live: false,
+            kind: MediaKind::Video,
+        }
+    }
+}
+
+/// Extraction preferences, extractors that don't support one simply ignore it
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Preferred resolution, 0 picks the best one
+    pub resolution: u16,
+    pub vid_codec: Box<str>,
+    pub audio_codec: Box<str>,
+    /// Separate video & audio links instead of a combined one
+    pub is_dash: bool,
+    /// Streaming link instead of a download link
+    pub streaming_link: bool,
+    /// Format ids to pick, see `Vid::select_format`
+    pub format: Option<Box<str>>,
+    /// Playlist positions to keep (1 based), empty keeps every entry
+    pub playlist_items: Vec<RangeInclusive<usize>>,
+    /// Subtitle languages to keep (en also keeps en-US), empty keeps all
+    pub sub_langs: Vec<Box<str>>,
+    /// Audio languages by code or name, the first one is the main audio,
+    /// the rest end up in `Vid::extra_audio`
+    pub audio_langs: Vec<Box<str>>,
+    /// Youtube innertube clients to try in order, empty tries all of them
+    pub yt_clients: Vec<Box<str>>,
+    /// Record live streams from the start of their DVR window instead of the live edge
+    pub live_from_start: bool,
+    /// SponsorBlock categories to mark as chapters, empty skips the lookup
+    pub sponsorblock: Vec<Box<str>>,
+    pub sponsorblock_api: Box<str>,
+    /// Media of a post with several (tweets etc) to pick, starts at 1
+    pub item: Option<usize>,
+    /// Every tweet of the author's self reply thread instead of only the linked one
+    pub thread: bool,
+    /// Stop listing a timeline after this many entries
+    pub max_items: Option<usize>,
+    /// Only list posts newer than this id, for incremental syncs of a timeline
+    pub since_id: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            resolution: 0,
+            vid_codec: Box::from("avc"),
+            audio_codec: Box::from("opus"),
+            is_dash: true,
+            streaming_link: true,
+            format: None,
+            playlist_items: Vec::new(),
+            sub_langs: Vec::new(),
+            audio_langs: Vec::new(),
+            yt_clients: Vec::new(),
+            live_from_start: false,
+            sponsorblock: Vec::new(),
+            sponsorblock_api: Box::from(sponsorblock::API),
+            item: None,
+            thread: false,
+            max_items: None,
+            since_id: None,
+        }
+    }
+}
+
+pub const RED: &str = "\u{1b}[31m";
+pub const RESET: &str = "\u{1b}[0m";
+pub const YELLOW: &str = "\u{1b}[33m";
+
+/// Extract the video (or playlist) behind `url` with the extractor matching it
+pub fn extract(url: &str, options: &Options) -> Result<Vid, Error> {
+    let url = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+
+    let mut vid = find_extractor(url)?.extract(url, options)?;
+
+    chapters::fill_ends(&mut vid.chapters, vid.duration);
+
+    if !options.playlist_items.is_empty() {
+        vid.entries.retain(|entry| {
+            options
+                .playlist_items
+                .iter()
+                .any(|items| items.contains(&entry.index))
+        });
+    }
+
+    if !options.sub_langs.is_empty() {
+        vid.subtitles.retain(|subtitle| {
+            options.sub_langs.iter().any(|lang| {
+                subtitle
+                    .language
+                    .strip_prefix(&**lang)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+            })
+        });
+    }
+
+    if let Some(format) = &options.format {
+        if vid.entries.is_empty() {
+            vid.select_format(format)?;
+        }
+    }
+
+    Ok(vid)
+}
@@ -1,51 +1,30 @@
-mod extractors;
-mod helpers;
-
-use extractors::{
-    bitchute::bitchute, doodstream::doodstream, libsyn::libsyn, lulustream::lulustream,
-    mp4upload::mp4upload, odysee::odysee, reddit::reddit, rokfin::rokfin, rumble::rumble,
-    spotify::spotify, streamdav::streamdav, streamhub::streamhub, streamtape::streamtape,
-    streamvid::streamvid, substack::substack, twatter::twatter, vtube::vtube,
-    wolfstream::wolfstream, youtube::youtube,
-};
-
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde_json::{to_string, to_string_pretty};
 use std::{
-    env::{args, consts::OS},
+    collections::VecDeque,
+    env::{args, consts::OS, temp_dir},
     error::Error,
-    fs::remove_file,
-    process::{exit, Command, Stdio},
+    fs::{read_to_string, remove_file, write},
+    io::{self, read_to_string as read_all, stdin},
+    num::ParseIntError,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    process::{exit, id, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+use titans::{
+    cache, chapters, cookies,
+    dash::is_manifest_link,
+    downloader::{self, human_size},
+    extract,
+    extractors::{youtube, EXTRACTORS},
+    hls::is_playlist_link,
+    mux::{self, Input},
+    sponsorblock, subtitles,
+    thumbnails::{self, ImageKind},
+    Format, FormatKind, MediaKind, Options, Subtitle, Vid, DEFAULT_FORMAT, RED, RESET, YELLOW,
 };
-
-#[derive(Debug, PartialEq)]
-pub struct Vid {
-    user_agent: &'static str,
-    referrer: Box<str>,
-    title: Box<str>,
-    vid_link: Box<str>,
-    vid_codec: Option<Box<str>>,
-    resolution: Option<u16>,
-    audio_link: Option<Box<str>>,
-    audio_codec: Option<Box<str>>,
-    chapter_file: Option<Box<str>>,
-}
-
-impl Default for Vid {
-    fn default() -> Self {
-        Self {
-            user_agent: "uwu",
-            referrer: Box::from(""),
-            title: Box::from(""),
-            vid_link: Box::from(""),
-            vid_codec: None,
-            resolution: None,
-            audio_link: None,
-            audio_codec: None,
-            chapter_file: None,
-        }
-    }
-}
 
 #[derive(Clone, Copy, PartialEq)]
 enum Todo {
@@ -53,25 +32,63 @@ enum Todo {
     Download,
     GetLink,
     Debug,
+    Json,
+    JsonLines,
+    ListFormats,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Downloader {
+    Native,
+    Aria2c,
 }
 
-pub const RED: &str = "\u{1b}[31m";
-pub const RESET: &str = "\u{1b}[0m";
-pub const YELLOW: &str = "\u{1b}[33m";
+#[derive(Clone, Copy, PartialEq)]
+enum SubFormat {
+    Srt,
+    Vtt,
+}
+
+struct Settings {
+    todo: Todo,
+    downloader: Downloader,
+    audio_only: bool,
+    loop_file: bool,
+    speed: f32,
+    /// List playlist entries instead of extracting each one
+    flat_playlist: bool,
+    write_subs: bool,
+    write_auto_subs: bool,
+    sub_format: SubFormat,
+    write_thumbnail: bool,
+    /// Cover art of downloads
+    embed_thumbnail: bool,
+    /// SponsorBlock categories skipped while playing & cut out of downloads
+    sponsorblock_remove: Vec<Box<str>>,
+    /// Picks the variant of HLS master playlists
+    resolution: u16,
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut vid = Vid::default();
-    let mut todo = Todo::Debug;
-    let mut audio_only = false;
-    let mut loop_file = false;
-    let mut streaming_link = true;
-    let mut is_dash = true;
+    let mut options = Options::default();
+    let mut urls: Vec<Box<str>> = Vec::new();
+    let mut settings = Settings {
+        todo: Todo::Debug,
+        downloader: Downloader::Native,
+        audio_only: false,
+        loop_file: false,
+        speed: 0.0,
+        flat_playlist: false,
+        write_subs: false,
+        write_auto_subs: false,
+        sub_format: SubFormat::Srt,
+        write_thumbnail: false,
+        embed_thumbnail: false,
+        sponsorblock_remove: Vec::new(),
+        resolution: 0,
+    };
     let mut no_args = true;
-    let mut multiple_links = false;
-    let mut resolution: u16 = 0;
-    let mut vid_codec = String::from("avc");
-    let mut audio_codec = String::from("opus");
-    let mut speed: f32 = 0.0;
+    let mut clear_cache = false;
     /*
     let set_play = |todo: &mut Todo, audio_only: bool, is_dash: &mut bool| {
         *todo = Todo::Play;
@@ -82,94 +99,6 @@ fn main() -> Result<(), Box<dyn Error>> {
     };
     */
 
-    const LULUSTREAM_PREFIXES: [&str; 4] =
-        ["lulu.st/", "lulustream.com/", "luluvdo.com/", "cdn1.site/"];
-    const VTUBE_PREFIXES: [&str; 2] = ["vtbe.to/", "vtube.network/"];
-    const LIBSYN_PREFIXES: [&str; 2] = ["play.libsyn.com/", "html5-player.libsyn.com/"];
-    const SPOTIFY_PREFIXES: [&str; 2] = [
-        "open.spotify.com/episode/",
-        "open.spotify.com/embed/episode/",
-    ];
-
-    const ODYSEE_PREFIXES: [&str; 4] = [
-        "odysee.com/",
-        // Librarian
-        "lbry.",
-        "librarian.",
-        "odysee.076.ne.jp/",
-    ];
-
-    const YT_PREFIXES: [&str; 17] = [
-        "youtu.be/",
-        // Hyperpipe
-        "hyperpipe.",
-        "music.",
-        "listen.",
-        "hp.",
-        // Piped
-        "piped.",
-        "watch.leptons.xyz/",
-        "pi.ggtyler.dev/",
-        // Invidious instances generally start with invidious, inv, etc
-        "invidious.",
-        "inv.",
-        "iv.",
-        "yt.",
-        "yewtu.be/",
-        "vid.puffyan.us/",
-        "vid.priv.au/",
-        "onion.tube/",
-        "anontube.lvkaszus.pl/",
-    ];
-
-    const REDDIT_PREFIXES: [&str; 16] = [
-        // Reddit
-        "old.reddit.com/",
-        "redd.it/",
-        "reddit.", // bcz some libreddit & teddit instances start with reddit.
-        // Libreddit
-        "libreddit.",
-        "lr.",
-        "safereddit.com/",
-        "r.walkx.fyi/",
-        "l.opnxng.com/",
-        "snoo.habedieeh.re/",
-        // Teddit
-        "teddit.",
-        "snoo.ioens.is/",
-        "incogsnoo.com/",
-        "rdt.trom.tf/",
-        "i.opnxng.com/",
-        "td.vern.cc/",
-        "t.sneed.network/",
-    ];
-
-    const TWATTER_PREFIXES: [&str; 12] = [
-        "x.com/",
-        "mobile.x.com/",
-        "twitter.com/",
-        "mobile.twitter.com/",
-        // Nitter
-        "nitter.",
-        "nt.",
-        "n.",
-        "xcancel.com/",
-        "twiiit.com/",
-        "tweet.lambda.dance/",
-        "bird.habedieeh.re/",
-        "t.com.sb/",
-    ];
-
-    const DOODSTREAM_PREFIXES: [&str; 7] = [
-        "doodstream.com/",
-        "dood.",
-        "d0o0d.com/",
-        "d0000d.com/",
-        "ds2play.com/",
-        "dooood.com/",
-        "doods.pro/",
-    ];
-
     for arg in args().skip(1) {
         no_args = false;
 
@@ -181,30 +110,46 @@ fn main() -> Result<(), Box<dyn Error>> {
                 version();
                 exit(0);
             }
-            "-g" | "--get" => todo = Todo::GetLink,
-            "-p" | "--play" => todo = Todo::Play,
+            "-g" | "--get" => settings.todo = Todo::GetLink,
+            "-j" | "--json" => settings.todo = Todo::Json,
+            "--json-lines" => settings.todo = Todo::JsonLines,
+            "-F" | "--list-formats" => settings.todo = Todo::ListFormats,
+            arg if starts(&["-f=", "--format="], arg) => {
+                options.format = Some(arg.split_once('=').unwrap().1.into());
+            }
+            "-p" | "--play" => settings.todo = Todo::Play,
             arg if starts(&["-sp=", "--speed="], arg) => {
-                speed = arg.rsplit_once('=').unwrap().1.parse()?;
-                todo = Todo::Play;
+                settings.speed = arg.rsplit_once('=').unwrap().1.parse()?;
+                settings.todo = Todo::Play;
             }
-            "-a" | "--audio-only" => audio_only = true,
-            "-l" | "--loop" => loop_file = true,
+            "-a" | "--audio-only" => settings.audio_only = true,
+            "-l" | "--loop" => settings.loop_file = true,
             "-m" | "--music" => {
-                audio_only = true;
-                loop_file = true;
-                speed = 1.0;
-                todo = Todo::Play;
+                settings.audio_only = true;
+                settings.loop_file = true;
+                settings.speed = 1.0;
+                settings.todo = Todo::Play;
             }
             "-d" | "--download" => {
-                todo = Todo::Download;
-                streaming_link = false;
+                settings.todo = Todo::Download;
+                options.streaming_link = false;
+            }
+            arg if arg.starts_with("--downloader=") => {
+                settings.downloader = match arg.split_once('=').unwrap().1 {
+                    "native" => Downloader::Native,
+                    "aria2c" | "aria2" => Downloader::Aria2c,
+                    downloader => {
+                        eprintln!("{RED}Unknown downloader:{YELLOW} {downloader}{RESET}\n");
+                        help_exit(1);
+                    }
+                };
             }
-            "-D" | "--dl_link" => streaming_link = false,
-            "-s" | "--stream_link" => streaming_link = true,
-            "-c" | "--combined" => is_dash = false,
-            "-b" | "--best" => resolution = 0,
+            "-D" | "--dl_link" => options.streaming_link = false,
+            "-s" | "--stream_link" => options.streaming_link = true,
+            "-c" | "--combined" => options.is_dash = false,
+            "-b" | "--best" => options.resolution = 0,
             arg if starts(&["-q=", "--quality="], arg) => {
-                resolution = arg
+                options.resolution = arg
                     .split_once('=')
                     .unwrap()
                     .1
@@ -212,66 +157,109 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .parse()?;
             }
             arg if starts(&["-vc=", "--video-codec="], arg) => {
-                vid_codec = arg.split_once('=').unwrap().1.to_string();
+                options.vid_codec = arg.split_once('=').unwrap().1.into();
             }
             arg if starts(&["-ac=", "--audio-codec="], arg) => {
-                audio_codec = arg.split_once('=').unwrap().1.to_string();
-            }
-            mut arg if starts(&["https://", "http://"], arg) => {
-                if vid == Vid::default() {
-                    arg = arg
-                        .trim_start_matches("https://")
-                        .trim_start_matches("http://")
-                        .trim_start_matches("www.");
-
-                    if arg.contains(".substack.com/p/") {
-                        vid = substack(arg)?;
-                    } else if arg.starts_with("streamhub.") {
-                        vid = streamhub(arg, streaming_link)?;
-                    } else if arg.starts_with("streamvid.") {
-                        vid = streamvid(arg, streaming_link)?;
-                    } else if arg.starts_with("streamtape.") {
-                        vid = streamtape(arg, streaming_link)?;
-                    } else if arg.starts_with("streamdav.com/") {
-                        vid = streamdav(arg)?;
-                    } else if arg.starts_with("wolfstream.tv/") {
-                        vid = wolfstream(arg)?;
-                    } else if starts(&SPOTIFY_PREFIXES, arg) {
-                        vid = spotify(arg)?;
-                    } else if arg.starts_with("bitchute.com/") {
-                        vid = bitchute(arg)?;
-                    } else if arg.starts_with("rumble.com/") {
-                        vid = rumble(arg, resolution)?;
-                    } else if starts(&ODYSEE_PREFIXES, arg) {
-                        vid = odysee(arg)?;
-                    } else if arg.contains("youtube.com/") || starts(&YT_PREFIXES, arg) {
-                        vid = youtube(arg, resolution, &vid_codec, &audio_codec, is_dash)?;
-                    } else if starts(&REDDIT_PREFIXES, arg) {
-                        vid = reddit(arg)?;
-                    } else if starts(&TWATTER_PREFIXES, arg) || arg.contains("unofficialbird.com/")
-                    {
-                        vid = twatter(arg, resolution, streaming_link)?;
-                    } else if starts(&DOODSTREAM_PREFIXES, arg) {
-                        vid = doodstream(arg, streaming_link)?;
-                    } else if starts(&VTUBE_PREFIXES, arg) {
-                        vid = vtube(arg, streaming_link)?;
-                    } else if starts(&LIBSYN_PREFIXES, arg) {
-                        vid = libsyn(arg)?;
-                    } else if starts(&LULUSTREAM_PREFIXES, arg) {
-                        vid = lulustream(arg)?;
-                    } else if arg.starts_with("mp4upload.com/") {
-                        vid = mp4upload(arg)?;
-                    } else if arg.starts_with("rokfin.com/post/") {
-                        vid = rokfin(arg, resolution)?;
-                    } else {
-                        eprintln!("{RED}Unsupported link:{YELLOW} https://{arg}{RESET}\n");
-                        exit(1);
+                options.audio_codec = arg.split_once('=').unwrap().1.into();
+            }
+            arg if arg.starts_with("--playlist-items=") => {
+                options.playlist_items = playlist_items(arg.split_once('=').unwrap().1)?;
+            }
+            "--flat-playlist" => settings.flat_playlist = true,
+            "--thread" => options.thread = true,
+            arg if arg.starts_with("--max-items=") => {
+                options.max_items = Some(arg.split_once('=').unwrap().1.parse()?);
+            }
+            arg if arg.starts_with("--since-id=") => {
+                options.since_id = Some(arg.split_once('=').unwrap().1.parse()?);
+            }
+            arg if arg.starts_with("--item=") => {
+                options.item = Some(arg.split_once('=').unwrap().1.parse()?);
+            }
+            arg if arg.starts_with("--sub-langs=") => {
+                options.sub_langs = arg
+                    .split_once('=')
+                    .unwrap()
+                    .1
+                    .split(',')
+                    .map(Box::from)
+                    .collect();
+            }
+            arg if arg.starts_with("--audio-lang=") => {
+                options.audio_langs = arg
+                    .split_once('=')
+                    .unwrap()
+                    .1
+                    .split(',')
+                    .map(Box::from)
+                    .collect();
+            }
+            arg if arg.starts_with("--yt-client=") => {
+                options.yt_clients = arg
+                    .split_once('=')
+                    .unwrap()
+                    .1
+                    .split(',')
+                    .map(Box::from)
+                    .collect();
+
+                if let Some(client) = options.yt_clients.iter().find(|name| {
+                    !youtube::CLIENTS
+                        .iter()
+                        .any(|client| client.name == &***name)
+                }) {
+                    eprintln!("{RED}Unknown youtube client:{YELLOW} {client}{RESET}\n");
+                    help_exit(1);
+                }
+            }
+            "--live-from-start" => options.live_from_start = true,
+            arg if arg.starts_with("--sponsorblock-mark=") => {
+                options.sponsorblock = sponsorblock_categories(arg.split_once('=').unwrap().1);
+            }
+            arg if arg.starts_with("--sponsorblock-remove=") => {
+                settings.sponsorblock_remove =
+                    sponsorblock_categories(arg.split_once('=').unwrap().1);
+            }
+            arg if arg.starts_with("--sponsorblock-api=") => {
+                options.sponsorblock_api = arg.split_once('=').unwrap().1.into();
+            }
+            "--write-subs" => settings.write_subs = true,
+            "--write-auto-subs" => settings.write_auto_subs = true,
+            arg if arg.starts_with("--sub-format=") => {
+                settings.sub_format = match arg.split_once('=').unwrap().1 {
+                    "srt" => SubFormat::Srt,
+                    "vtt" => SubFormat::Vtt,
+                    format => {
+                        eprintln!("{RED}Unknown subtitle format:{YELLOW} {format}{RESET}\n");
+                        help_exit(1);
+                    }
+                };
+            }
+            "--write-thumbnail" => settings.write_thumbnail = true,
+            "--embed-thumbnail" => settings.embed_thumbnail = true,
+            "--no-cache" => cache::disable(),
+            "--clear-cache" => clear_cache = true,
+            arg if arg.starts_with("--cookies=") => {
+                let file = arg.split_once('=').unwrap().1;
+
+                if let Err(err) = cookies::load(Path::new(file)) {
+                    eprintln!("{RED}Failed to read cookies file {file}:{YELLOW} {err}{RESET}");
+                    exit(8);
+                }
+            }
+            arg if arg.starts_with("--batch-file=") => {
+                let file = arg.split_once('=').unwrap().1;
+
+                match read_to_string(file) {
+                    Ok(batch) => urls.extend(batch_urls(&batch)),
+                    Err(err) => {
+                        eprintln!("{RED}Failed to read batch file {file}:{YELLOW} {err}{RESET}");
+                        exit(8);
                     }
-                } else if !multiple_links {
-                    eprintln!("{RED}Multiple links are not allowed as of now{RESET}\n");
-                    multiple_links = true;
                 }
             }
+            "-" => urls.extend(batch_urls(&read_all(stdin().lock())?)),
+            arg if starts(&["https://", "http://"], arg) => urls.push(arg.into()),
             _ => {
                 eprintln!("{RED}Invalid arg:{YELLOW} {arg}{RESET}\n");
                 help_exit(1);
@@ -284,13 +272,135 @@ fn main() -> Result<(), Box<dyn Error>> {
         help_exit(1);
     }
 
-    if vid.vid_link.is_empty() && vid.audio_link.is_none() {
-        eprintln!("{RED}No video or audio link found{RESET}");
+    if clear_cache {
+        if let Err(err) = cache::clear() {
+            eprintln!("{RED}Failed to clear the cache:{YELLOW} {err}{RESET}");
+            exit(8);
+        }
+
+        eprintln!("{YELLOW}Cleared {}{RESET}", cache::dir().display());
+
+        if urls.is_empty() {
+            return Ok(());
+        }
+    }
+
+    if urls.is_empty() {
+        eprintln!("{RED}No link provided{RESET}");
         exit(1);
     }
 
+    // only the downloader knows how to record the segmented links from the start
+    options.live_from_start &= settings.todo == Todo::Download;
+    settings.resolution = options.resolution;
+
+    // removed segments need looking up too
+    for category in &settings.sponsorblock_remove {
+        if !options.sponsorblock.contains(category) {
+            options.sponsorblock.push(category.clone());
+        }
+    }
+
+    let mut failed = Vec::new();
+    let mut json_vids = Vec::new();
+    let mut urls = VecDeque::from(urls);
+    let mut total = 0;
+
+    while let Some(url) = urls.pop_front() {
+        let result = match extract(&url, &options) {
+            // entries are extracted right after their playlist
+            Ok(vid) if !vid.entries.is_empty() && !settings.flat_playlist => {
+                eprintln!(
+                    "{YELLOW}Playlist:{RESET} {} ({} videos)",
+                    vid.title,
+                    vid.entries.len()
+                );
+
+                let photos = vid
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.kind == MediaKind::Photo)
+                    .count();
+
+                if photos > 0 {
+                    eprintln!("{YELLOW}Skipping {photos} photos, pick them with --item={RESET}");
+                }
+
+                // photos are optional, only downloaded when picked
+                for entry in vid.entries.into_iter().rev() {
+                    if entry.kind != MediaKind::Photo {
+                        urls.push_front(entry.url);
+                    }
+                }
+
+                continue;
+            }
+            Ok(vid) => run(vid, &settings, &mut json_vids)
+                .map_err(|err| (err.to_string(), exit_code(&*err))),
+            Err(err) => Err((err.to_string(), err.exit_code())),
+        };
+
+        total += 1;
+
+        if let Err((err, exit_code)) = result {
+            eprintln!("{RED}{err}{RESET}");
+            failed.push((url, err, exit_code));
+        }
+    }
+
+    if settings.todo == Todo::Json {
+        match json_vids.as_slice() {
+            [vid] if total == 1 => println!("{}", to_string_pretty(vid)?),
+            [] => (),
+            vids => println!("{}", to_string_pretty(vids)?),
+        }
+    }
+
+    if total > 1 {
+        eprintln!(
+            "\n{YELLOW}Done:{RESET} {} succeeded, {} failed",
+            total - failed.len(),
+            failed.len()
+        );
+
+        for (url, err, _) in &failed {
+            eprintln!("{RED}Failed:{YELLOW} {url}{RESET} ({err})");
+        }
+    }
+
+    if let Some((_, _, exit_code)) = failed.first() {
+        exit(*exit_code);
+    }
+
+    Ok(())
+}
+
+fn run(mut vid: Vid, settings: &Settings, json_vids: &mut Vec<Vid>) -> Result<(), Box<dyn Error>> {
+    let Settings {
+        todo,
+        audio_only,
+        loop_file,
+        speed,
+        write_subs,
+        write_auto_subs,
+        resolution,
+        ..
+    } = *settings;
+
+    if !vid.entries.is_empty() && !matches!(todo, Todo::Debug | Todo::Json | Todo::JsonLines) {
+        list_entries(&vid);
+        return Ok(());
+    }
+
+    if vid.vid_link.is_empty() && vid.audio_link.is_none() && vid.entries.is_empty() {
+        return Err("No video or audio link found".into());
+    }
+
     match todo {
         Todo::Debug => println!("{:#?}", vid),
+        Todo::Json => json_vids.push(vid),
+        Todo::JsonLines => println!("{}", to_string(&vid)?),
+        Todo::ListFormats => list_formats(&vid),
         Todo::GetLink => {
             if let Some(audio_link) = vid.audio_link {
                 if !audio_only {
@@ -298,6 +408,10 @@ fn main() -> Result<(), Box<dyn Error>> {
                 } else {
                     println!("{}", audio_link);
                 }
+
+                for format in &vid.extra_audio {
+                    println!("{}", format.url);
+                }
             } else {
                 println!("{}", vid.vid_link);
             }
@@ -305,6 +419,17 @@ fn main() -> Result<(), Box<dyn Error>> {
         Todo::Play => {
             println!("{}Playing {}{}", YELLOW, vid.title, RESET);
 
+            let subtitles = if write_subs || write_auto_subs {
+                write_subtitles(&vid, settings, &temp_dir())
+            } else {
+                Vec::new()
+            }
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+
+            let chapters = chapter_file(&vid);
+            let skip_script = skip_script(&vid, settings);
             let mut audio_arg = String::new();
 
             if (audio_only && vid.audio_link.is_some()) || vid.vid_link.is_empty() {
@@ -337,8 +462,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .args(am_mpv_args)
                     .stdout(Stdio::null())
                     .stderr(Stdio::null())
-                    .spawn()
-                    .expect("Failed to execute am command");
+                    .status()
+                    .map_err(|err| format!("Failed to execute am command: {err}"))?;
             } else {
                 let mpv = {
                     if OS == "windows" {
@@ -356,20 +481,41 @@ fn main() -> Result<(), Box<dyn Error>> {
                         format!("--referrer={}", vid.referrer),
                     ];
 
+                    if let Some(file) = cookies::file() {
+                        mpv_args.push(String::from("--cookies"));
+                        mpv_args.push(format!("--cookies-file={}", file.display()));
+                    }
+
                     if speed != 0.0 {
                         mpv_args.push(format!("--speed={}", speed));
                     }
 
-                    if loop_file {
+                    if loop_file || vid.kind == MediaKind::Gif {
                         mpv_args.push(String::from("--loop-file"));
                     }
 
+                    if vid.kind == MediaKind::Photo {
+                        mpv_args.push(String::from("--image-display-duration=inf"));
+                    }
+
                     if !audio_arg.is_empty() {
                         mpv_args.push(audio_arg);
                     }
 
-                    if let Some(chapters) = vid.chapter_file {
-                        mpv_args.push(format!("--chapters-file={}", chapters));
+                    for format in &vid.extra_audio {
+                        mpv_args.push(format!("--audio-file={}", format.url));
+                    }
+
+                    if let Some(chapters) = &chapters {
+                        mpv_args.push(format!("--chapters-file={}", chapters.display()));
+                    }
+
+                    if let Some(script) = &skip_script {
+                        mpv_args.push(format!("--script={}", script.display()));
+                    }
+
+                    for path in &subtitles {
+                        mpv_args.push(format!("--sub-file={}", path.display()));
                     }
 
                     mpv_args.into_boxed_slice()
@@ -378,16 +524,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                 let mpv_args = args.iter();
 
                 if !audio_only {
+                    // mpv is detached on purpose so the terminal is freed
+                    #[allow(clippy::zombie_processes)]
                     Command::new(mpv)
                         .args(mpv_args)
                         .args(["--no-terminal", "--force-window=immediate"])
                         .spawn()
-                        .expect("Failed to execute mpv");
+                        .map_err(|err| format!("Failed to execute mpv: {err}"))?;
                 } else if !Command::new(mpv)
                     .args(mpv_args)
                     .arg("--no-video")
                     .status()
-                    .expect("Failed to execute mpv")
+                    .map_err(|err| format!("Failed to execute mpv: {err}"))?
                     .success()
                 {
                     eprintln!("{RED}Failed to play audio:{YELLOW} {}{RESET}", vid.vid_link);
@@ -395,54 +543,128 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
         }
         Todo::Download => {
-            let vid_ext = if some_codec_matches(&vid.vid_codec, "vp9", true) {
+            // a master's variant can keep its audio in a separate playlist,
+            // which downloads & merges like any other video + audio
+            if vid.audio_link.is_none() && is_playlist_link(&vid.vid_link) {
+                let (vid_link, audio_link) = downloader::resolve_hls(
+                    &vid.vid_link,
+                    vid.user_agent,
+                    &vid.referrer,
+                    resolution,
+                )?;
+
+                vid.vid_link = vid_link;
+                vid.audio_link = audio_link;
+            }
+
+            // spaces, podcasts, etc
+            let audio_only = audio_only || vid.vid_link.is_empty();
+            let vid_ext = if vid.kind == MediaKind::Photo {
+                image_ext(&vid.vid_link)
+            } else if some_codec_matches(&vid.vid_codec, "vp9", true)
+                || (!audio_only && !vid.extra_audio.is_empty())
+            {
                 "mkv"
+            } else if is_playlist_link(&vid.vid_link) {
+                "ts"
             } else {
                 "mp4"
             };
 
-            let chapter = if vid.chapter_file.is_some() {
+            let subtitles = if write_subs || write_auto_subs {
+                write_subtitles(&vid, settings, Path::new(""))
+            } else {
+                Vec::new()
+            };
+            let subtitle_inputs = subtitles.iter().map(|(path, subtitle)| Input {
+                path,
+                language: Some(&subtitle.language),
+                name: Some(&subtitle.name),
+            });
+            // audio files keep the subtitles next to them
+            let embed_subs = !audio_only && !subtitles.is_empty();
+            let cover = thumbnail(&vid, settings);
+            let cover = cover.as_ref().map(|(image, kind)| (&image[..], *kind));
+
+            let chapter = if !vid.chapters.is_empty() {
                 " without chapters"
+            } else if embed_subs {
+                " without subs"
+            } else if cover.is_some() {
+                " without cover"
             } else {
                 ""
             };
+            let added = if !vid.chapters.is_empty() {
+                "Chapters"
+            } else if embed_subs {
+                "Subs"
+            } else {
+                "Cover"
+            };
 
             let no_emoji = remove_emojis(&vid.title);
+            let cuts = sponsor_cuts(&vid, settings);
 
             if let Some(audio_link) = vid.audio_link.as_deref() {
-                let audio_ext = if some_codec_matches(&vid.audio_codec, "opus", false) {
-                    "opus"
-                } else if some_codec_matches(&vid.audio_codec, "mp4a", true) {
-                    "m4a"
-                } else {
-                    "mp3"
-                };
+                let extra_audio = vid
+                    .extra_audio
+                    .iter()
+                    .map(|format| {
+                        let types = format!(
+                            " audio.{}",
+                            format.language.as_deref().unwrap_or(&format.id)
+                        );
+                        let ext = audio_ext(&format.audio_codec, &format.url);
+
+                        download(
+                            &vid,
+                            &no_emoji,
+                            &format.url,
+                            &types,
+                            ext,
+                            true,
+                            chapter,
+                            settings,
+                        )?;
+
+                        Ok((
+                            PathBuf::from(format!("{no_emoji}{types}{chapter}.{ext}")),
+                            format,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+                let audio_ext = audio_ext(&vid.audio_codec, audio_link);
 
                 if audio_only {
                     download(
-                        &vid, &no_emoji, audio_link, " audio", audio_ext, false, chapter,
-                    );
+                        &vid, &no_emoji, audio_link, " audio", audio_ext, false, chapter, settings,
+                    )?;
 
-                    if let Some(chapters) = vid.chapter_file {
+                    if !chapter.is_empty() {
                         let audio_title =
                             format!("{} audio{}.{}", no_emoji, chapter, audio_ext).into_boxed_str();
 
                         drop(no_emoji);
 
-                        if Command::new("ffmpeg")
-                            .args(["-i", &audio_title])
-                            .args(["-i", &chapters])
-                            .args(["-c", "copy"])
-                            .args(["-y".to_owned(), format!("{}.{}", vid.title, audio_ext)])
-                            .output()
-                            .expect("Failed to execute ffmpeg")
-                            .status
-                            .success()
-                        {
-                            println!("{YELLOW}\nAudio & Chapters merged successfully{RESET}");
+                        // ts can't carry chapters or a cover, the merge repackages it
+                        let merged_ext = match audio_ext {
+                            "ts" => self::audio_ext(&vid.audio_codec, ""),
+                            ext => ext,
+                        };
+
+                        if merge(
+                            &vid,
+                            &[Path::new(&*audio_title).into()],
+                            &format!("{}.{}", vid.title, merged_ext),
+                            &cuts,
+                            cover,
+                        )? {
+                            println!("{YELLOW}\nAudio & {added} merged successfully{RESET}");
                             remove(&audio_title, "Failed to remove downloaded audio");
                         } else {
-                            eprintln!("\n{RED}Audio & Chapters merge failed{RESET}");
+                            eprintln!("\n{RED}Audio & {added} merge failed{RESET}");
                         }
                     }
                 } else {
@@ -454,10 +676,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                         vid_ext,
                         true,
                         chapter,
-                    );
+                        settings,
+                    )?;
                     download(
-                        &vid, &no_emoji, audio_link, " audio", audio_ext, true, chapter,
-                    );
+                        &vid, &no_emoji, audio_link, " audio", audio_ext, true, chapter, settings,
+                    )?;
 
                     let vid_title =
                         format!("{} video{}.{}", no_emoji, chapter, vid_ext).into_boxed_str();
@@ -466,41 +689,49 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     drop(no_emoji);
 
-                    let mut chapter_name = "";
-
-                    let ffmpeg_args = {
-                        let mut args = Vec::new();
-
-                        if let Some(chapters) = vid.chapter_file {
-                            args.push("-i".to_owned());
-                            args.push(chapters.into_string());
-
-                            chapter_name = " + Chapters";
-                        }
-
-                        args.push("-c".to_owned());
-                        args.push("copy".to_owned());
-
-                        args.push("-y".to_owned());
-                        args.push(format!("{}.{}", vid.title, vid_ext));
-
-                        args.into_boxed_slice()
+                    let chapter_name = if !vid.chapters.is_empty() {
+                        " + Chapters"
+                    } else {
+                        ""
                     };
 
-                    if Command::new("ffmpeg")
-                        .args(["-i", &vid_title])
-                        .args(["-i", &audio_title])
-                        .args(ffmpeg_args.iter())
-                        .status()
-                        .expect("Failed to execute ffmpeg")
-                        .success()
-                    {
+                    let main_audio = vid.formats.iter().find(|format| *format.url == *audio_link);
+                    let inputs = [
+                        Path::new(&*vid_title).into(),
+                        Input {
+                            path: Path::new(&*audio_title),
+                            language: main_audio.and_then(|format| format.language.as_deref()),
+                            name: main_audio.and_then(|format| format.language_name.as_deref()),
+                        },
+                    ]
+                    .into_iter()
+                    .chain(extra_audio.iter().map(|(path, format)| Input {
+                        path,
+                        language: format.language.as_deref(),
+                        name: format.language_name.as_deref(),
+                    }))
+                    .chain(subtitle_inputs)
+                    .collect::<Vec<_>>();
+
+                    if merge(
+                        &vid,
+                        &inputs,
+                        &format!("{}.{}", vid.title, vid_ext),
+                        &cuts,
+                        cover,
+                    )? {
                         println!(
                             "{YELLOW}\nVideo + Audio{chapter_name} merged successfully{RESET}"
                         );
 
                         remove(&vid_title, "Failed to remove downloaded video");
                         remove(&audio_title, "Failed to remove downloaded audio");
+
+                        for (path, _) in &extra_audio {
+                            remove_file(path).unwrap_or_else(|_| {
+                                eprintln!("{RED}Failed to remove downloaded audio{RESET}")
+                            });
+                        }
                     } else {
                         eprintln!("\n{RED}Video + Audio{chapter_name} merge failed{RESET}");
                     }
@@ -514,28 +745,31 @@ fn main() -> Result<(), Box<dyn Error>> {
                     vid_ext,
                     false,
                     chapter,
-                );
+                    settings,
+                )?;
 
-                if let Some(chapters) = vid.chapter_file {
+                if !chapter.is_empty() {
                     let vid_title =
                         format!("{} video{}.{}", no_emoji, chapter, vid_ext).into_boxed_str();
 
                     drop(no_emoji);
 
-                    if Command::new("ffmpeg")
-                        .args(["-i", &vid_title])
-                        .args(["-i", &chapters])
-                        .args(["-c", "copy"])
-                        .args(["-y".to_owned(), format!("{}.{}", vid.title, vid_ext)])
-                        .output()
-                        .expect("Failed to execute ffmpeg")
-                        .status
-                        .success()
-                    {
-                        println!("{YELLOW}\nVideo & Chapters merged successfully{RESET}");
+                    let inputs = [Path::new(&*vid_title).into()]
+                        .into_iter()
+                        .chain(subtitle_inputs)
+                        .collect::<Vec<_>>();
+
+                    if merge(
+                        &vid,
+                        &inputs,
+                        &format!("{}.{}", vid.title, vid_ext),
+                        &cuts,
+                        cover,
+                    )? {
+                        println!("{YELLOW}\nVideo & {added} merged successfully{RESET}");
                         remove(&vid_title, "Failed to remove downloaded video");
                     } else {
-                        eprintln!("\n{RED}Video & Chapters merge failed{RESET}");
+                        eprintln!("\n{RED}Video & {added} merge failed{RESET}");
                     }
                 }
             }
@@ -548,19 +782,23 @@ fn main() -> Result<(), Box<dyn Error>> {
 fn help() {
     version();
 
-    println!("
-Usage: titans <args> <url>
+    println!(
+        "
+Usage: titans <args> <url>...
 
 Arguments:
 \t-h, --help\t\t Display this help message
 \t-V, --version\t\t Print version
 \t-g, --get\t\t Get streaming link
+\t-j, --json\t\t Print the extracted data as json
+\t--json-lines\t\t Print the extracted data as one json object per line
 \t-p, --play\t\t Play video in mpv
 \t-sp=, --speed=\t\t Play video in mpv at --speed=1.5
 \t-a, --audio-only\t Play or Download only the audio
 \t-l, --loop\t\t Loop file while playing
 \t-m, --music\t\t Play music (loop audio at speed 1)
-\t-d, --download\t\t Download video with aria2
+\t-d, --download\t\t Download video
+\t--downloader=aria2c\t Download with aria2c instead of the built-in downloader
 \t-D, --dl_link\t\t Get download link
 \t-s, --stream_link\t Get streaming link
 \t-q=, --quality=720p\t Select resolution
@@ -568,14 +806,140 @@ Arguments:
 \t-ac=, --audio-codec=mp4a Select audio codec (default: opus)
 \t-c, --combined\t\t Combined video & audio
 \t-b, --best\t\t best resolution while playing (use it after -p flag)
+\t-F, --list-formats\t List all available formats
+\t-f=, --format=SELECTOR\t Pick formats by id or expression
+\t\t\t\t e.g. bestvideo[height<=720][vcodec^=vp9]+bestaudio/best
+\t--playlist-items=1-10,15 Only these playlist or channel entries
+\t--flat-playlist\t\t List playlist entries without extracting them
+\t--item=2\t\t Pick one media of a tweet with several (photos only this way)
+\t--thread\t\t Media of the whole thread the tweet is part of
+\t--max-items=50\t\t Stop listing a profile's media after 50 tweets
+\t--since-id=ID\t\t Only tweets newer than ID, to sync a profile
+\t--audio-lang=es,hi\t Audio languages by code or name, more than one
+\t\t\t\t are muxed into one mkv on download
+\t--yt-client=ios,web\t Youtube clients to try in order
+\t\t\t\t (default: android_vr,ios,tv_embedded,web)
+\t--sub-langs=en,de\t Subtitle languages (default: all)
+\t--live-from-start\t Record live streams from the start of the DVR window
+\t--sponsorblock-mark=all Mark SponsorBlock segments (sponsor,intro,..) as chapters
+\t--sponsorblock-remove=sponsor Skip them while playing, cut them out of downloads
+\t--sponsorblock-api=URL\t SponsorBlock server (default: https://sponsor.ajay.app)
+\t--write-subs\t\t Download subtitles (embedded in videos, passed to mpv)
+\t--write-auto-subs\t Same for the auto generated ones
+\t--sub-format=vtt\t Subtitle format (default: srt)
+\t--write-thumbnail\t Save the thumbnail next to the download
+\t--embed-thumbnail\t Embed the thumbnail as cover art (mp4, mkv, m4a, opus, mp3)
+\t--cookies=FILE\t\t Netscape cookies.txt for logged in sessions (protected tweets,
+\t\t\t\t age restricted videos, nsfw posts), passed to mpv & aria2c too
+\t--no-cache\t\t Don't read or write cached tokens (guest token, ..)
+\t--clear-cache\t\t Delete the cache dir ({}) first
+\t--batch-file=FILE\t Read links from FILE, one per line (# comments)
+\t-\t\t\t Read links from stdin
+
+Exit codes:
+\t2 Unsupported link\t 3 Network error\t 4 Site changed (parse failure)
+\t5 Geo blocked\t\t 6 Login required\t 7 No formats\t 8 IO error
+\t9 Unsupported media\t 10 Unavailable (removed, private)
+\t11 Not available yet (premiere, scheduled stream), retry later
+
+Supported Extractors: {}",
+        cache::dir().display(),
+        EXTRACTORS
+            .iter()
+            .map(|extractor| extractor.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+fn list_entries(vid: &Vid) {
+    println!("{YELLOW}Playlist {}{RESET}", vid.title);
+
+    for entry in &vid.entries {
+        let kind = match entry.kind {
+            MediaKind::Video => "",
+            MediaKind::Gif => "  [gif]",
+            MediaKind::Photo => "  [photo]",
+        };
+
+        println!("{:>4}  {}  {}{kind}", entry.index, entry.id, entry.title);
+    }
+}
+
+fn list_formats(vid: &Vid) {
+    println!("{YELLOW}Formats of {}{RESET}", vid.title);
+
+    if vid.formats.is_empty() {
+        println!(
+            "No formats listed, picked link ({DEFAULT_FORMAT}): {}",
+            vid.vid_link
+        );
+        return;
+    }
 
-Supported Extractors: bitchute, doodstream, libsyn, lulustream, mp4upload, odysee, reddit, rokfin, rumble, spotify, streamdav, streamhub, streamtape, streamvid, substack, twatter, vtube, wolfstream, youtube");
+    println!(
+        "{:<16} {:<5} {:<10} {:>3} {:>7} {:>10}  {:<14} {:<12} LANG",
+        "ID", "EXT", "RESOLUTION", "FPS", "BITRATE", "SIZE", "VCODEC", "ACODEC"
+    );
+
+    for format in &vid.formats {
+        let resolution = match (format.width, format.height) {
+            (Some(width), Some(height)) => format!("{width}x{height}"),
+            (None, Some(height)) => format!("{height}p"),
+            _ if !format.has_video() => String::from("audio only"),
+            _ => String::new(),
+        };
+
+        let codec = |codec: &Option<Box<str>>, present: bool| match codec {
+            Some(codec) => codec.to_string(),
+            None if present => String::from("unknown"),
+            None => String::from("none"),
+        };
+
+        println!(
+            "{:<16} {:<5} {:<10} {:>3} {:>7} {:>10}  {:<14} {:<12} {}",
+            format.id,
+            format.container,
+            resolution,
+            format.fps.map(|fps| fps.to_string()).unwrap_or_default(),
+            format
+                .bitrate
+                .map(|bitrate| format!("{}k", bitrate / 1000))
+                .unwrap_or_default(),
+            format.filesize.map(human_size).unwrap_or_default(),
+            codec(&format.vid_codec, format.has_video()),
+            codec(&format.audio_codec, format.has_audio()),
+            language(format),
+        );
+    }
+
+    let mut languages = vid
+        .formats
+        .iter()
+        .filter(|format| format.kind == FormatKind::Audio && format.language.is_some())
+        .map(language)
+        .collect::<Vec<_>>();
+    languages.sort_unstable();
+    languages.dedup();
+
+    if languages.len() > 1 {
+        println!("\n{YELLOW}Audio languages:{RESET} {}", languages.join(", "));
+    }
+}
+
+/// `es (Spanish)`
+fn language(format: &Format) -> String {
+    match (&format.language, &format.language_name) {
+        (Some(language), Some(name)) => format!("{language} ({name})"),
+        (language, _) => language.as_deref().unwrap_or_default().to_owned(),
+    }
 }
 
 fn version() {
     println!("Version: {}", env!("CARGO_PKG_VERSION"));
 }
 
+#[allow(clippy::too_many_arguments)]
 fn download(
     vid: &Vid,
     vid_title: &str,
@@ -584,7 +948,8 @@ fn download(
     extension: &str,
     format_title: bool,
     chapter: &str,
-) {
+    settings: &Settings,
+) -> Result<(), Box<dyn Error>> {
     println!(
         "\n{}Downloading{}:{} {}.{}",
         YELLOW, types, RESET, vid.title, extension
@@ -594,7 +959,7 @@ fn download(
         types = "";
     }
 
-    let title_arg = {
+    let out = {
         let title = if vid_title.len() > 201 {
             let title = &vid_title[..201];
             title.rsplit_once(' ').unwrap_or((title, "")).0
@@ -608,42 +973,417 @@ fn download(
 
         let no_multi_space = remove_multiple_spaces(&title);
 
-        format!("--out={}{}{}.{}", no_multi_space, types, chapter, extension).into_boxed_str()
+        format!("{}{}{}.{}", no_multi_space, types, chapter, extension).into_boxed_str()
     };
 
-    if Command::new("aria2c")
-        .args([
-            link,
-            "--max-connection-per-server=16",
-            "--max-concurrent-downloads=16",
-            "--split=16",
-            "--min-split-size=1M",
-            "--check-certificate=false",
-            "--summary-interval=0",
-            "--download-result=hide",
-            &title_arg,
-        ])
-        .args(["--user-agent", vid.user_agent])
-        .args(["--referer", &vid.referrer])
-        .status()
-        .expect("Failed to execute aria2")
-        .success()
-    {
+    let success = match settings.downloader {
+        Downloader::Aria2c if !vid.live && !is_playlist_link(link) && !is_manifest_link(link) => {
+            Command::new("aria2c")
+                .args([
+                    link,
+                    "--max-connection-per-server=16",
+                    "--max-concurrent-downloads=16",
+                    "--split=16",
+                    "--min-split-size=1M",
+                    "--check-certificate=false",
+                    "--summary-interval=0",
+                    "--download-result=hide",
+                    &format!("--out={out}"),
+                ])
+                .args(["--user-agent", vid.user_agent])
+                .args(["--referer", &vid.referrer])
+                .args(cookies::file().map(|file| format!("--load-cookies={}", file.display())))
+                .status()
+                .map_err(|err| format!("Failed to execute aria2: {err}"))?
+                .success()
+        }
+        // aria2c doesn't understand playlists, manifests & live streams
+        _ => {
+            let result = if vid.live && !is_playlist_link(link) {
+                downloader::download_live(
+                    link,
+                    out.as_ref().as_ref(),
+                    vid.user_agent,
+                    &vid.referrer,
+                )
+            } else {
+                downloader::download(
+                    link,
+                    out.as_ref().as_ref(),
+                    vid.user_agent,
+                    &vid.referrer,
+                    settings.resolution,
+                )
+            };
+
+            result?;
+            true
+        }
+    };
+
+    if success {
         println!("\n{YELLOW}Downloaded{types} successfully{RESET}");
+        Ok(())
+    } else {
+        Err("Download Failed".into())
+    }
+}
+
+/// Exit code of a failed link, the library's errors carry their own
+fn exit_code(err: &(dyn Error + 'static)) -> i32 {
+    if let Some(err) = err.downcast_ref::<titans::Error>() {
+        err.exit_code()
+    } else if err.is::<io::Error>() {
+        8
     } else {
-        eprintln!("\n{RED}Download Failed{RESET}");
+        1
     }
 }
 
+/// `1-10,15,20-` to ranges of playlist positions
+fn playlist_items(items: &str) -> Result<Vec<RangeInclusive<usize>>, ParseIntError> {
+    items
+        .split(',')
+        .map(|item| match item.split_once('-') {
+            Some((start, "")) => Ok(start.parse()?..=usize::MAX),
+            Some((start, end)) => Ok(start.parse()?..=end.parse()?),
+            None => item.parse().map(|index| index..=index),
+        })
+        .collect()
+}
+
+fn batch_urls(batch: &str) -> impl Iterator<Item = Box<str>> + '_ {
+    batch
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Box::from)
+}
+
 fn starts(prefixes: &[&str], arg: &str) -> bool {
     prefixes.iter().any(|&prefix| arg.starts_with(prefix))
 }
 
-fn help_exit(exit_code: i32) {
+fn help_exit(exit_code: i32) -> ! {
     help();
     exit(exit_code);
 }
 
+/// Merge with the built-in muxer, ffmpeg only if it can't handle the files
+fn merge(
+    vid: &Vid,
+    inputs: &[Input],
+    output: &str,
+    cuts: &[Range<u64>],
+    cover: Option<(&[u8], ImageKind)>,
+) -> Result<bool, Box<dyn Error>> {
+    match mux::remux(
+        inputs,
+        Path::new(output),
+        &vid.title,
+        &vid.chapters,
+        cuts,
+        cover,
+    ) {
+        Ok(()) => return Ok(true),
+        Err(err) => eprintln!("{YELLOW}{err}, trying ffmpeg{RESET}"),
+    }
+
+    if !cuts.is_empty() {
+        eprintln!("{YELLOW}ffmpeg keeps the SponsorBlock segments, they are only marked{RESET}");
+    }
+
+    let mut ffmpeg = Command::new("ffmpeg");
+
+    for input in inputs {
+        ffmpeg.arg("-i").arg(input.path);
+    }
+
+    let mut next_input = inputs.len();
+
+    if let Some(chapters) = chapter_file(vid) {
+        ffmpeg.arg("-i").arg(chapters);
+        next_input += 1;
+    }
+
+    // without maps ffmpeg keeps only one stream per type,
+    // every input has one stream so the stream index is the input index
+    for (i, input) in inputs.iter().enumerate() {
+        ffmpeg.args(["-map", &i.to_string()]);
+
+        if let Some(language) = input.language {
+            ffmpeg.arg(format!("-metadata:s:{i}"));
+            ffmpeg.arg(format!("language={language}"));
+        }
+
+        if let Some(name) = input.name {
+            ffmpeg.arg(format!("-metadata:s:{i}"));
+            ffmpeg.arg(format!("title={name}"));
+        }
+    }
+
+    let ext = Path::new(output)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let cover_file = cover.and_then(|(image, kind)| match (ext, kind) {
+        // ogg has no cover streams, only a tag
+        ("opus" | "ogg", _) => {
+            let picture = thumbnails::flac_picture(image, kind).replace('=', "\\=");
+            let metadata = format!(";FFMETADATA1\nMETADATA_BLOCK_PICTURE={picture}\n");
+            let path = temp_file("cover", "txt", metadata)?;
+
+            ffmpeg.arg("-i").arg(&path);
+            ffmpeg.args(["-map_metadata", &next_input.to_string()]);
+            Some(path)
+        }
+        ("mkv" | "mka" | "webm", _) => {
+            let path = temp_file("cover", kind.ext(), image)?;
+
+            ffmpeg.arg("-attach").arg(&path);
+            ffmpeg.args(["-metadata:s:t", &format!("mimetype={}", kind.mime())]);
+            ffmpeg.args(["-metadata:s:t", &format!("filename=cover.{}", kind.ext())]);
+            Some(path)
+        }
+        (_, ImageKind::Webp) => None,
+        _ => {
+            let path = temp_file("cover", kind.ext(), image)?;
+
+            ffmpeg.arg("-i").arg(&path);
+            ffmpeg.args(["-map", &next_input.to_string()]);
+            ffmpeg.args([&format!("-disposition:{}", inputs.len()), "attached_pic"]);
+            Some(path)
+        }
+    });
+
+    let has_subtitles = inputs.iter().any(|input| {
+        input
+            .path
+            .extension()
+            .is_some_and(|ext| ext == "srt" || ext == "vtt")
+    });
+
+    if has_subtitles && output.ends_with(".mp4") {
+        ffmpeg.args(["-c:s", "mov_text"]);
+    }
+
+    let success = ffmpeg
+        .args(["-c", "copy", "-y", output])
+        .output()
+        .map_err(|err| format!("Failed to execute ffmpeg: {err}"))?
+        .status
+        .success();
+
+    if let Some(path) = cover_file {
+        remove_file(path).ok();
+    }
+
+    Ok(success)
+}
+
+/// Thumbnail saved next to the download with `--write-thumbnail`,
+/// returned for `--embed-thumbnail`
+fn thumbnail(vid: &Vid, settings: &Settings) -> Option<(Vec<u8>, ImageKind)> {
+    // a photo is its own thumbnail
+    if !settings.write_thumbnail && !settings.embed_thumbnail || vid.kind == MediaKind::Photo {
+        return None;
+    }
+
+    let Some(thumbnail) = thumbnails::best(&vid.thumbnails) else {
+        eprintln!("{YELLOW}No thumbnail found{RESET}");
+        return None;
+    };
+
+    let (image, kind) = thumbnails::fetch(thumbnail, vid.user_agent, &vid.referrer)
+        .map_err(|err| eprintln!("{RED}Failed to get thumbnail:{YELLOW} {err}{RESET}"))
+        .ok()?;
+
+    if settings.write_thumbnail {
+        let path = format!("{}.{}", vid.title.replace('/', "|"), kind.ext());
+
+        if let Err(err) = write(&path, &image) {
+            eprintln!("{RED}Failed to write {path}:{YELLOW} {err}{RESET}");
+        }
+    }
+
+    if settings.embed_thumbnail && kind == ImageKind::Webp {
+        eprintln!("{YELLOW}webp thumbnails only embed in mkv & opus{RESET}");
+    }
+
+    settings.embed_thumbnail.then_some((image, kind))
+}
+
+/// `all` or comma separated SponsorBlock categories
+fn sponsorblock_categories(categories: &str) -> Vec<Box<str>> {
+    if categories == "all" {
+        sponsorblock::CATEGORIES.map(Box::from).to_vec()
+    } else {
+        categories.split(',').map(Box::from).collect()
+    }
+}
+
+/// Time ranges of the chapters `--sponsorblock-remove=` wants gone
+fn sponsor_cuts(vid: &Vid, settings: &Settings) -> Vec<Range<u64>> {
+    vid.chapters
+        .iter()
+        .filter(|chapter| {
+            chapter
+                .category
+                .as_ref()
+                .is_some_and(|category| settings.sponsorblock_remove.contains(category))
+        })
+        .map(|chapter| chapter.start_ms..chapter.end_ms)
+        .collect()
+}
+
+/// mpv script seeking past the chapters of removed SponsorBlock segments
+fn skip_script(vid: &Vid, settings: &Settings) -> Option<PathBuf> {
+    if sponsor_cuts(vid, settings).is_empty() {
+        return None;
+    }
+
+    let titles = settings
+        .sponsorblock_remove
+        .iter()
+        .map(|category| format!("[{:?}] = true", sponsorblock::chapter_title(category)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let script = format!(
+        r#"local skip = {{ {titles} }}
+
+mp.observe_property("chapter", "number", function(_, chapter)
+    if chapter == nil or chapter < 0 then
+        return
+    end
+
+    local chapters = mp.get_property_native("chapter-list")
+
+    if skip[chapters[chapter + 1].title] then
+        local next = chapters[chapter + 2]
+
+        if next then
+            mp.set_property_number("time-pos", next.time)
+        else
+            mp.commandv("seek", "100", "absolute-percent", "exact")
+        end
+
+        mp.osd_message("Skipped " .. chapters[chapter + 1].title)
+    end
+end)
+"#
+    );
+
+    let path = temp_dir().join(format!("titans-sponsorblock-{}.lua", id()));
+
+    match write(&path, script) {
+        Ok(()) => Some(path),
+        Err(err) => {
+            eprintln!("{RED}Failed to create mpv script:{YELLOW} {err}{RESET}");
+            None
+        }
+    }
+}
+
+/// Chapters as an FFMETADATA file in the temp dir, for mpv & ffmpeg
+fn chapter_file(vid: &Vid) -> Option<PathBuf> {
+    if vid.chapters.is_empty() {
+        return None;
+    }
+
+    temp_file("chapters", "txt", chapters::to_ffmetadata(&vid.chapters))
+}
+
+/// File in the temp dir for mpv or ffmpeg to read
+fn temp_file(name: &str, ext: &str, contents: impl AsRef<[u8]>) -> Option<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    // mpv reads it after we moved on to the next link, so every video gets its own
+    let path = temp_dir().join(format!(
+        "titans-{name}-{}-{}.{ext}",
+        id(),
+        COUNT.fetch_add(1, Relaxed)
+    ));
+
+    match write(&path, contents) {
+        Ok(()) => Some(path),
+        Err(err) => {
+            eprintln!("{RED}Failed to create {name} file:{YELLOW} {err}{RESET}");
+            None
+        }
+    }
+}
+
+/// Extension in the path of an image link, jpg when there's none
+fn image_ext(link: &str) -> &str {
+    link.split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit_once('/'))
+        .and_then(|(_, name)| name.rsplit_once('.'))
+        .map_or("jpg", |(_, ext)| ext)
+}
+
+/// Extension of the audio at `link`, playlists are downloaded as the TS (or ADTS) they're made of
+fn audio_ext(codec: &Option<Box<str>>, link: &str) -> &'static str {
+    if is_playlist_link(link) {
+        "ts"
+    } else if some_codec_matches(codec, "opus", false) {
+        "opus"
+    } else if some_codec_matches(codec, "mp4a", true) {
+        "m4a"
+    } else {
+        "mp3"
+    }
+}
+
+/// Subtitles picked by `--write-subs` & `--write-auto-subs` as files in `dir`
+fn write_subtitles<'a>(
+    vid: &'a Vid,
+    settings: &Settings,
+    dir: &Path,
+) -> Vec<(PathBuf, &'a Subtitle)> {
+    let title = vid.title.replace('/', "|");
+
+    vid.subtitles
+        .iter()
+        .filter(|subtitle| {
+            if subtitle.auto {
+                settings.write_auto_subs
+            } else {
+                settings.write_subs
+            }
+        })
+        .filter_map(|subtitle| {
+            let cues = subtitles::fetch(subtitle, vid.user_agent, &vid.referrer)
+                .map_err(|err| {
+                    eprintln!(
+                        "{RED}Failed to get {} subtitles:{YELLOW} {err}{RESET}",
+                        subtitle.name
+                    )
+                })
+                .ok()?;
+
+            let (ext, text) = match settings.sub_format {
+                SubFormat::Srt => ("srt", subtitles::to_srt(&cues)),
+                SubFormat::Vtt => ("vtt", subtitles::to_vtt(&cues)),
+            };
+            let auto = if subtitle.auto { ".auto" } else { "" };
+            let path = dir.join(format!("{title}.{}{auto}.{ext}", subtitle.language));
+
+            write(&path, text)
+                .map_err(|err| {
+                    eprintln!(
+                        "{RED}Failed to write {}:{YELLOW} {err}{RESET}",
+                        path.display()
+                    )
+                })
+                .ok()?;
+
+            Some((path, subtitle))
+        })
+        .collect()
+}
+
 fn remove(path: &str, msg: &str) {
     remove_file(path).unwrap_or_else(|_| eprintln!("{RED}{msg}{RESET}"));
 }
@@ -0,0 +1,905 @@
+use super::{chapter_end, interleave, read_sample, unsupported, Bytes, Codec, Kind, Sample, Track};
+use crate::{thumbnails::ImageKind, Chapter, Error};
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+pub(super) const EBML_MAGIC: [u8; 4] = [0x1a, 0x45, 0xdf, 0xa3];
+
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114d_9b74;
+const SEEK: u32 = 0x4dbb;
+const SEEK_ID: u32 = 0x53ab;
+const SEEK_POSITION: u32 = 0x53ac;
+const INFO: u32 = 0x1549_a966;
+const TIMESTAMP_SCALE: u32 = 0x2ad7b1;
+const DURATION: u32 = 0x4489;
+const TITLE: u32 = 0x7ba9;
+const MUXING_APP: u32 = 0x4d80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_ae6b;
+const TRACK_ENTRY: u32 = 0xae;
+const TRACK_NUMBER: u32 = 0xd7;
+const TRACK_UID: u32 = 0x73c5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_DEFAULT: u32 = 0x88;
+const FLAG_LACING: u32 = 0x9c;
+const NAME: u32 = 0x536e;
+const LANGUAGE_BCP47: u32 = 0x22b59d;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63a2;
+const CODEC_DELAY: u32 = 0x56aa;
+const SEEK_PRE_ROLL: u32 = 0x56bb;
+const DEFAULT_DURATION: u32 = 0x23e383;
+const CONTENT_ENCODINGS: u32 = 0x6d80;
+const VIDEO: u32 = 0xe0;
+const PIXEL_WIDTH: u32 = 0xb0;
+const PIXEL_HEIGHT: u32 = 0xba;
+const AUDIO: u32 = 0xe1;
+const SAMPLING_FREQUENCY: u32 = 0xb5;
+const CHANNELS: u32 = 0x9f;
+const CLUSTER: u32 = 0x1f43_b675;
+const TIMESTAMP: u32 = 0xe7;
+const SIMPLE_BLOCK: u32 = 0xa3;
+const BLOCK_GROUP: u32 = 0xa0;
+const BLOCK: u32 = 0xa1;
+const REFERENCE_BLOCK: u32 = 0xfb;
+const BLOCK_DURATION: u32 = 0x9b;
+const CUES: u32 = 0x1c53_bb6b;
+const CUE_POINT: u32 = 0xbb;
+const CUE_TIME: u32 = 0xb3;
+const CUE_TRACK_POSITIONS: u32 = 0xb7;
+const CUE_TRACK: u32 = 0xf7;
+const CUE_CLUSTER_POSITION: u32 = 0xf1;
+const CHAPTERS: u32 = 0x1043_a770;
+const EDITION_ENTRY: u32 = 0x45b9;
+const CHAPTER_ATOM: u32 = 0xb6;
+const CHAPTER_UID: u32 = 0x73c4;
+const CHAPTER_TIME_START: u32 = 0x91;
+const CHAPTER_TIME_END: u32 = 0x92;
+const CHAPTER_DISPLAY: u32 = 0x80;
+const CHAP_STRING: u32 = 0x85;
+const CHAP_LANGUAGE: u32 = 0x437c;
+const ATTACHMENTS: u32 = 0x1941_a469;
+const ATTACHED_FILE: u32 = 0x61a7;
+const FILE_NAME: u32 = 0x466e;
+const FILE_MEDIA_TYPE: u32 = 0x4660;
+const FILE_DATA: u32 = 0x465c;
+const FILE_UID: u32 = 0x46ae;
+
+/// Demuxed track before the decode times are known
+struct Demuxed {
+    number: u64,
+    kind: Kind,
+    codec: Codec,
+    /// Nanoseconds
+    default_duration: Option<u64>,
+    /// (offset, size, pts, keyframe)
+    blocks: Vec<(u64, u32, i64, bool)>,
+}
+
+pub(super) fn demux(file: &mut File) -> Result<Vec<Track>, Error> {
+    let len = file.metadata()?.len();
+    let mut pos = 0;
+    let mut segment_end = len;
+    let mut timestamp_scale = 1_000_000;
+    let mut tracks: Vec<Demuxed> = Vec::new();
+
+    while pos < segment_end {
+        file.seek(SeekFrom::Start(pos))?;
+
+        let mut header = [0; 12];
+        let read = file.read(&mut header)?;
+        let mut bytes = Bytes::new(&header[..read]);
+
+        let (Some(id), Some(size)) = (read_id(&mut bytes), read_size(&mut bytes)) else {
+            break;
+        };
+
+        let body_start = pos + bytes.pos as u64;
+
+        if id == SEGMENT {
+            // descend
+            segment_end = size.map_or(len, |size| (body_start + size).min(len));
+            pos = body_start;
+            continue;
+        }
+
+        let size = size.ok_or_else(|| unsupported("unknown sized matroska element"))?;
+
+        match id {
+            INFO | TRACKS | CLUSTER => {
+                let mut body = vec![0; size as usize];
+                file.seek(SeekFrom::Start(body_start))?;
+                file.read_exact(&mut body)?;
+
+                match id {
+                    INFO => {
+                        if let Some(scale) = elements(&body).find(|e| e.0 == TIMESTAMP_SCALE) {
+                            timestamp_scale = uint(scale.1);
+                        }
+                    }
+                    TRACKS => tracks = parse_tracks(&body)?,
+                    _ => parse_cluster(&body, body_start, &mut tracks)?,
+                }
+            }
+            _ => (),
+        }
+
+        pos = body_start + size;
+    }
+
+    let (timescale, tick) = if timestamp_scale > 0 && 1_000_000_000 % timestamp_scale == 0 {
+        ((1_000_000_000 / timestamp_scale) as u32, 1)
+    } else {
+        (1_000_000_000, timestamp_scale as i64)
+    };
+
+    Ok(tracks
+        .into_iter()
+        .map(|track| {
+            let mut decode_times = track
+                .blocks
+                .iter()
+                .map(|block| block.2 * tick)
+                .collect::<Vec<_>>();
+            // blocks carry presentation times, decode times are the same times sorted
+            decode_times.sort_unstable();
+
+            let default_duration = track
+                .default_duration
+                .map(|ns| (u128::from(ns) * u128::from(timescale) / 1_000_000_000) as u32);
+
+            let mut samples = track
+                .blocks
+                .iter()
+                .zip(&decode_times)
+                .map(|(&(offset, size, pts, keyframe), &dts)| Sample {
+                    offset,
+                    size,
+                    dts,
+                    cts: (pts * tick - dts) as i32,
+                    duration: 0,
+                    keyframe,
+                })
+                .collect::<Vec<_>>();
+
+            for i in 0..samples.len() {
+                samples[i].duration = match samples.get(i + 1) {
+                    Some(next) => (next.dts - samples[i].dts) as u32,
+                    None => default_duration
+                        .or_else(|| i.checked_sub(1).map(|i| samples[i].duration))
+                        .unwrap_or_default(),
+                };
+            }
+
+            Track {
+                kind: track.kind,
+                codec: track.codec,
+                timescale,
+                samples,
+                input: 0,
+            }
+        })
+        .collect())
+}
+
+fn parse_tracks(body: &[u8]) -> Result<Vec<Demuxed>, Error> {
+    let mut tracks = Vec::new();
+
+    for (_, entry) in elements(body).filter(|e| e.0 == TRACK_ENTRY) {
+        let mut track = Demuxed {
+            number: 0,
+            kind: Kind::Video,
+            codec: Codec::default(),
+            default_duration: None,
+            blocks: Vec::new(),
+        };
+        let mut track_type = 0;
+
+        for (id, value) in elements(entry) {
+            match id {
+                TRACK_NUMBER => track.number = uint(value),
+                TRACK_TYPE => track_type = uint(value),
+                CODEC_ID => track.codec.mkv_id = String::from_utf8_lossy(value).into(),
+                CODEC_PRIVATE => track.codec.private = Some(value.to_vec()),
+                DEFAULT_DURATION => track.default_duration = Some(uint(value)),
+                CONTENT_ENCODINGS => return Err(unsupported("compressed or encrypted matroska")),
+                VIDEO => {
+                    for (id, value) in elements(value) {
+                        match id {
+                            PIXEL_WIDTH => track.codec.width = uint(value) as u16,
+                            PIXEL_HEIGHT => track.codec.height = uint(value) as u16,
+                            _ => (),
+                        }
+                    }
+                }
+                AUDIO => {
+                    for (id, value) in elements(value) {
+                        match id {
+                            SAMPLING_FREQUENCY => track.codec.sample_rate = float(value) as u32,
+                            CHANNELS => track.codec.channels = uint(value) as u16,
+                            _ => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        track.kind = match track_type {
+            1 => Kind::Video,
+            2 => Kind::Audio,
+            _ => continue,
+        };
+
+        tracks.push(track);
+    }
+
+    Ok(tracks)
+}
+
+fn parse_cluster(body: &[u8], body_start: u64, tracks: &mut [Demuxed]) -> Result<(), Error> {
+    let mut cluster_time = 0;
+    let mut bytes = Bytes::new(body);
+
+    while let (Some(id), Some(size)) = (read_id(&mut bytes), read_size(&mut bytes)) {
+        let size = size.ok_or_else(|| unsupported("unknown sized matroska element"))? as usize;
+        let start = bytes.pos;
+        let value = bytes
+            .take(size)
+            .ok_or_else(|| unsupported("truncated cluster"))?;
+
+        match id {
+            TIMESTAMP => cluster_time = uint(value) as i64,
+            SIMPLE_BLOCK => block(value, body_start + start as u64, cluster_time, None, tracks)?,
+            BLOCK_GROUP => {
+                let mut group = Bytes::new(value);
+                let mut found = None;
+                let mut referenced = false;
+
+                while let (Some(id), Some(Some(size))) =
+                    (read_id(&mut group), read_size(&mut group))
+                {
+                    let child_start = group.pos;
+                    let child = group
+                        .take(size as usize)
+                        .ok_or_else(|| unsupported("truncated block group"))?;
+
+                    match id {
+                        BLOCK => found = Some((child, start + child_start)),
+                        REFERENCE_BLOCK => referenced = true,
+                        _ => (),
+                    }
+                }
+
+                if let Some((child, child_start)) = found {
+                    block(
+                        child,
+                        body_start + child_start as u64,
+                        cluster_time,
+                        Some(!referenced),
+                        tracks,
+                    )?;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// `keyframe` is only known from the flags for SimpleBlocks
+fn block(
+    block: &[u8],
+    offset: u64,
+    cluster_time: i64,
+    keyframe: Option<bool>,
+    tracks: &mut [Demuxed],
+) -> Result<(), Error> {
+    let mut bytes = Bytes::new(block);
+    let invalid = || unsupported("matroska block");
+
+    let number = read_size(&mut bytes).flatten().ok_or_else(invalid)?;
+    let time = bytes.u16().ok_or_else(invalid)? as i16;
+    let flags = bytes.u8().ok_or_else(invalid)?;
+
+    if flags & 0x06 != 0 {
+        return Err(unsupported("laced matroska blocks"));
+    }
+
+    if let Some(track) = tracks.iter_mut().find(|track| track.number == number) {
+        track.blocks.push((
+            offset + bytes.pos as u64,
+            bytes.rest().len() as u32,
+            cluster_time + i64::from(time),
+            keyframe.unwrap_or(flags & 0x80 != 0),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Element ids keep their length marker
+fn read_id(bytes: &mut Bytes) -> Option<u32> {
+    let first = bytes.u8()?;
+    let len = first.leading_zeros() as usize + 1;
+
+    if len > 4 {
+        return None;
+    }
+
+    let mut id = u32::from(first);
+
+    for byte in bytes.take(len - 1)? {
+        id = id << 8 | u32::from(*byte);
+    }
+
+    Some(id)
+}
+
+/// `Some(None)` for unknown sizes
+fn read_size(bytes: &mut Bytes) -> Option<Option<u64>> {
+    let first = bytes.u8()?;
+    let len = first.leading_zeros() as usize + 1;
+
+    if len > 8 {
+        return None;
+    }
+
+    let mut size = u64::from(first) & (0xff >> len);
+    let mut all_ones = size == 0xff >> len;
+
+    for byte in bytes.take(len - 1)? {
+        size = size << 8 | u64::from(*byte);
+        all_ones &= *byte == 0xff;
+    }
+
+    Some((!all_ones).then_some(size))
+}
+
+fn elements(data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut bytes = Bytes::new(data);
+
+    std::iter::from_fn(move || {
+        let id = read_id(&mut bytes)?;
+        let size = read_size(&mut bytes)??;
+        Some((id, bytes.take(size as usize)?))
+    })
+}
+
+fn uint(value: &[u8]) -> u64 {
+    value
+        .iter()
+        .fold(0, |uint, byte| uint << 8 | u64::from(*byte))
+}
+
+fn float(value: &[u8]) -> f64 {
+    match value.len() {
+        4 => f64::from(f32::from_be_bytes(value.try_into().unwrap_or_default())),
+        8 => f64::from_be_bytes(value.try_into().unwrap_or_default()),
+        _ => 0.0,
+    }
+}
+
+pub(super) fn write(
+    files: &mut [File],
+    tracks: &[Track],
+    output: &Path,
+    title: &str,
+    chapters: &[Chapter],
+    cover: Option<(&[u8], ImageKind)>,
+) -> Result<(), Error> {
+    let duration_ms = tracks
+        .iter()
+        .map(|track| track.rescale(track.duration() as i64, 1000) as u64)
+        .max()
+        .unwrap_or_default();
+
+    let mut info = uint_element(TIMESTAMP_SCALE, 1_000_000);
+    info.extend(element(DURATION, &(duration_ms as f64).to_be_bytes()));
+    info.extend(element(MUXING_APP, b"titans"));
+    info.extend(element(WRITING_APP, b"titans"));
+
+    if !title.is_empty() {
+        info.extend(element(TITLE, title.as_bytes()));
+    }
+
+    let info = element(INFO, &info);
+    let tracks_element = element(
+        TRACKS,
+        &tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| {
+                let default = tracks[..i].iter().all(|other| other.kind != track.kind);
+                track_entry(track, i as u64 + 1, default)
+            })
+            .collect::<Vec<_>>()
+            .concat(),
+    );
+    let chapters_element = chapters_element(chapters, duration_ms);
+    let attachments = cover.map_or_else(Vec::new, |(image, kind)| attachments(image, kind));
+
+    // positions are fixed 8 byte uints, so the seek head can be sized upfront
+    let seek = |id: u32, position: u64| {
+        element(
+            SEEK,
+            &[
+                element(SEEK_ID, &id_bytes(id)),
+                element(SEEK_POSITION, &position.to_be_bytes()),
+            ]
+            .concat(),
+        )
+    };
+
+    let mut targets = vec![INFO, TRACKS];
+
+    if !chapters_element.is_empty() {
+        targets.push(CHAPTERS);
+    }
+
+    if !attachments.is_empty() {
+        targets.push(ATTACHMENTS);
+    }
+
+    targets.push(CUES);
+
+    let seek_head_len = element(
+        SEEK_HEAD,
+        &targets
+            .iter()
+            .map(|&id| seek(id, 0))
+            .collect::<Vec<_>>()
+            .concat(),
+    )
+    .len() as u64;
+
+    let mut position = seek_head_len;
+    let mut positions = Vec::new();
+
+    for element in [&info, &tracks_element, &chapters_element, &attachments] {
+        if !element.is_empty() {
+            positions.push(position);
+            position += element.len() as u64;
+        }
+    }
+
+    let clusters_start = position;
+    // cues position is patched once the clusters are written
+    positions.push(0);
+
+    let seek_head = element(
+        SEEK_HEAD,
+        &targets
+            .iter()
+            .zip(&positions)
+            .map(|(&id, &position)| seek(id, position))
+            .collect::<Vec<_>>()
+            .concat(),
+    );
+
+    let mut header = element(0x4286, &[1]);
+    header.extend(element(0x42f7, &[1]));
+    header.extend(element(0x42f2, &[4]));
+    header.extend(element(0x42f3, &[8]));
+    let doc_type = match output.extension().and_then(|ext| ext.to_str()) {
+        Some("webm") => "webm",
+        _ => "matroska",
+    };
+    header.extend(element(0x4282, doc_type.as_bytes()));
+    header.extend(element(0x4287, &[4]));
+    header.extend(element(0x4285, &[2]));
+
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(&element(u32::from_be_bytes(EBML_MAGIC), &header))?;
+    out.write_all(&id_bytes(SEGMENT))?;
+
+    let segment_size_at = out.stream_position()?;
+    out.write_all(&[0x01, 0, 0, 0, 0, 0, 0, 0])?;
+
+    let segment_start = out.stream_position()?;
+    out.write_all(&seek_head)?;
+    out.write_all(&info)?;
+    out.write_all(&tracks_element)?;
+    out.write_all(&chapters_element)?;
+    out.write_all(&attachments)?;
+
+    let video = tracks.iter().position(|track| track.kind == Kind::Video);
+    let mut cues = Vec::new();
+    let mut cluster: Vec<u8> = Vec::new();
+    let mut cluster_time = 0;
+    let mut cluster_position = clusters_start;
+    let mut buf = Vec::new();
+
+    let flush = |cluster: &mut Vec<u8>,
+                 cluster_time: i64,
+                 out: &mut BufWriter<File>|
+     -> Result<u64, Error> {
+        if cluster.is_empty() {
+            return Ok(0);
+        }
+
+        let mut body = uint_element(TIMESTAMP, cluster_time.max(0) as u64);
+        body.append(cluster);
+
+        let cluster = element(CLUSTER, &body);
+        out.write_all(&cluster)?;
+        Ok(cluster.len() as u64)
+    };
+
+    for (t, s) in interleave(tracks, 1) {
+        let track = &tracks[t];
+        let sample = &track.samples[s];
+        let time = track.rescale(sample.dts + i64::from(sample.cts), 1000);
+        let relative = time - cluster_time;
+
+        let new_cluster = cluster.is_empty()
+            || !(i64::from(i16::MIN)..=i64::from(i16::MAX)).contains(&relative)
+            || (Some(t) == video && sample.keyframe && relative >= 1000)
+            || (video.is_none() && relative >= 5000)
+            || cluster.len() > 8 * 1024 * 1024;
+
+        if new_cluster {
+            cluster_position += flush(&mut cluster, cluster_time, &mut out)?;
+            cluster_time = time;
+
+            if video.is_none_or(|video| video == t && sample.keyframe) {
+                cues.push((time, t as u64 + 1, cluster_position));
+            }
+        }
+
+        read_sample(files, track, sample, &mut buf)?;
+
+        let mut block = vec![0x80 | (t as u8 + 1)];
+        block.extend(((time - cluster_time) as i16).to_be_bytes());
+
+        if track.kind == Kind::Subtitle {
+            // mp4 only gaps
+            if buf.is_empty() {
+                continue;
+            }
+
+            // subtitles need a duration, so a block group instead of a simple block
+            block.push(0);
+            block.extend(&buf);
+
+            cluster.extend(element(
+                BLOCK_GROUP,
+                &[
+                    element(BLOCK, &block),
+                    uint_element(
+                        BLOCK_DURATION,
+                        track.rescale(sample.duration.into(), 1000) as u64,
+                    ),
+                ]
+                .concat(),
+            ));
+        } else {
+            block.push(if sample.keyframe { 0x80 } else { 0 });
+            block.extend(&buf);
+
+            cluster.extend(element(SIMPLE_BLOCK, &block));
+        }
+    }
+
+    cluster_position += flush(&mut cluster, cluster_time, &mut out)?;
+
+    let cues = element(
+        CUES,
+        &cues
+            .into_iter()
+            .map(|(time, track, position)| {
+                element(
+                    CUE_POINT,
+                    &[
+                        uint_element(CUE_TIME, time.max(0) as u64),
+                        element(
+                            CUE_TRACK_POSITIONS,
+                            &[
+                                uint_element(CUE_TRACK, track),
+                                uint_element(CUE_CLUSTER_POSITION, position),
+                            ]
+                            .concat(),
+                        ),
+                    ]
+                    .concat(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .concat(),
+    );
+
+    out.write_all(&cues)?;
+
+    let segment_size = cluster_position + cues.len() as u64;
+    // last 8 bytes of the last seek entry
+    let cues_seek_at = segment_start + seek_head_len - 8;
+
+    out.seek(SeekFrom::Start(cues_seek_at))?;
+    out.write_all(&cluster_position.to_be_bytes())?;
+
+    out.seek(SeekFrom::Start(segment_size_at + 1))?;
+    out.write_all(&segment_size.to_be_bytes()[1..])?;
+
+    out.flush()?;
+    Ok(())
+}
+
+fn track_entry(track: &Track, number: u64, default: bool) -> Vec<u8> {
+    let codec = &track.codec;
+
+    let mut entry = uint_element(TRACK_NUMBER, number);
+    entry.extend(uint_element(TRACK_UID, number));
+    entry.extend(uint_element(
+        TRACK_TYPE,
+        match track.kind {
+            Kind::Video => 1,
+            Kind::Audio => 2,
+            Kind::Subtitle => 0x11,
+        },
+    ));
+    entry.extend(uint_element(FLAG_DEFAULT, default.into()));
+    entry.extend(uint_element(FLAG_LACING, 0));
+    entry.extend(element(CODEC_ID, codec.mkv_id.as_bytes()));
+
+    if let Some(private) = &codec.private {
+        entry.extend(element(CODEC_PRIVATE, private));
+    }
+
+    if let Some(language) = &codec.language {
+        entry.extend(element(LANGUAGE_BCP47, language.as_bytes()));
+    }
+
+    if let Some(name) = &codec.name {
+        entry.extend(element(NAME, name.as_bytes()));
+    }
+
+    match track.kind {
+        Kind::Video => entry.extend(element(
+            VIDEO,
+            &[
+                uint_element(PIXEL_WIDTH, codec.width.into()),
+                uint_element(PIXEL_HEIGHT, codec.height.into()),
+            ]
+            .concat(),
+        )),
+        Kind::Audio => {
+            if &*codec.mkv_id == "A_OPUS" {
+                let pre_skip = codec
+                    .private
+                    .as_deref()
+                    .and_then(|head| head.get(10..12))
+                    .map_or(0, |pre_skip| u16::from_le_bytes([pre_skip[0], pre_skip[1]]));
+
+                entry.extend(uint_element(
+                    CODEC_DELAY,
+                    u64::from(pre_skip) * 1_000_000_000 / 48000,
+                ));
+                entry.extend(uint_element(SEEK_PRE_ROLL, 80_000_000));
+            }
+
+            entry.extend(element(
+                AUDIO,
+                &[
+                    element(
+                        SAMPLING_FREQUENCY,
+                        &f64::from(codec.sample_rate).to_be_bytes(),
+                    ),
+                    uint_element(CHANNELS, codec.channels.max(1).into()),
+                ]
+                .concat(),
+            ));
+        }
+        Kind::Subtitle => (),
+    }
+
+    element(TRACK_ENTRY, &entry)
+}
+
+/// Cover art, players pick it up by the `cover.` file name
+fn attachments(image: &[u8], kind: ImageKind) -> Vec<u8> {
+    let file = [
+        element(FILE_NAME, format!("cover.{}", kind.ext()).as_bytes()),
+        element(FILE_MEDIA_TYPE, kind.mime().as_bytes()),
+        element(FILE_DATA, image),
+        uint_element(FILE_UID, 1),
+    ]
+    .concat();
+
+    element(ATTACHMENTS, &element(ATTACHED_FILE, &file))
+}
+
+fn chapters_element(chapters: &[Chapter], duration_ms: u64) -> Vec<u8> {
+    if chapters.is_empty() {
+        return Vec::new();
+    }
+
+    let atoms = chapters
+        .iter()
+        .enumerate()
+        .map(|(i, chapter)| {
+            let display = [
+                element(CHAP_STRING, chapter.title.as_bytes()),
+                element(CHAP_LANGUAGE, b"und"),
+            ]
+            .concat();
+
+            element(
+                CHAPTER_ATOM,
+                &[
+                    uint_element(CHAPTER_UID, i as u64 + 1),
+                    uint_element(CHAPTER_TIME_START, chapter.start_ms * 1_000_000),
+                    uint_element(
+                        CHAPTER_TIME_END,
+                        chapter_end(chapter, duration_ms) * 1_000_000,
+                    ),
+                    element(CHAPTER_DISPLAY, &display),
+                ]
+                .concat(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .concat();
+
+    element(CHAPTERS, &element(EDITION_ENTRY, &atoms))
+}
+
+fn id_bytes(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(3);
+    bytes[skip..].to_vec()
+}
+
+fn element(id: u32, body: &[u8]) -> Vec<u8> {
+    let len = body.len() as u64;
+    let mut element = id_bytes(id);
+
+    // smallest size that fits, all ones is reserved for unknown
+    let width = (1..=8)
+        .find(|width| len < (1 << (7 * width)) - 1)
+        .unwrap_or(8);
+    let marked = len | 1 << (7 * width);
+    element.extend(&marked.to_be_bytes()[8 - width..]);
+
+    element.extend(body);
+    element
+}
+
+fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+    element(id, &bytes[skip..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::tests::chapter;
+
+    fn read(data: &[u8]) -> (Option<u32>, Option<Option<u64>>) {
+        let mut bytes = Bytes::new(data);
+        (read_id(&mut bytes), read_size(&mut bytes))
+    }
+
+    #[test]
+    fn encodes_ids() {
+        assert_eq!(id_bytes(SIMPLE_BLOCK), [0xa3]);
+        assert_eq!(id_bytes(SEEK), [0x4d, 0xbb]);
+        assert_eq!(id_bytes(TIMESTAMP_SCALE), [0x2a, 0xd7, 0xb1]);
+        assert_eq!(id_bytes(SEGMENT), [0x18, 0x53, 0x80, 0x67]);
+
+        for id in [SIMPLE_BLOCK, SEEK, TIMESTAMP_SCALE, SEGMENT] {
+            assert_eq!(read_id(&mut Bytes::new(&id_bytes(id))), Some(id));
+        }
+
+        // 5 byte ids don't exist
+        assert_eq!(read(&[0x04, 0, 0, 0, 0]).0, None);
+    }
+
+    #[test]
+    fn encodes_sizes() {
+        let size = |len: usize| element(SIMPLE_BLOCK, &vec![0; len])[1..].to_vec();
+
+        assert_eq!(size(0)[0], 0x80);
+        assert_eq!(size(5)[0], 0x85);
+        assert_eq!(size(126)[0], 0xfe);
+        // 0xff would mean unknown size
+        assert_eq!(size(127)[..2], [0x40, 0x7f]);
+        assert_eq!(size(16_382)[..2], [0x7f, 0xfe]);
+        assert_eq!(size(16_383)[..3], [0x20, 0x3f, 0xff]);
+
+        for len in [0, 5, 126, 127, 16_383, 100_000] {
+            let element = element(CLUSTER, &vec![7; len]);
+            let (id, size) = read(&element);
+
+            assert_eq!(id, Some(CLUSTER));
+            assert_eq!(size, Some(Some(len as u64)));
+        }
+
+        assert_eq!(read(&[0xa3, 0xff]).1, Some(None));
+        assert_eq!(
+            read(&[0xa3, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).1,
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn encodes_uints_minimally() {
+        assert_eq!(uint_element(TRACK_NUMBER, 0), [0xd7, 0x81, 0]);
+        assert_eq!(uint_element(TRACK_NUMBER, 1), [0xd7, 0x81, 1]);
+        assert_eq!(uint_element(TIMESTAMP, 0x1234), [0xe7, 0x82, 0x12, 0x34]);
+        assert_eq!(uint(&uint_element(DURATION, u64::MAX)[2..]), u64::MAX);
+    }
+
+    #[test]
+    fn iterates_elements() {
+        let data = [
+            uint_element(TRACK_NUMBER, 2),
+            element(NAME, b"English"),
+            uint_element(TRACK_TYPE, 2),
+        ]
+        .concat();
+        let parsed = elements(&data).collect::<Vec<_>>();
+
+        assert_eq!(
+            parsed,
+            [
+                (TRACK_NUMBER, &[2][..]),
+                (NAME, b"English"),
+                (TRACK_TYPE, &[2])
+            ]
+        );
+        // stops at a truncated element
+        assert_eq!(elements(&data[..data.len() - 1]).count(), 2);
+    }
+
+    #[test]
+    fn writes_chapter_atoms() {
+        assert!(chapters_element(&[], 1000).is_empty());
+
+        let chapters = [chapter(0, 1500, "Intro"), chapter(1500, 0, "Rest")];
+        let element = chapters_element(&chapters, 60_000);
+
+        let (id, body) = elements(&element).next().unwrap();
+        assert_eq!(id, CHAPTERS);
+        let (id, edition) = elements(body).next().unwrap();
+        assert_eq!(id, EDITION_ENTRY);
+
+        let atoms = elements(edition)
+            .map(|(id, atom)| {
+                assert_eq!(id, CHAPTER_ATOM);
+
+                let field = |wanted| {
+                    elements(atom)
+                        .find(|(id, _)| *id == wanted)
+                        .map(|(_, value)| value)
+                        .unwrap()
+                };
+                let title = elements(field(CHAPTER_DISPLAY))
+                    .find(|(id, _)| *id == CHAP_STRING)
+                    .map(|(_, title)| String::from_utf8_lossy(title).into_owned())
+                    .unwrap();
+
+                (
+                    uint(field(CHAPTER_UID)),
+                    uint(field(CHAPTER_TIME_START)),
+                    uint(field(CHAPTER_TIME_END)),
+                    title,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            atoms,
+            [
+                (1, 0, 1_500_000_000, "Intro".into()),
+                // open ended, lasts till the end
+                (2, 1_500_000_000, 60_000_000_000, "Rest".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,700 @@
+//! Built-in remuxer for the simple case, one video & one audio track copied into mp4 or mkv
+//!
+//! Inputs can be progressive or fragmented mp4 and matroska/webm, plus srt or
+//! vtt subtitles, with a jpeg or png as cover art. Anything else returns [`Error::UnsupportedMedia`] so the
+//! caller can fall back to ffmpeg.
+
+mod mkv;
+mod mp4;
+
+use crate::{thumbnails::ImageKind, Chapter, Error};
+use std::{
+    fs::{read, remove_file, File},
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Video,
+    Audio,
+    Subtitle,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Codec {
+    /// Matroska CodecID
+    mkv_id: Box<str>,
+    /// Matroska CodecPrivate (avcC, AudioSpecificConfig, OpusHead, etc)
+    private: Option<Vec<u8>>,
+    /// Sample entry of mp4 inputs, copied as is into mp4 output
+    sample_entry: Option<Vec<u8>>,
+    width: u16,
+    height: u16,
+    sample_rate: u32,
+    channels: u16,
+    /// Language code & display name of audio & subtitle tracks
+    language: Option<Box<str>>,
+    name: Option<Box<str>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Sample {
+    /// Position in the input file
+    offset: u64,
+    size: u32,
+    dts: i64,
+    /// pts - dts
+    cts: i32,
+    duration: u32,
+    keyframe: bool,
+}
+
+#[derive(Debug)]
+struct Track {
+    kind: Kind,
+    codec: Codec,
+    /// Ticks per second of the sample times
+    timescale: u32,
+    samples: Vec<Sample>,
+    /// Index of the input file
+    input: usize,
+}
+
+impl Track {
+    fn duration(&self) -> u64 {
+        self.samples
+            .iter()
+            .map(|sample| u64::from(sample.duration))
+            .sum()
+    }
+
+    /// Ticks to `scale` units (1000 for ms, etc)
+    fn rescale(&self, ticks: i64, scale: u64) -> i64 {
+        (i128::from(ticks) * i128::from(scale) / i128::from(self.timescale.max(1))) as i64
+    }
+}
+
+/// File to remux, srt & vtt files are read as subtitles
+pub struct Input<'a> {
+    pub path: &'a Path,
+    /// Language code like en or pt-BR of its audio or subtitle track
+    pub language: Option<&'a str>,
+    /// Track name shown by players
+    pub name: Option<&'a str>,
+}
+
+impl<'a> From<&'a Path> for Input<'a> {
+    fn from(path: &'a Path) -> Self {
+        Self {
+            path,
+            language: None,
+            name: None,
+        }
+    }
+}
+
+/// Copy the first video track, the first audio track of every input and
+/// every subtitle into `output`, mp4 or mkv is picked by the extension of `output`
+///
+/// `cuts` are time ranges in ms left out of the output (sponsor segments etc),
+/// each one runs on till the next video keyframe
+///
+/// `cover` is attached to mkv & stored as `covr` in mp4, which only takes jpeg & png
+pub fn remux(
+    inputs: &[Input],
+    output: &Path,
+    title: &str,
+    chapters: &[Chapter],
+    cuts: &[Range<u64>],
+    cover: Option<(&[u8], ImageKind)>,
+) -> Result<(), Error> {
+    let format = match output.extension().and_then(|ext| ext.to_str()) {
+        Some("mkv" | "mka" | "webm") => Container::Mkv,
+        Some("mp4" | "m4a" | "m4v" | "mov") => Container::Mp4,
+        ext => return Err(unsupported(&format!("output extension {ext:?}"))),
+    };
+
+    let mut files = inputs
+        .iter()
+        .map(|input| File::open(input.path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut picked: Vec<Track> = Vec::new();
+
+    for (i, (input, file)) in inputs.iter().zip(&mut files).enumerate() {
+        let is_subtitle = input
+            .path
+            .extension()
+            .is_some_and(|ext| ext == "srt" || ext == "vtt");
+
+        let found = if is_subtitle {
+            vec![subtitle_track(input.path)?]
+        } else {
+            let mut magic = [0; 8];
+            file.read_exact(&mut magic)?;
+            file.rewind()?;
+
+            if magic[..4] == mkv::EBML_MAGIC {
+                mkv::demux(file)?
+            } else if [b"ftyp", b"styp", b"moov", b"sidx", b"free"]
+                .iter()
+                .any(|name| magic[4..] == name[..])
+            {
+                mp4::demux(file)?
+            } else {
+                return Err(unsupported("input container"));
+            }
+        };
+
+        let has_video = picked.iter().any(|track| track.kind == Kind::Video);
+        let mut has_audio = false;
+
+        for mut track in found {
+            let keep = match track.kind {
+                Kind::Video => !has_video,
+                Kind::Audio => !has_audio,
+                Kind::Subtitle => true,
+            };
+
+            if !keep {
+                continue;
+            }
+
+            has_audio |= track.kind == Kind::Audio;
+            track.input = i;
+
+            if track.kind != Kind::Video {
+                track.codec.language = input.language.map(Box::from);
+                track.codec.name = input.name.map(Box::from);
+            }
+
+            picked.push(track);
+        }
+    }
+
+    // video first, then audio & subtitles in input order
+    picked.sort_by_key(|track| track.kind != Kind::Video);
+
+    if picked
+        .iter()
+        .filter(|track| track.kind != Kind::Subtitle)
+        .all(|track| track.samples.is_empty())
+    {
+        return Err(unsupported("no tracks"));
+    }
+
+    let cuts = align_cuts(&picked, cuts);
+    let chapters = shift_chapters(chapters, &cuts);
+
+    for track in &mut picked {
+        cut(track, &cuts);
+    }
+
+    let result = match format {
+        Container::Mkv => mkv::write(&mut files, &picked, output, title, &chapters, cover),
+        Container::Mp4 => mp4::write(&mut files, &picked, output, title, &chapters, cover),
+    };
+
+    if result.is_err() {
+        remove_file(output).ok();
+    }
+
+    result
+}
+
+enum Container {
+    Mkv,
+    Mp4,
+}
+
+/// Cuts moved on to the next video keyframe & merged where they overlap
+fn align_cuts(tracks: &[Track], cuts: &[Range<u64>]) -> Vec<Range<u64>> {
+    let keyframes = tracks
+        .iter()
+        .find(|track| track.kind == Kind::Video)
+        .map(|track| {
+            track
+                .samples
+                .iter()
+                .filter(|sample| sample.keyframe)
+                .map(|sample| pts_ms(track, sample))
+                .collect::<Vec<_>>()
+        });
+
+    let mut aligned = cuts
+        .iter()
+        .filter(|cut| cut.end > cut.start)
+        .map(|cut| {
+            let end = match &keyframes {
+                // no keyframe after it, the rest of the video goes
+                Some(keyframes) => keyframes
+                    .iter()
+                    .copied()
+                    .find(|&time| time >= cut.end)
+                    .unwrap_or(u64::MAX),
+                None => cut.end,
+            };
+
+            cut.start..end
+        })
+        .collect::<Vec<_>>();
+
+    aligned.sort_by_key(|cut| cut.start);
+
+    let mut merged: Vec<Range<u64>> = Vec::new();
+
+    for cut in aligned {
+        match merged.last_mut() {
+            Some(last) if cut.start <= last.end => last.end = last.end.max(cut.end),
+            _ => merged.push(cut),
+        }
+    }
+
+    merged
+}
+
+/// Time cut out before `time`
+fn time_cut(cuts: &[Range<u64>], time: u64) -> u64 {
+    cuts.iter()
+        .map(|cut| time.clamp(cut.start, cut.end) - cut.start)
+        .sum()
+}
+
+/// Drop the samples starting inside a cut & move the rest back by the time cut before them
+fn cut(track: &mut Track, cuts: &[Range<u64>]) {
+    if cuts.is_empty() {
+        return;
+    }
+
+    let timescale = i128::from(track.timescale);
+    let times = track
+        .samples
+        .iter()
+        .map(|sample| pts_ms(track, sample))
+        .collect::<Vec<_>>();
+    let mut times = times.into_iter();
+
+    track.samples.retain_mut(|sample| {
+        let time = times.next().unwrap_or_default();
+
+        if cuts.iter().any(|cut| cut.contains(&time)) {
+            return false;
+        }
+
+        sample.dts -= (i128::from(time_cut(cuts, time)) * timescale / 1000) as i64;
+        true
+    });
+
+    // subtitle durations are what place them in mp4, gaps & cues spanning a cut would drift
+    if track.kind == Kind::Subtitle {
+        for i in 1..track.samples.len() {
+            let gap = track.samples[i].dts - track.samples[i - 1].dts;
+            let previous = &mut track.samples[i - 1];
+            previous.duration = previous.duration.min(gap.max(0) as u32);
+        }
+    }
+}
+
+/// Chapters moved back by the time cut before them, the ones cut entirely are gone
+fn shift_chapters(chapters: &[Chapter], cuts: &[Range<u64>]) -> Vec<Chapter> {
+    chapters
+        .iter()
+        .filter_map(|chapter| {
+            let start = chapter.start_ms - time_cut(cuts, chapter.start_ms);
+            let end = chapter.end_ms - time_cut(cuts, chapter.end_ms);
+
+            // chapters without an end last till the next one
+            if chapter.end_ms > chapter.start_ms && end <= start {
+                return None;
+            }
+
+            Some(Chapter {
+                start_ms: start,
+                end_ms: end,
+                ..chapter.clone()
+            })
+        })
+        .collect()
+}
+
+fn pts_ms(track: &Track, sample: &Sample) -> u64 {
+    track
+        .rescale(sample.dts + i64::from(sample.cts), 1000)
+        .max(0) as u64
+}
+
+/// `(track, sample)` pairs in decode order, grouped in windows of `window_ms`
+fn interleave(tracks: &[Track], window_ms: i64) -> Vec<(usize, usize)> {
+    let mut order = tracks
+        .iter()
+        .enumerate()
+        .flat_map(|(t, track)| {
+            track.samples.iter().enumerate().map(move |(s, sample)| {
+                let ns = track.rescale(sample.dts, 1_000_000_000);
+                ((ns / 1_000_000).div_euclid(window_ms.max(1)), t, ns, s)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    order.sort_unstable();
+    order.into_iter().map(|(_, t, _, s)| (t, s)).collect()
+}
+
+/// Cues of a srt or vtt file as samples pointing at their text,
+/// gaps between cues become empty samples bcz mp4 needs them
+fn subtitle_track(path: &Path) -> Result<Track, Error> {
+    let data = read(path)?;
+    let text = std::str::from_utf8(&data).map_err(|_| unsupported("subtitle encoding"))?;
+    let is_vtt = text.trim_start_matches('\u{feff}').starts_with("WEBVTT");
+
+    // (start ms, end ms, text offset, text size)
+    let mut cues = Vec::new();
+    let mut lines = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end_matches(['\r', '\n'])))
+        })
+        .peekable();
+
+    while let Some((_, line)) = lines.next() {
+        let Some((start, end)) = line.split_once("-->") else {
+            continue;
+        };
+
+        // vtt cue settings follow the end time
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (cue_time(start.trim()), cue_time(end)) else {
+            return Err(unsupported("subtitle timestamp"));
+        };
+
+        let mut span: Option<(usize, usize)> = None;
+
+        while let Some((offset, line)) = lines.next_if(|(_, line)| !line.trim().is_empty()) {
+            span = Some((span.map_or(offset, |span| span.0), offset + line.len()));
+        }
+
+        if let Some((from, to)) = span {
+            cues.push((start, end, from as u64, (to - from) as u32));
+        }
+    }
+
+    cues.sort_by_key(|cue| cue.0);
+
+    let mut samples = Vec::new();
+    let mut time = 0;
+
+    for (i, &(start, end, offset, size)) in cues.iter().enumerate() {
+        // overlapping cues are cut at the next one
+        let end = cues
+            .get(i + 1)
+            .map_or(end, |next| end.min(next.0))
+            .max(start);
+
+        if start > time {
+            samples.push(Sample {
+                dts: time as i64,
+                duration: (start - time) as u32,
+                keyframe: true,
+                ..Default::default()
+            });
+        }
+
+        if end > start {
+            samples.push(Sample {
+                offset,
+                size,
+                dts: start as i64,
+                cts: 0,
+                duration: (end - start) as u32,
+                keyframe: true,
+            });
+            time = end;
+        }
+    }
+
+    Ok(Track {
+        kind: Kind::Subtitle,
+        codec: Codec {
+            mkv_id: if is_vtt {
+                "S_TEXT/WEBVTT"
+            } else {
+                "S_TEXT/UTF8"
+            }
+            .into(),
+            ..Default::default()
+        },
+        timescale: 1000,
+        samples,
+        input: 0,
+    })
+}
+
+/// `HH:MM:SS,mmm`, `MM:SS.mmm` etc to ms
+fn cue_time(time: &str) -> Option<u64> {
+    let (time, ms) = time.split_once([',', '.'])?;
+
+    let seconds = time.split(':').try_fold(0, |total, part| {
+        Some(total * 60 + part.parse::<u64>().ok()?)
+    })?;
+
+    // a fraction of a second, `.5` is 500ms
+    let ms = ms.get(..3).unwrap_or(ms);
+    let fraction = ms.parse::<u64>().ok()? * 10u64.pow(3 - ms.len() as u32);
+
+    Some(seconds * 1000 + fraction)
+}
+
+fn read_sample(
+    files: &mut [File],
+    track: &Track,
+    sample: &Sample,
+    buf: &mut Vec<u8>,
+) -> Result<(), Error> {
+    let file = &mut files[track.input];
+
+    buf.resize(sample.size as usize, 0);
+    file.seek(SeekFrom::Start(sample.offset))?;
+    file.read_exact(buf)?;
+
+    Ok(())
+}
+
+/// Chapter end, cut at the end of the file
+fn chapter_end(chapter: &Chapter, duration_ms: u64) -> u64 {
+    if chapter.end_ms > chapter.start_ms {
+        chapter.end_ms.min(duration_ms)
+    } else {
+        duration_ms
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    Error::UnsupportedMedia(what.into())
+}
+
+/// Big endian reader over a box or element body, `None` once out of bytes
+struct Bytes<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.data.get(self.pos..).unwrap_or_default()
+    }
+}
+
+/// OpusHead (matroska CodecPrivate) to the dOps box body of mp4
+fn opus_head_to_dops(head: &[u8]) -> Option<Vec<u8>> {
+    let head = head.strip_prefix(b"OpusHead")?;
+    let mut dops = vec![0, *head.get(1)?];
+
+    dops.extend(u16::from_le_bytes(head.get(2..4)?.try_into().ok()?).to_be_bytes());
+    dops.extend(u32::from_le_bytes(head.get(4..8)?.try_into().ok()?).to_be_bytes());
+    dops.extend(i16::from_le_bytes(head.get(8..10)?.try_into().ok()?).to_be_bytes());
+    dops.extend(head.get(10..)?);
+
+    Some(dops)
+}
+
+/// dOps box body to OpusHead
+fn dops_to_opus_head(dops: &[u8]) -> Option<Vec<u8>> {
+    let mut head = b"OpusHead".to_vec();
+
+    head.extend([1, *dops.get(1)?]);
+    head.extend(u16::from_be_bytes(dops.get(2..4)?.try_into().ok()?).to_le_bytes());
+    head.extend(u32::from_be_bytes(dops.get(4..8)?.try_into().ok()?).to_le_bytes());
+    head.extend(i16::from_be_bytes(dops.get(8..10)?.try_into().ok()?).to_le_bytes());
+    head.extend(dops.get(10..)?);
+
+    Some(head)
+}
+
+#[cfg(test)]
+// single cuts are ranges in a slice, not a vec of the range
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    /// Track of samples `(dts, duration, keyframe)` one byte apart
+    pub(super) fn track(kind: Kind, timescale: u32, samples: &[(i64, u32, bool)]) -> Track {
+        Track {
+            kind,
+            codec: Codec::default(),
+            timescale,
+            samples: samples
+                .iter()
+                .enumerate()
+                .map(|(i, &(dts, duration, keyframe))| Sample {
+                    offset: i as u64,
+                    size: 1,
+                    dts,
+                    cts: 0,
+                    duration,
+                    keyframe,
+                })
+                .collect(),
+            input: 0,
+        }
+    }
+
+    pub(super) fn chapter(start_ms: u64, end_ms: u64, title: &str) -> Chapter {
+        Chapter {
+            start_ms,
+            end_ms,
+            title: title.into(),
+            category: None,
+        }
+    }
+
+    #[test]
+    fn parses_cue_times() {
+        assert_eq!(cue_time("00:00:01,500"), Some(1500));
+        assert_eq!(cue_time("01:02:03.004"), Some(3_723_004));
+        assert_eq!(cue_time("02:03.5"), Some(123_500));
+        assert_eq!(cue_time("1.5"), Some(1500));
+        assert_eq!(cue_time("1.05"), Some(1050));
+        // past a millisecond is dropped
+        assert_eq!(cue_time("1.23456"), Some(1234));
+        assert_eq!(cue_time("1"), None);
+        assert_eq!(cue_time("a:b.c"), None);
+    }
+
+    #[test]
+    fn aligns_cuts_to_keyframes() {
+        // a keyframe every 2s, 1000 ticks per second
+        let video = track(
+            Kind::Video,
+            1000,
+            &(0..10)
+                .map(|i| (i * 1000, 1000, i % 2 == 0))
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(
+            align_cuts(&[video], &[3000..5000, 4500..4600, 100..100, 8500..9000]),
+            [3000..6000, 8500..u64::MAX]
+        );
+        // no video, cuts stay as they are
+        assert_eq!(align_cuts(&[], &[1000..2000, 1500..2500]), [1000..2500]);
+    }
+
+    #[test]
+    fn cuts_samples_and_shifts_the_rest() {
+        let cuts = [2000..4000, 6000..7000];
+
+        assert_eq!(time_cut(&cuts, 1000), 0);
+        assert_eq!(time_cut(&cuts, 3000), 1000);
+        assert_eq!(time_cut(&cuts, 5000), 2000);
+        assert_eq!(time_cut(&cuts, 9000), 3000);
+
+        // 90khz, a sample a second
+        let mut audio = track(
+            Kind::Audio,
+            90_000,
+            &(0..8)
+                .map(|i| (i * 90_000, 90_000, true))
+                .collect::<Vec<_>>(),
+        );
+        cut(&mut audio, &cuts);
+
+        let times = audio
+            .samples
+            .iter()
+            .map(|sample| pts_ms(&audio, sample))
+            .collect::<Vec<_>>();
+        // 0, 1, 4, 5, 7 are kept & moved back
+        assert_eq!(times, [0, 1000, 2000, 3000, 4000]);
+        assert_eq!(
+            audio.samples.iter().map(|s| s.offset).collect::<Vec<_>>(),
+            [0, 1, 4, 5, 7]
+        );
+    }
+
+    #[test]
+    fn cut_subtitles_dont_overlap() {
+        let mut subs = track(Kind::Subtitle, 1000, &[(0, 3000, true), (5000, 1000, true)]);
+        cut(&mut subs, &[1000..4000]);
+
+        assert_eq!(subs.samples[1].dts, 2000);
+        // the first cue would run into the second otherwise
+        assert_eq!(subs.samples[0].duration, 2000);
+    }
+
+    #[test]
+    fn shifts_chapters() {
+        let chapters = [
+            chapter(0, 2000, "intro"),
+            chapter(2000, 4000, "sponsor"),
+            chapter(4000, 10_000, "video"),
+            chapter(10_000, 0, "outro"),
+        ];
+        let shifted = shift_chapters(&chapters, &[2000..4000]);
+
+        assert_eq!(
+            shifted,
+            [
+                chapter(0, 2000, "intro"),
+                chapter(2000, 8000, "video"),
+                chapter(8000, 0, "outro"),
+            ]
+        );
+        assert_eq!(chapter_end(&shifted[2], 9000), 9000);
+        assert_eq!(chapter_end(&shifted[1], 5000), 5000);
+    }
+
+    #[test]
+    fn interleaves_by_time() {
+        let video = track(
+            Kind::Video,
+            1000,
+            &[(0, 400, true), (400, 400, false), (800, 400, false)],
+        );
+        let audio = track(
+            Kind::Audio,
+            48_000,
+            &[(0, 24_000, true), (24_000, 24_000, true)],
+        );
+
+        assert_eq!(
+            interleave(&[video, audio], 500),
+            [(0, 0), (0, 1), (1, 0), (0, 2), (1, 1)]
+        );
+    }
+}
@@ -0,0 +1,1189 @@
+use super::{
+    chapter_end, dops_to_opus_head, interleave, opus_head_to_dops, read_sample, unsupported, Bytes,
+    Codec, Kind, Sample, Track,
+};
+use crate::{thumbnails::ImageKind, Chapter, Error};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Defaults of fragmented files from `trex`
+#[derive(Default, Clone, Copy)]
+struct TrackDefaults {
+    duration: u32,
+    size: u32,
+    flags: u32,
+}
+
+const NON_SYNC: u32 = 0x10000;
+
+pub(super) fn demux(file: &mut File) -> Result<Vec<Track>, Error> {
+    let len = file.metadata()?.len();
+    let mut tracks = Vec::new();
+    // track id to (index in tracks, defaults)
+    let mut ids: HashMap<u32, (usize, TrackDefaults)> = HashMap::new();
+    let mut pos = 0;
+
+    while pos + 8 <= len {
+        file.seek(SeekFrom::Start(pos))?;
+
+        let mut header = [0; 16];
+        file.read_exact(&mut header[..8])?;
+
+        let mut size = u64::from(u32::from_be_bytes(
+            header[..4].try_into().unwrap_or_default(),
+        ));
+        let name: [u8; 4] = header[4..8].try_into().unwrap_or_default();
+        let mut header_len = 8;
+
+        if size == 1 {
+            file.read_exact(&mut header[8..])?;
+            size = u64::from_be_bytes(header[8..].try_into().unwrap_or_default());
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+
+        if size < header_len {
+            return Err(unsupported("mp4 box size"));
+        }
+
+        if &name == b"moov" || &name == b"moof" {
+            let mut body = vec![0; (size - header_len) as usize];
+            file.read_exact(&mut body)?;
+
+            if &name == b"moov" {
+                parse_moov(&body, &mut tracks, &mut ids).ok_or_else(|| unsupported("mp4 moov"))?;
+            } else {
+                parse_moof(&body, pos, &mut tracks, &ids).ok_or_else(|| unsupported("mp4 moof"))?;
+            }
+        }
+
+        pos += size;
+    }
+
+    Ok(tracks)
+}
+
+/// Child boxes of a box body
+fn boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut bytes = Bytes::new(data);
+
+    std::iter::from_fn(move || {
+        let start = bytes.pos;
+        let size = bytes.u32()?;
+        let name = bytes.take(4)?;
+
+        let size = match size {
+            0 => bytes.data.len() - start,
+            1 => bytes.u64()? as usize,
+            size => size as usize,
+        };
+
+        let body = bytes.take(size.checked_sub(bytes.pos - start)?)?;
+        Some((name, body))
+    })
+}
+
+fn child<'a>(data: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(found, _)| *found == name)
+        .map(|(_, body)| body)
+}
+
+fn parse_moov(
+    moov: &[u8],
+    tracks: &mut Vec<Track>,
+    ids: &mut HashMap<u32, (usize, TrackDefaults)>,
+) -> Option<()> {
+    let mut defaults = HashMap::new();
+
+    if let Some(mvex) = child(moov, b"mvex") {
+        for (_, trex) in boxes(mvex).filter(|(name, _)| *name == b"trex") {
+            let mut trex = Bytes::new(trex);
+            trex.skip(4)?;
+
+            let id = trex.u32()?;
+            trex.skip(4)?;
+
+            defaults.insert(
+                id,
+                TrackDefaults {
+                    duration: trex.u32()?,
+                    size: trex.u32()?,
+                    flags: trex.u32()?,
+                },
+            );
+        }
+    }
+
+    for (_, trak) in boxes(moov).filter(|(name, _)| *name == b"trak") {
+        let mut tkhd = Bytes::new(child(trak, b"tkhd")?);
+        let version = tkhd.u8()?;
+        tkhd.skip(if version == 1 { 19 } else { 11 })?;
+        let id = tkhd.u32()?;
+
+        let mdia = child(trak, b"mdia")?;
+
+        let mut mdhd = Bytes::new(child(mdia, b"mdhd")?);
+        let version = mdhd.u8()?;
+        mdhd.skip(if version == 1 { 19 } else { 11 })?;
+        let timescale = mdhd.u32()?;
+
+        let kind = match child(mdia, b"hdlr")?.get(8..12)? {
+            b"vide" => Kind::Video,
+            b"soun" => Kind::Audio,
+            _ => continue,
+        };
+
+        let stbl = child(child(mdia, b"minf")?, b"stbl")?;
+
+        let stsd = child(stbl, b"stsd")?;
+        let (_, entry) = boxes(stsd.get(8..)?).next()?;
+        // with its header, that's how it's copied
+        let entry = stsd.get(8..8 + 8 + entry.len())?;
+
+        let codec = codec(kind, entry)?;
+
+        let samples = sample_table(stbl)?;
+
+        ids.insert(
+            id,
+            (tracks.len(), defaults.get(&id).copied().unwrap_or_default()),
+        );
+
+        tracks.push(Track {
+            kind,
+            codec,
+            timescale,
+            samples,
+            input: 0,
+        });
+    }
+
+    Some(())
+}
+
+fn codec(kind: Kind, entry: &[u8]) -> Option<Codec> {
+    let fourcc = entry.get(4..8)?;
+    let mut codec = Codec {
+        sample_entry: Some(entry.to_vec()),
+        ..Default::default()
+    };
+
+    match kind {
+        Kind::Video => {
+            let mut fields = Bytes::new(entry.get(32..)?);
+            codec.width = fields.u16()?;
+            codec.height = fields.u16()?;
+
+            let children = entry.get(86..)?;
+
+            (codec.mkv_id, codec.private) = match fourcc {
+                b"avc1" | b"avc3" => (
+                    "V_MPEG4/ISO/AVC".into(),
+                    child(children, b"avcC").map(<[u8]>::to_vec),
+                ),
+                b"hvc1" | b"hev1" => (
+                    "V_MPEGH/ISO/HEVC".into(),
+                    child(children, b"hvcC").map(<[u8]>::to_vec),
+                ),
+                b"av01" => ("V_AV1".into(), child(children, b"av1C").map(<[u8]>::to_vec)),
+                b"vp09" => ("V_VP9".into(), None),
+                // encv & friends are encrypted
+                _ => return None,
+            };
+        }
+        Kind::Audio => {
+            let mut fields = Bytes::new(entry.get(24..)?);
+            codec.channels = fields.u16()?;
+            fields.skip(6)?;
+            codec.sample_rate = fields.u32()? >> 16;
+
+            let children = entry.get(36..)?;
+
+            (codec.mkv_id, codec.private) = match fourcc {
+                b"mp4a" => (
+                    "A_AAC".into(),
+                    child(children, b"esds").and_then(audio_specific_config),
+                ),
+                b"Opus" => (
+                    "A_OPUS".into(),
+                    child(children, b"dOps").and_then(dops_to_opus_head),
+                ),
+                _ => return None,
+            };
+        }
+        // only srt & vtt files are read as subtitles
+        Kind::Subtitle => return None,
+    }
+
+    Some(codec)
+}
+
+/// DecoderSpecificInfo in the esds descriptors
+fn audio_specific_config(esds: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = Bytes::new(esds.get(4..)?);
+
+    loop {
+        let tag = bytes.u8()?;
+        let mut size = 0;
+
+        for _ in 0..4 {
+            let byte = bytes.u8()?;
+            size = size << 7 | usize::from(byte & 0x7f);
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        match tag {
+            // ES_Descriptor, descend into it
+            3 => {
+                bytes.skip(2)?;
+                let flags = bytes.u8()?;
+
+                if flags & 0x80 != 0 {
+                    bytes.skip(2)?;
+                }
+
+                if flags & 0x40 != 0 {
+                    let len = bytes.u8()?;
+                    bytes.skip(len.into())?;
+                }
+
+                if flags & 0x20 != 0 {
+                    bytes.skip(2)?;
+                }
+            }
+            // DecoderConfigDescriptor
+            4 => bytes.skip(13)?,
+            5 => return bytes.take(size).map(<[u8]>::to_vec),
+            _ => bytes.skip(size)?,
+        }
+    }
+}
+
+fn sample_table(stbl: &[u8]) -> Option<Vec<Sample>> {
+    let table = |name: &[u8]| child(stbl, name).map(Bytes::new);
+
+    let mut stsz = table(b"stsz")?;
+    stsz.skip(4)?;
+    let (fixed_size, count) = (stsz.u32()?, stsz.u32()? as usize);
+
+    let mut samples = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        samples.push(Sample {
+            size: if fixed_size == 0 {
+                stsz.u32()?
+            } else {
+                fixed_size
+            },
+            keyframe: true,
+            ..Default::default()
+        });
+    }
+
+    if let Some(mut stts) = table(b"stts") {
+        stts.skip(4)?;
+        let (mut i, mut dts) = (0, 0);
+
+        for _ in 0..stts.u32()? {
+            let (run, delta) = (stts.u32()?, stts.u32()?);
+
+            for _ in 0..run {
+                if let Some(sample) = samples.get_mut(i) {
+                    sample.dts = dts;
+                    sample.duration = delta;
+                }
+
+                dts += i64::from(delta);
+                i += 1;
+            }
+        }
+    }
+
+    if let Some(mut ctts) = table(b"ctts") {
+        ctts.skip(4)?;
+        let mut i = 0;
+
+        for _ in 0..ctts.u32()? {
+            let (run, offset) = (ctts.u32()?, ctts.u32()? as i32);
+
+            for _ in 0..run {
+                if let Some(sample) = samples.get_mut(i) {
+                    sample.cts = offset;
+                }
+
+                i += 1;
+            }
+        }
+    }
+
+    if let Some(mut stss) = table(b"stss") {
+        stss.skip(4)?;
+        samples
+            .iter_mut()
+            .for_each(|sample| sample.keyframe = false);
+
+        for _ in 0..stss.u32()? {
+            if let Some(sample) = samples.get_mut((stss.u32()? as usize).checked_sub(1)?) {
+                sample.keyframe = true;
+            }
+        }
+    }
+
+    let mut chunk_offsets = Vec::new();
+
+    if let Some(mut stco) = table(b"stco") {
+        stco.skip(4)?;
+
+        for _ in 0..stco.u32()? {
+            chunk_offsets.push(u64::from(stco.u32()?));
+        }
+    } else if let Some(mut co64) = table(b"co64") {
+        co64.skip(4)?;
+
+        for _ in 0..co64.u32()? {
+            chunk_offsets.push(co64.u64()?);
+        }
+    }
+
+    let mut stsc = table(b"stsc")?;
+    stsc.skip(4)?;
+
+    let mut runs = Vec::new();
+
+    for _ in 0..stsc.u32()? {
+        runs.push((stsc.u32()? as usize, stsc.u32()? as usize));
+        stsc.skip(4)?;
+    }
+
+    let mut i = 0;
+
+    for (r, &(first_chunk, per_chunk)) in runs.iter().enumerate() {
+        let last_chunk = runs
+            .get(r + 1)
+            .map_or(chunk_offsets.len(), |next| next.0 - 1);
+
+        for chunk in first_chunk..=last_chunk {
+            let mut offset = *chunk_offsets.get(chunk.checked_sub(1)?)?;
+
+            for _ in 0..per_chunk {
+                let Some(sample) = samples.get_mut(i) else {
+                    break;
+                };
+
+                sample.offset = offset;
+                offset += u64::from(sample.size);
+                i += 1;
+            }
+        }
+    }
+
+    Some(samples)
+}
+
+fn parse_moof(
+    moof: &[u8],
+    moof_start: u64,
+    tracks: &mut [Track],
+    ids: &HashMap<u32, (usize, TrackDefaults)>,
+) -> Option<()> {
+    for (_, traf) in boxes(moof).filter(|(name, _)| *name == b"traf") {
+        let mut tfhd = Bytes::new(child(traf, b"tfhd")?);
+        let flags = tfhd.u32()? & 0xff_ffff;
+        let id = tfhd.u32()?;
+
+        let Some(&(index, mut defaults)) = ids.get(&id) else {
+            continue;
+        };
+
+        let base = if flags & 0x1 != 0 {
+            tfhd.u64()?
+        } else {
+            moof_start
+        };
+
+        if flags & 0x2 != 0 {
+            tfhd.skip(4)?;
+        }
+
+        if flags & 0x8 != 0 {
+            defaults.duration = tfhd.u32()?;
+        }
+
+        if flags & 0x10 != 0 {
+            defaults.size = tfhd.u32()?;
+        }
+
+        if flags & 0x20 != 0 {
+            defaults.flags = tfhd.u32()?;
+        }
+
+        let track = &mut tracks[index];
+
+        let mut dts = match child(traf, b"tfdt") {
+            Some(tfdt) => {
+                let mut tfdt = Bytes::new(tfdt);
+
+                if tfdt.u8()? == 1 {
+                    tfdt.skip(3)?;
+                    tfdt.u64()? as i64
+                } else {
+                    tfdt.skip(3)?;
+                    i64::from(tfdt.u32()?)
+                }
+            }
+            None => track
+                .samples
+                .last()
+                .map_or(0, |last| last.dts + i64::from(last.duration)),
+        };
+
+        let mut offset = base;
+
+        for (_, trun) in boxes(traf).filter(|(name, _)| *name == b"trun") {
+            let mut trun = Bytes::new(trun);
+            let flags = trun.u32()? & 0xff_ffff;
+            let count = trun.u32()?;
+
+            if flags & 0x1 != 0 {
+                offset = base.checked_add_signed(i64::from(trun.u32()? as i32))?;
+            }
+
+            let first_flags = if flags & 0x4 != 0 {
+                Some(trun.u32()?)
+            } else {
+                None
+            };
+
+            for i in 0..count {
+                let duration = if flags & 0x100 != 0 {
+                    trun.u32()?
+                } else {
+                    defaults.duration
+                };
+                let size = if flags & 0x200 != 0 {
+                    trun.u32()?
+                } else {
+                    defaults.size
+                };
+                let sample_flags = if flags & 0x400 != 0 {
+                    trun.u32()?
+                } else if i == 0 {
+                    first_flags.unwrap_or(defaults.flags)
+                } else {
+                    defaults.flags
+                };
+                let cts = if flags & 0x800 != 0 {
+                    trun.u32()? as i32
+                } else {
+                    0
+                };
+
+                track.samples.push(Sample {
+                    offset,
+                    size,
+                    dts,
+                    cts,
+                    duration,
+                    keyframe: track.kind == Kind::Audio || sample_flags & NON_SYNC == 0,
+                });
+
+                offset += u64::from(size);
+                dts += i64::from(duration);
+            }
+        }
+    }
+
+    Some(())
+}
+
+pub(super) fn write(
+    files: &mut [File],
+    tracks: &[Track],
+    output: &Path,
+    title: &str,
+    chapters: &[Chapter],
+    cover: Option<(&[u8], ImageKind)>,
+) -> Result<(), Error> {
+    let entries = tracks
+        .iter()
+        .map(|track| {
+            sample_entry(track)
+                .ok_or_else(|| unsupported(&format!("{} in mp4", track.codec.mkv_id)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let order = interleave(tracks, 500);
+
+    // runs of the same track become chunks
+    let mut chunks: Vec<Vec<(usize, u64)>> = vec![Vec::new(); tracks.len()];
+    let mut relative = 0;
+    let mut previous = None;
+
+    for &(t, s) in &order {
+        let sample = &tracks[t].samples[s];
+
+        if previous != Some(t) {
+            chunks[t].push((0, relative));
+            previous = Some(t);
+        }
+
+        if let Some(chunk) = chunks[t].last_mut() {
+            chunk.0 += 1;
+        }
+
+        relative += u64::from(stored_size(&tracks[t], sample));
+    }
+
+    let ftyp = mp4_box(
+        b"ftyp",
+        &[
+            b"isom",
+            &0x200u32.to_be_bytes()[..],
+            b"isom",
+            b"iso2",
+            b"avc1",
+            b"mp41",
+        ]
+        .concat(),
+    );
+
+    // co64 keeps the size the same whatever the offsets are
+    let udta = udta(tracks, title, chapters, cover);
+    let moov_len = moov(tracks, &entries, &chunks, 0, &udta).len() as u64;
+    let data_start = ftyp.len() as u64 + moov_len + 16;
+    let moov = moov(tracks, &entries, &chunks, data_start, &udta);
+
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(&ftyp)?;
+    out.write_all(&moov)?;
+    out.write_all(&1u32.to_be_bytes())?;
+    out.write_all(b"mdat")?;
+    out.write_all(&(relative + 16).to_be_bytes())?;
+
+    let mut buf = Vec::new();
+
+    for (t, s) in order {
+        read_sample(files, &tracks[t], &tracks[t].samples[s], &mut buf)?;
+
+        // tx3g samples are the text prefixed with its length
+        if tracks[t].kind == Kind::Subtitle {
+            out.write_all(&(buf.len() as u16).to_be_bytes())?;
+        }
+
+        out.write_all(&buf)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn stored_size(track: &Track, sample: &Sample) -> u32 {
+    if track.kind == Kind::Subtitle {
+        sample.size + 2
+    } else {
+        sample.size
+    }
+}
+
+fn sample_entry(track: &Track) -> Option<Vec<u8>> {
+    if let Some(entry) = &track.codec.sample_entry {
+        return Some(entry.clone());
+    }
+
+    let codec = &track.codec;
+
+    match &*codec.mkv_id {
+        "V_MPEG4/ISO/AVC" => {
+            let mut entry = vec![0; 6];
+            entry.extend(1u16.to_be_bytes());
+            entry.extend([0; 16]);
+            entry.extend(codec.width.to_be_bytes());
+            entry.extend(codec.height.to_be_bytes());
+            // 72 dpi
+            entry.extend(0x0048_0000u32.to_be_bytes());
+            entry.extend(0x0048_0000u32.to_be_bytes());
+            entry.extend([0; 4]);
+            entry.extend(1u16.to_be_bytes());
+            entry.extend([0; 32]);
+            entry.extend(0x18u16.to_be_bytes());
+            entry.extend((-1i16).to_be_bytes());
+            entry.extend(mp4_box(b"avcC", codec.private.as_deref()?));
+
+            Some(mp4_box(b"avc1", &entry))
+        }
+        "A_OPUS" => {
+            let mut entry = audio_entry(codec.channels, 48000);
+            entry.extend(mp4_box(
+                b"dOps",
+                &opus_head_to_dops(codec.private.as_deref()?)?,
+            ));
+
+            Some(mp4_box(b"Opus", &entry))
+        }
+        "A_AAC" => {
+            let config = codec.private.as_deref()?;
+            let mut entry = audio_entry(codec.channels, codec.sample_rate);
+
+            let mut decoder_config = vec![0x40, 0x15, 0, 0, 0];
+            decoder_config.extend([0; 8]);
+            decoder_config.extend(descriptor(5, config));
+
+            let mut es = vec![0, 1, 0];
+            es.extend(descriptor(4, &decoder_config));
+            es.extend(descriptor(6, &[2]));
+
+            let mut esds = vec![0; 4];
+            esds.extend(descriptor(3, &es));
+            entry.extend(mp4_box(b"esds", &esds));
+
+            Some(mp4_box(b"mp4a", &entry))
+        }
+        "S_TEXT/UTF8" | "S_TEXT/WEBVTT" => {
+            let mut entry = vec![0; 6];
+            entry.extend(1u16.to_be_bytes());
+            entry.extend([0; 4]);
+            // centered at the bottom
+            entry.extend([1, 0xff]);
+            entry.extend([0; 4]);
+            entry.extend([0; 8]);
+            // style record, font 1 in white
+            entry.extend([0, 0, 0, 0, 0, 1, 0, 0x12, 0xff, 0xff, 0xff, 0xff]);
+            entry.extend(mp4_box(b"ftab", b"\0\x01\0\x01\x05Serif"));
+
+            Some(mp4_box(b"tx3g", &entry))
+        }
+        _ => None,
+    }
+}
+
+fn audio_entry(channels: u16, sample_rate: u32) -> Vec<u8> {
+    let mut entry = vec![0; 6];
+    entry.extend(1u16.to_be_bytes());
+    entry.extend([0; 8]);
+    entry.extend(channels.max(1).to_be_bytes());
+    entry.extend(16u16.to_be_bytes());
+    entry.extend([0; 4]);
+    entry.extend((sample_rate.min(0xffff) << 16).to_be_bytes());
+    entry
+}
+
+fn descriptor(tag: u8, body: &[u8]) -> Vec<u8> {
+    let len = body.len() as u32;
+    let mut descriptor = vec![
+        tag,
+        0x80 | (len >> 21) as u8 & 0x7f,
+        0x80 | (len >> 14) as u8 & 0x7f,
+        0x80 | (len >> 7) as u8 & 0x7f,
+        len as u8 & 0x7f,
+    ];
+
+    descriptor.extend(body);
+    descriptor
+}
+
+fn moov(
+    tracks: &[Track],
+    entries: &[Vec<u8>],
+    chunks: &[Vec<(usize, u64)>],
+    data_start: u64,
+    udta: &[u8],
+) -> Vec<u8> {
+    let duration_ms = duration_ms(tracks);
+
+    let mut mvhd = vec![1, 0, 0, 0];
+    mvhd.extend([0; 16]);
+    mvhd.extend(1000u32.to_be_bytes());
+    mvhd.extend(duration_ms.to_be_bytes());
+    mvhd.extend(0x0001_0000u32.to_be_bytes());
+    mvhd.extend(0x0100u16.to_be_bytes());
+    mvhd.extend([0; 10]);
+    mvhd.extend(matrix());
+    mvhd.extend([0; 24]);
+    mvhd.extend((tracks.len() as u32 + 1).to_be_bytes());
+
+    let mut moov = mp4_box(b"mvhd", &mvhd);
+
+    for (i, track) in tracks.iter().enumerate() {
+        let default = tracks[..i].iter().all(|other| other.kind != track.kind);
+
+        moov.extend(trak(
+            track,
+            default,
+            i as u32 + 1,
+            &entries[i],
+            &chunks[i],
+            data_start,
+            duration_ms,
+        ));
+    }
+
+    moov.extend(udta);
+
+    mp4_box(b"moov", &moov)
+}
+
+fn trak(
+    track: &Track,
+    default: bool,
+    id: u32,
+    entry: &[u8],
+    chunks: &[(usize, u64)],
+    data_start: u64,
+    movie_duration: u64,
+) -> Vec<u8> {
+    let duration = track.duration();
+
+    // in movie, only the first track of a kind is enabled
+    let mut tkhd = vec![1, 0, 0, if default { 3 } else { 2 }];
+    tkhd.extend([0; 16]);
+    tkhd.extend(id.to_be_bytes());
+    tkhd.extend([0; 4]);
+    tkhd.extend(
+        track
+            .rescale(duration as i64, 1000)
+            .clamp(0, movie_duration as i64)
+            .to_be_bytes(),
+    );
+    tkhd.extend([0; 8]);
+    tkhd.extend([0; 2]);
+    // tracks of the same alternate group replace each other
+    tkhd.extend(
+        match track.kind {
+            Kind::Video => 0u16,
+            Kind::Audio => 1,
+            Kind::Subtitle => 2,
+        }
+        .to_be_bytes(),
+    );
+    tkhd.extend(
+        if track.kind == Kind::Audio {
+            0x0100u16
+        } else {
+            0
+        }
+        .to_be_bytes(),
+    );
+    tkhd.extend([0; 2]);
+    tkhd.extend(matrix());
+    tkhd.extend((u32::from(track.codec.width) << 16).to_be_bytes());
+    tkhd.extend((u32::from(track.codec.height) << 16).to_be_bytes());
+
+    let mut mdhd = vec![1, 0, 0, 0];
+    mdhd.extend([0; 16]);
+    mdhd.extend(track.timescale.to_be_bytes());
+    mdhd.extend(duration.to_be_bytes());
+    mdhd.extend(packed_language(track.codec.language.as_deref()).to_be_bytes());
+    mdhd.extend([0; 2]);
+
+    // players show the handler name as the track title
+    let (handler, name) = match track.kind {
+        Kind::Video => (b"vide", "VideoHandler"),
+        Kind::Audio => (b"soun", "SoundHandler"),
+        Kind::Subtitle => (b"sbtl", "SubtitleHandler"),
+    };
+    let name = track.codec.name.as_deref().unwrap_or(name);
+
+    let mut hdlr = vec![0; 8];
+    hdlr.extend(handler);
+    hdlr.extend([0; 12]);
+    hdlr.extend(name.as_bytes());
+    hdlr.push(0);
+
+    let media_header = match track.kind {
+        Kind::Video => mp4_box(b"vmhd", &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]),
+        Kind::Audio => mp4_box(b"smhd", &[0; 8]),
+        Kind::Subtitle => mp4_box(b"nmhd", &[0; 4]),
+    };
+
+    let dinf = mp4_box(
+        b"dinf",
+        &mp4_box(
+            b"dref",
+            &[
+                &[0, 0, 0, 0, 0, 0, 0, 1][..],
+                &mp4_box(b"url ", &[0, 0, 0, 1]),
+            ]
+            .concat(),
+        ),
+    );
+
+    let minf = mp4_box(
+        b"minf",
+        &[media_header, dinf, stbl(track, entry, chunks, data_start)].concat(),
+    );
+
+    let mdia = mp4_box(
+        b"mdia",
+        &[mp4_box(b"mdhd", &mdhd), mp4_box(b"hdlr", &hdlr), minf].concat(),
+    );
+
+    mp4_box(b"trak", &[mp4_box(b"tkhd", &tkhd), mdia].concat())
+}
+
+fn stbl(track: &Track, entry: &[u8], chunks: &[(usize, u64)], data_start: u64) -> Vec<u8> {
+    let samples = &track.samples;
+
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend(entry);
+
+    let mut stts_runs: Vec<(u32, u32)> = Vec::new();
+
+    for sample in samples {
+        match stts_runs.last_mut() {
+            Some((count, delta)) if *delta == sample.duration => *count += 1,
+            _ => stts_runs.push((1, sample.duration)),
+        }
+    }
+
+    let mut stbl = [
+        mp4_box(b"stsd", &stsd),
+        full_box_table(
+            b"stts",
+            0,
+            stts_runs.iter().map(|&(count, delta)| [count, delta]),
+        ),
+    ]
+    .concat();
+
+    if samples.iter().any(|sample| sample.cts != 0) {
+        let mut ctts_runs: Vec<(u32, i32)> = Vec::new();
+
+        for sample in samples {
+            match ctts_runs.last_mut() {
+                Some((count, offset)) if *offset == sample.cts => *count += 1,
+                _ => ctts_runs.push((1, sample.cts)),
+            }
+        }
+
+        let version = u8::from(ctts_runs.iter().any(|run| run.1 < 0));
+        stbl.extend(full_box_table(
+            b"ctts",
+            version,
+            ctts_runs
+                .iter()
+                .map(|&(count, offset)| [count, offset as u32]),
+        ));
+    }
+
+    if samples.iter().any(|sample| !sample.keyframe) {
+        stbl.extend(full_box_table(
+            b"stss",
+            0,
+            samples
+                .iter()
+                .enumerate()
+                .filter(|(_, sample)| sample.keyframe)
+                .map(|(i, _)| [i as u32 + 1]),
+        ));
+    }
+
+    let mut stsc_runs: Vec<[u32; 3]> = Vec::new();
+
+    for (i, &(count, _)) in chunks.iter().enumerate() {
+        if stsc_runs.last().is_none_or(|run| run[1] != count as u32) {
+            stsc_runs.push([i as u32 + 1, count as u32, 1]);
+        }
+    }
+
+    stbl.extend(full_box_table(b"stsc", 0, stsc_runs.into_iter()));
+
+    let mut stsz = vec![0; 8];
+    stsz.extend((samples.len() as u32).to_be_bytes());
+
+    for sample in samples {
+        stsz.extend(stored_size(track, sample).to_be_bytes());
+    }
+
+    stbl.extend(mp4_box(b"stsz", &stsz));
+
+    let mut co64 = vec![0; 4];
+    co64.extend((chunks.len() as u32).to_be_bytes());
+
+    for &(_, offset) in chunks {
+        co64.extend((data_start + offset).to_be_bytes());
+    }
+
+    stbl.extend(mp4_box(b"co64", &co64));
+
+    mp4_box(b"stbl", &stbl)
+}
+
+fn duration_ms(tracks: &[Track]) -> u64 {
+    tracks
+        .iter()
+        .map(|track| track.rescale(track.duration() as i64, 1000) as u64)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Title as `©nam`, cover as `covr` & chapters as a nero `chpl` box
+fn udta(
+    tracks: &[Track],
+    title: &str,
+    chapters: &[Chapter],
+    cover: Option<(&[u8], ImageKind)>,
+) -> Vec<u8> {
+    let duration_ms = duration_ms(tracks);
+    let mut udta = Vec::new();
+
+    if !chapters.is_empty() {
+        let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0];
+        let chapters = &chapters[..chapters.len().min(255)];
+        chpl.push(chapters.len() as u8);
+
+        for chapter in chapters {
+            let start = chapter.start_ms.min(chapter_end(chapter, duration_ms));
+            let title = truncate(&chapter.title, 255);
+
+            chpl.extend((start * 10_000).to_be_bytes());
+            chpl.push(title.len() as u8);
+            chpl.extend(title.as_bytes());
+        }
+
+        udta.extend(mp4_box(b"chpl", &chpl));
+    }
+
+    let mut ilst = Vec::new();
+
+    if !title.is_empty() {
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend(title.as_bytes());
+
+        ilst.extend(mp4_box(b"\xa9nam", &mp4_box(b"data", &data)));
+    }
+
+    // well known types 13 & 14, there's none for webp
+    let cover = cover.and_then(|(image, kind)| match kind {
+        ImageKind::Jpeg => Some((image, 13)),
+        ImageKind::Png => Some((image, 14)),
+        ImageKind::Webp => None,
+    });
+
+    if let Some((image, data_type)) = cover {
+        let mut data = vec![0, 0, 0, data_type, 0, 0, 0, 0];
+        data.extend(image);
+
+        ilst.extend(mp4_box(b"covr", &mp4_box(b"data", &data)));
+    }
+
+    if !ilst.is_empty() {
+        let ilst = mp4_box(b"ilst", &ilst);
+
+        let mut hdlr = vec![0; 8];
+        hdlr.extend(b"mdirappl");
+        hdlr.extend([0; 9]);
+
+        let mut meta = vec![0; 4];
+        meta.extend(mp4_box(b"hdlr", &hdlr));
+        meta.extend(ilst);
+
+        udta.extend(mp4_box(b"meta", &meta));
+    }
+
+    if udta.is_empty() {
+        udta
+    } else {
+        mp4_box(b"udta", &udta)
+    }
+}
+
+/// ISO 639-2 code packed into 15 bits, und for anything else
+fn packed_language(language: Option<&str>) -> u16 {
+    match language.map(str::as_bytes) {
+        Some(&[a, b, c]) if [a, b, c].iter().all(u8::is_ascii_lowercase) => [a, b, c]
+            .iter()
+            .fold(0, |packed, &letter| packed << 5 | u16::from(letter - 0x60)),
+        _ => 0x55c4,
+    }
+}
+
+fn full_box_table<const N: usize>(
+    name: &[u8; 4],
+    version: u8,
+    rows: impl Iterator<Item = [u32; N]>,
+) -> Vec<u8> {
+    let rows = rows.collect::<Vec<_>>();
+    let mut body = vec![version, 0, 0, 0];
+    body.extend((rows.len() as u32).to_be_bytes());
+
+    for row in rows {
+        for value in row {
+            body.extend(value.to_be_bytes());
+        }
+    }
+
+    mp4_box(name, &body)
+}
+
+fn matrix() -> Vec<u8> {
+    [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+fn mp4_box(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut mp4_box = (body.len() as u32 + 8).to_be_bytes().to_vec();
+    mp4_box.extend(name);
+    mp4_box.extend(body);
+    mp4_box
+}
+
+/// At most `max` bytes without splitting a char
+fn truncate(string: &str, max: usize) -> &str {
+    let mut end = string.len().min(max);
+
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &string[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::tests::{chapter, track};
+
+    fn u32s(data: &[u8]) -> Vec<u32> {
+        data.chunks(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    fn video() -> Track {
+        let mut video = track(
+            Kind::Video,
+            1000,
+            &[
+                (0, 40, true),
+                (40, 40, false),
+                (80, 40, false),
+                (120, 20, true),
+                (140, 40, false),
+            ],
+        );
+
+        for (sample, (size, cts)) in
+            video
+                .samples
+                .iter_mut()
+                .zip([(100, 0), (20, 80), (30, -40), (90, 0), (10, 0)])
+        {
+            sample.size = size;
+            sample.cts = cts;
+        }
+
+        video
+    }
+
+    #[test]
+    fn sample_table_round_trips() {
+        let video = video();
+        // 2 samples, then 2 & 1 further into mdat
+        let chunks = [(2, 0), (2, 500), (1, 900)];
+        let stbl = stbl(&video, b"entry", &chunks, 1000);
+        let body = child(&stbl, b"stbl").unwrap();
+
+        let stsz = child(body, b"stsz").unwrap();
+        assert_eq!(u32s(stsz), [0, 0, 5, 100, 20, 30, 90, 10]);
+
+        let co64 = child(body, b"co64").unwrap();
+        assert_eq!(u32s(&co64[..8]), [0, 3]);
+        let offsets = co64[8..]
+            .chunks(8)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [1000, 1500, 1900]);
+
+        // runs of chunks with the same sample count
+        assert_eq!(
+            u32s(child(body, b"stsc").unwrap()),
+            [0, 2, 1, 2, 1, 3, 1, 1]
+        );
+        assert_eq!(
+            u32s(child(body, b"stts").unwrap()),
+            [0, 3, 3, 40, 1, 20, 1, 40]
+        );
+        assert_eq!(u32s(child(body, b"stss").unwrap()), [0, 2, 1, 4]);
+        // negative offsets need version 1
+        assert_eq!(child(body, b"ctts").unwrap()[0], 1);
+
+        let parsed = sample_table(body).unwrap();
+        let expected = video
+            .samples
+            .iter()
+            .zip([1000, 1100, 1500, 1530, 1900])
+            .map(|(sample, offset)| Sample { offset, ..*sample })
+            .collect::<Vec<_>>();
+
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn reads_stco_and_fixed_sizes() {
+        let stbl = [
+            full_box_table(b"stts", 0, [[3, 10]].into_iter()),
+            full_box_table(b"stsc", 0, [[1, 2, 1], [2, 1, 1]].into_iter()),
+            mp4_box(b"stsz", &[0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 3]),
+            full_box_table(b"stco", 0, [[64], [200]].into_iter()),
+        ]
+        .concat();
+        let samples = sample_table(&stbl).unwrap();
+
+        assert_eq!(
+            samples
+                .iter()
+                .map(|s| (s.offset, s.size, s.dts))
+                .collect::<Vec<_>>(),
+            [(64, 7, 0), (71, 7, 10), (200, 7, 20)]
+        );
+        assert!(samples.iter().all(|s| s.keyframe));
+    }
+
+    #[test]
+    fn writes_chapters_and_title() {
+        let audio = track(Kind::Audio, 1000, &[(0, 60_000, true)]);
+        let chapters = [
+            chapter(0, 1500, "Intro"),
+            chapter(90_000, 0, "Past the end"),
+        ];
+        let written = udta(&[audio], "Title", &chapters, None);
+
+        let body = child(&written, b"udta").unwrap();
+        let chpl = child(body, b"chpl").unwrap();
+
+        let mut expected = vec![1, 0, 0, 0, 0, 0, 0, 0, 2];
+        expected.extend(0u64.to_be_bytes());
+        expected.push(5);
+        expected.extend(b"Intro");
+        // starts are clamped to the duration, in 100ns units
+        expected.extend((60_000u64 * 10_000).to_be_bytes());
+        expected.push(12);
+        expected.extend(b"Past the end");
+        assert_eq!(chpl, expected);
+
+        let ilst = child(&child(body, b"meta").unwrap()[4..], b"ilst").unwrap();
+        let data = child(child(ilst, b"\xa9nam").unwrap(), b"data").unwrap();
+        assert_eq!(&data[8..], b"Title");
+
+        assert!(udta(&[], "", &[], None).is_empty());
+    }
+
+    #[test]
+    fn packs_languages() {
+        assert_eq!(packed_language(Some("eng")), 0x15c7);
+        assert_eq!(packed_language(Some("und")), 0x55c4);
+        assert_eq!(packed_language(Some("en")), 0x55c4);
+        assert_eq!(packed_language(None), 0x55c4);
+    }
+}
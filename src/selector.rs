@@ -0,0 +1,477 @@
+//! Format selection expressions like `bestvideo[height<=720][vcodec^=vp9]+bestaudio/best`
+//!
+//! `/` separates fallbacks, `+` merges a video & an audio format and every
+//! `[key op value]` filter narrows down the formats an atom can pick from.
+//! Appending `?` to an operator (`[height<=?720]`) also accepts unknown values.
+
+use crate::{Error, Format, FormatKind};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector(Vec<Choice>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Choice {
+    first: Atom,
+    /// Audio merged with the first atom
+    merge: Option<Atom>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Atom {
+    target: Target,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Best,
+    Worst,
+    BestVideo,
+    WorstVideo,
+    BestAudio,
+    WorstAudio,
+    Id(Box<str>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    key: Key,
+    op: Op,
+    value: Box<str>,
+    allow_unknown: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Height,
+    Width,
+    Fps,
+    Bitrate,
+    Filesize,
+    VidCodec,
+    AudioCodec,
+    Container,
+    Language,
+    Id,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    StartsWith,
+    EndsWith,
+    Contains,
+}
+
+/// Formats picked by a selector
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Selection {
+    pub video: Option<Format>,
+    pub audio: Option<Format>,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidSelector(selector.into());
+
+        let choices = split_top_level(selector, '/')
+            .into_iter()
+            .map(|choice| {
+                let mut atoms = split_top_level(choice, '+').into_iter();
+                let first = Atom::parse(atoms.next().ok_or_else(invalid)?).ok_or_else(invalid)?;
+                let merge = atoms
+                    .next()
+                    .map(|atom| Atom::parse(atom).ok_or_else(invalid))
+                    .transpose()?;
+
+                if atoms.next().is_some() {
+                    return Err(invalid());
+                }
+
+                Ok(Choice { first, merge })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self(choices))
+    }
+
+    /// First choice which can be satisfied by the formats
+    pub fn select(&self, formats: &[Format]) -> Option<Selection> {
+        self.0.iter().find_map(|choice| {
+            let first = choice.first.pick(formats)?;
+
+            match &choice.merge {
+                Some(merge) => {
+                    let audio = merge.pick(formats)?;
+
+                    (first.has_video() && audio.has_audio()).then(|| Selection {
+                        video: Some(first.clone()),
+                        audio: Some(audio.clone()),
+                    })
+                }
+                None if first.has_video() => Some(Selection {
+                    video: Some(first.clone()),
+                    audio: None,
+                }),
+                None => Some(Selection {
+                    video: None,
+                    audio: Some(first.clone()),
+                }),
+            }
+        })
+    }
+}
+
+impl Atom {
+    fn parse(atom: &str) -> Option<Self> {
+        let atom = atom.trim();
+        let (name, mut rest) = atom.split_at(atom.find('[').unwrap_or(atom.len()));
+
+        let target = match name {
+            "best" | "b" => Target::Best,
+            "worst" | "w" => Target::Worst,
+            "bestvideo" | "bv" => Target::BestVideo,
+            "worstvideo" | "wv" => Target::WorstVideo,
+            "bestaudio" | "ba" => Target::BestAudio,
+            "worstaudio" | "wa" => Target::WorstAudio,
+            "" => return None,
+            id => Target::Id(id.into()),
+        };
+
+        let mut filters = Vec::new();
+
+        while !rest.is_empty() {
+            let (filter, remaining) = rest.strip_prefix('[')?.split_once(']')?;
+            filters.push(Filter::parse(filter)?);
+            rest = remaining;
+        }
+
+        Some(Self { target, filters })
+    }
+
+    fn pick<'a>(&self, formats: &'a [Format]) -> Option<&'a Format> {
+        let mut candidates = formats
+            .iter()
+            .filter(|format| self.filters.iter().all(|filter| filter.matches(format)));
+
+        match &self.target {
+            Target::Id(id) => candidates.find(|format| format.id == *id),
+            Target::Best => candidates
+                .filter(|format| format.kind == FormatKind::Combined)
+                .max_by(|a, b| quality(a, b)),
+            Target::Worst => candidates
+                .filter(|format| format.kind == FormatKind::Combined)
+                .min_by(|a, b| quality(a, b)),
+            Target::BestVideo => candidates
+                .filter(|format| format.kind == FormatKind::Video)
+                .max_by(|a, b| quality(a, b)),
+            Target::WorstVideo => candidates
+                .filter(|format| format.kind == FormatKind::Video)
+                .min_by(|a, b| quality(a, b)),
+            Target::BestAudio => candidates
+                .filter(|format| format.kind == FormatKind::Audio)
+                .max_by_key(|format| format.bitrate),
+            Target::WorstAudio => candidates
+                .filter(|format| format.kind == FormatKind::Audio)
+                .min_by_key(|format| format.bitrate),
+        }
+    }
+}
+
+impl Filter {
+    fn parse(filter: &str) -> Option<Self> {
+        const OPS: [(&str, Op); 9] = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("!=", Op::Ne),
+            ("^=", Op::StartsWith),
+            ("$=", Op::EndsWith),
+            ("*=", Op::Contains),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+
+        let (pos, (symbol, op)) = OPS
+            .iter()
+            .filter_map(|op| filter.find(op.0).map(|pos| (pos, op)))
+            .min_by_key(|(pos, op)| (*pos, 2 - op.0.len()))?;
+
+        let key = match filter[..pos].trim() {
+            "height" | "res" => Key::Height,
+            "width" => Key::Width,
+            "fps" => Key::Fps,
+            "bitrate" | "tbr" | "abr" | "vbr" => Key::Bitrate,
+            "filesize" | "size" => Key::Filesize,
+            "vcodec" => Key::VidCodec,
+            "acodec" => Key::AudioCodec,
+            "ext" | "container" => Key::Container,
+            "language" | "lang" => Key::Language,
+            "id" | "format_id" => Key::Id,
+            _ => return None,
+        };
+
+        let value = &filter[pos + symbol.len()..];
+        let (value, allow_unknown) = match value.strip_prefix('?') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+
+        Some(Self {
+            key,
+            op: *op,
+            value: value.trim().into(),
+            allow_unknown,
+        })
+    }
+
+    fn matches(&self, format: &Format) -> bool {
+        let number = |value: Option<u64>| match (value, parse_number(&self.value)) {
+            (Some(value), Some(wanted)) => self.compare(value.cmp(&wanted)),
+            (None, _) => self.allow_unknown,
+            (Some(_), None) => false,
+        };
+
+        let text = |value: Option<&str>| match value {
+            Some(value) => {
+                let (value, wanted) = (value.to_lowercase(), self.value.to_lowercase());
+
+                match self.op {
+                    Op::StartsWith => value.starts_with(&wanted),
+                    Op::EndsWith => value.ends_with(&wanted),
+                    Op::Contains => value.contains(&wanted),
+                    op => self.compare_op(op, value.cmp(&wanted)),
+                }
+            }
+            None => self.allow_unknown,
+        };
+
+        match self.key {
+            Key::Height => number(format.height.map(u64::from)),
+            Key::Width => number(format.width.map(u64::from)),
+            Key::Fps => number(format.fps.map(u64::from)),
+            Key::Bitrate => number(format.bitrate),
+            Key::Filesize => number(format.filesize),
+            Key::VidCodec => text(format.vid_codec.as_deref()),
+            Key::AudioCodec => text(format.audio_codec.as_deref()),
+            Key::Container => text(Some(&format.container)),
+            Key::Language => text(format.language.as_deref()),
+            Key::Id => text(Some(&format.id)),
+        }
+    }
+
+    fn compare(&self, ordering: Ordering) -> bool {
+        self.compare_op(self.op, ordering)
+    }
+
+    fn compare_op(&self, op: Op, ordering: Ordering) -> bool {
+        match op {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+            // string operators don't make sense for numbers
+            Op::StartsWith | Op::EndsWith | Op::Contains => false,
+        }
+    }
+}
+
+/// `720`, `720p`, `1.5M`, `500k`
+fn parse_number(value: &str) -> Option<u64> {
+    let value = value.trim_end_matches(['p', 'B', 'b', 'i']);
+
+    let (number, multiplier) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 1_000.0),
+        (i, 'm' | 'M') => (&value[..i], 1_000_000.0),
+        (i, 'g' | 'G') => (&value[..i], 1_000_000_000.0),
+        _ => (value, 1.0),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|number| (number * multiplier) as u64)
+}
+
+/// Higher resolution, then fps, then bitrate wins
+fn quality(a: &Format, b: &Format) -> Ordering {
+    (a.height, a.fps, a.bitrate).cmp(&(b.height, b.fps, b.bitrate))
+}
+
+/// Split at `separator` outside of `[...]` filters
+fn split_top_level(selector: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+
+    for (i, char) in selector.char_indices() {
+        match char {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            char if char == separator && depth == 0 => {
+                parts.push(&selector[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    parts.push(&selector[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats() -> Vec<Format> {
+        let format = |id: &str, kind, height, vcodec: Option<&str>, bitrate| Format {
+            id: id.into(),
+            kind,
+            container: if vcodec == Some("vp9") { "webm" } else { "mp4" }.into(),
+            vid_codec: vcodec.map(Into::into),
+            audio_codec: (kind != FormatKind::Video).then(|| "mp4a.40.2".into()),
+            height,
+            fps: height.map(|_| 30),
+            bitrate,
+            ..Default::default()
+        };
+
+        vec![
+            format(
+                "18",
+                FormatKind::Combined,
+                Some(360),
+                Some("avc1"),
+                Some(500_000),
+            ),
+            format(
+                "22",
+                FormatKind::Combined,
+                Some(720),
+                Some("avc1"),
+                Some(1_500_000),
+            ),
+            format(
+                "137",
+                FormatKind::Video,
+                Some(1080),
+                Some("avc1"),
+                Some(4_000_000),
+            ),
+            format(
+                "248",
+                FormatKind::Video,
+                Some(1080),
+                Some("vp9"),
+                Some(3_000_000),
+            ),
+            format(
+                "136",
+                FormatKind::Video,
+                Some(720),
+                Some("avc1"),
+                Some(2_000_000),
+            ),
+            format("v?", FormatKind::Video, None, Some("av01"), None),
+            format("139", FormatKind::Audio, None, None, Some(48_000)),
+            format("140", FormatKind::Audio, None, None, Some(128_000)),
+        ]
+    }
+
+    /// `video+audio` ids of the selection
+    fn ids(selector: &str) -> Option<String> {
+        let selection = Selector::parse(selector).unwrap().select(&formats())?;
+        let ids = [selection.video, selection.audio]
+            .into_iter()
+            .flatten()
+            .map(|format| format.id)
+            .collect::<Vec<_>>();
+
+        Some(ids.join("+"))
+    }
+
+    #[test]
+    fn selects() {
+        let table: [(&str, Option<&str>); 19] = [
+            ("best", Some("22")),
+            ("worst", Some("18")),
+            ("bv", Some("137")),
+            ("wa", Some("139")),
+            ("137+140", Some("137+140")),
+            ("bv+ba", Some("137+140")),
+            // unknown id
+            ("999+ba", None),
+            // + binds tighter than /
+            ("bv[height>2000]+ba/b", Some("22")),
+            ("nope/bv[height<=720]+wa", Some("136+139")),
+            ("nope/also-nope", None),
+            // merging needs video first & audio second
+            ("ba+bv", None),
+            ("bv[vcodec^=vp]", Some("248")),
+            ("bv[vcodec$=1][height<1080]", Some("136")),
+            ("bv[ext=webm]", Some("248")),
+            ("bv[vcodec*=v0]", Some("v?")),
+            ("b[height!=720]", Some("18")),
+            ("ba[abr>=100k]", Some("140")),
+            // unknown heights only pass with ?
+            ("bv[height<=480]", None),
+            ("bv[height<=?480]", Some("v?")),
+        ];
+
+        for (selector, expected) in table {
+            assert_eq!(ids(selector).as_deref(), expected, "{selector}");
+        }
+    }
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("720p"), Some(720));
+        assert_eq!(parse_number("1.5M"), Some(1_500_000));
+        assert_eq!(parse_number("500k"), Some(500_000));
+        assert_eq!(parse_number("2GiB"), Some(2_000_000_000));
+        assert_eq!(parse_number("lots"), None);
+    }
+
+    #[test]
+    fn splits_outside_of_filters() {
+        assert_eq!(
+            split_top_level("bv[vcodec=a/b]+ba/b", '/'),
+            ["bv[vcodec=a/b]+ba", "b"]
+        );
+        assert_eq!(split_top_level("bv[id*=+]+ba", '+'), ["bv[id*=+]", "ba"]);
+    }
+
+    #[test]
+    fn rejects_bad_selectors() {
+        for selector in [
+            "",
+            "bv+",
+            "+ba",
+            "bv+ba+ba",
+            "bv//b",
+            "bv[height<=720",
+            "bv[height]",
+            "bv[colour=red]",
+            "bv[height<=720]x",
+        ] {
+            let err = Selector::parse(selector).unwrap_err();
+
+            assert!(
+                matches!(&err, Error::InvalidSelector(bad) if **bad == *selector),
+                "{selector}"
+            );
+            assert_eq!(
+                err.to_string(),
+                format!("Invalid format selector: {selector}")
+            );
+        }
+    }
+}
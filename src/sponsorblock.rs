@@ -0,0 +1,339 @@
+//! SponsorBlock segments of youtube videos as chapters
+
+use crate::{chapters::fill_ends, Chapter, Error};
+use isahc::{
+    config::{Configurable, VersionNegotiation},
+    ReadResponseExt, Request, RequestExt,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use url::Url;
+
+pub const API: &str = "https://sponsor.ajay.app";
+
+pub const CATEGORIES: [&str; 8] = [
+    "sponsor",
+    "intro",
+    "outro",
+    "selfpromo",
+    "preview",
+    "filler",
+    "interaction",
+    "music_offtopic",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub category: Box<str>,
+}
+
+/// Segments of the video `id` in `categories`, only the first 4 chars of
+/// the sha256 of the id are sent so the server doesn't know which video it is
+pub fn segments(api: &str, id: &str, categories: &[Box<str>]) -> Result<Vec<Segment>, Error> {
+    // no cookies, they'd tell a third party who's asking
+    let mut resp = Request::get(url(api, id, categories)?.as_str())
+        .version_negotiation(VersionNegotiation::latest_compatible())
+        .body(())?
+        .send()?;
+
+    // no video with that prefix has segments
+    if resp.status() == 404 {
+        return Ok(Vec::new());
+    }
+
+    Ok(parse(&resp.json()?, id))
+}
+
+fn url(api: &str, id: &str, categories: &[Box<str>]) -> Result<Url, Error> {
+    let hash = Sha256::digest(id.as_bytes())
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        });
+
+    let categories = categories
+        .iter()
+        .map(|category| format!(r#""{category}""#))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut url = Url::parse(&format!(
+        "{}/api/skipSegments/{}",
+        api.trim_end_matches('/'),
+        &hash[..4]
+    ))
+    .map_err(|_| Error::UnsupportedUrl(api.into()))?;
+
+    url.query_pairs_mut()
+        .append_pair("categories", &format!("[{categories}]"));
+
+    Ok(url)
+}
+
+/// Skippable segments of `id` among every video sharing its hash prefix
+fn parse(videos: &Value, id: &str) -> Vec<Segment> {
+    videos
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|video| video["videoID"] == id)
+        .flat_map(|video| video["segments"].as_array().into_iter().flatten())
+        // poi_highlight & full video labels aren't something to skip
+        .filter(|segment| segment["actionType"] == "skip")
+        .filter_map(|segment| {
+            let start = segment["segment"][0].as_f64()?;
+            let end = segment["segment"][1].as_f64()?;
+            let category = segment["category"].as_str()?;
+
+            (end > start).then(|| Segment {
+                start_ms: (start * 1000.0) as u64,
+                end_ms: (end * 1000.0) as u64,
+                category: category.into(),
+            })
+        })
+        .collect()
+}
+
+/// Cut the chapters around the segments & add a chapter for every segment,
+/// without chapters the whole video becomes one named `title`
+pub fn mark(
+    chapters: &mut Vec<Chapter>,
+    segments: &[Segment],
+    duration_ms: Option<u64>,
+    title: &str,
+) {
+    if segments.is_empty() {
+        return;
+    }
+
+    fill_ends(chapters, duration_ms);
+
+    if chapters.is_empty() {
+        chapters.push(Chapter {
+            start_ms: 0,
+            end_ms: duration_ms
+                .or_else(|| segments.iter().map(|segment| segment.end_ms).max())
+                .unwrap_or_default(),
+            title: title.into(),
+            category: None,
+        });
+    }
+
+    for segment in segments {
+        let mut cut = Vec::new();
+
+        for chapter in chapters.drain(..) {
+            if chapter.end_ms <= segment.start_ms || chapter.start_ms >= segment.end_ms {
+                cut.push(chapter);
+                continue;
+            }
+
+            if chapter.start_ms < segment.start_ms {
+                cut.push(Chapter {
+                    end_ms: segment.start_ms,
+                    ..chapter.clone()
+                });
+            }
+
+            if chapter.end_ms > segment.end_ms {
+                cut.push(Chapter {
+                    start_ms: segment.end_ms,
+                    ..chapter
+                });
+            }
+        }
+
+        cut.push(Chapter {
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            title: chapter_title(&segment.category).into(),
+            category: Some(segment.category.clone()),
+        });
+
+        *chapters = cut;
+    }
+
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+}
+
+/// Title of the chapters of `category`, how they're told apart in mpv
+pub fn chapter_title(category: &str) -> String {
+    format!("[SponsorBlock]: {}", category_name(category))
+}
+
+pub fn category_name(category: &str) -> &str {
+    match category {
+        "sponsor" => "Sponsor",
+        "intro" => "Intermission/Intro Animation",
+        "outro" => "Endcards/Credits",
+        "selfpromo" => "Unpaid/Self Promotion",
+        "preview" => "Preview/Recap",
+        "filler" => "Filler Tangent",
+        "interaction" => "Interaction Reminder",
+        "music_offtopic" => "Non-Music Section",
+        category => category,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segment(start_ms: u64, end_ms: u64, category: &str) -> Segment {
+        Segment {
+            start_ms,
+            end_ms,
+            category: category.into(),
+        }
+    }
+
+    fn chapter(start_ms: u64, end_ms: u64, title: &str, category: Option<&str>) -> Chapter {
+        Chapter {
+            start_ms,
+            end_ms,
+            title: title.into(),
+            category: category.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn sends_only_the_hash_prefix() {
+        let url = url(
+            "https://sponsor.ajay.app/",
+            "dQw4w9WgXcQ",
+            &["sponsor".into(), "intro".into()],
+        )
+        .unwrap();
+
+        // sha256("dQw4w9WgXcQ") = 5f6b0b4e...
+        assert_eq!(url.path(), "/api/skipSegments/5f6b");
+        assert_eq!(
+            url.query_pairs().collect::<Vec<_>>(),
+            [("categories".into(), r#"["sponsor","intro"]"#.into())]
+        );
+        assert!(!url.as_str().contains("dQw4w9WgXcQ"));
+        assert!(matches!(
+            super::url("not a url", "dQw4w9WgXcQ", &[]),
+            Err(Error::UnsupportedUrl(_))
+        ));
+    }
+
+    #[test]
+    fn keeps_the_skip_segments_of_the_video() {
+        let videos = json!([
+            {
+                "videoID": "someoneElse",
+                "segments": [
+                    {"segment": [0.0, 10.0], "category": "sponsor", "actionType": "skip"}
+                ]
+            },
+            {
+                "videoID": "dQw4w9WgXcQ",
+                "segments": [
+                    {"segment": [1.5, 10.25], "category": "sponsor", "actionType": "skip"},
+                    {"segment": [30.0, 30.0], "category": "poi_highlight", "actionType": "poi"},
+                    {"segment": [0.0, 0.0], "category": "filler", "actionType": "full"},
+                    {"segment": [50.0, 40.0], "category": "intro", "actionType": "skip"},
+                    {"segment": [60.0, 75.5], "category": "outro", "actionType": "skip"}
+                ]
+            }
+        ]);
+
+        assert_eq!(
+            parse(&videos, "dQw4w9WgXcQ"),
+            [
+                segment(1500, 10250, "sponsor"),
+                segment(60000, 75500, "outro")
+            ]
+        );
+        assert!(parse(&videos, "missing0000").is_empty());
+        assert!(parse(&json!({"message": "Not Found"}), "dQw4w9WgXcQ").is_empty());
+    }
+
+    #[test]
+    fn splits_chapters_around_segments() {
+        let mut chapters = vec![
+            chapter(0, 0, "Intro", None),
+            chapter(30_000, 0, "Song", None),
+        ];
+
+        mark(
+            &mut chapters,
+            &[segment(20_000, 40_000, "sponsor")],
+            Some(60_000),
+            "Video",
+        );
+
+        assert_eq!(
+            chapters,
+            [
+                chapter(0, 20_000, "Intro", None),
+                chapter(20_000, 40_000, "[SponsorBlock]: Sponsor", Some("sponsor")),
+                chapter(40_000, 60_000, "Song", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn later_overlapping_segments_cut_earlier_ones() {
+        let mut chapters = Vec::new();
+
+        mark(
+            &mut chapters,
+            &[
+                segment(10_000, 20_000, "sponsor"),
+                segment(15_000, 25_000, "selfpromo"),
+                segment(40_000, 50_000, "outro"),
+                segment(42_000, 45_000, "interaction"),
+            ],
+            Some(50_000),
+            "Video",
+        );
+
+        assert_eq!(
+            chapters,
+            [
+                chapter(0, 10_000, "Video", None),
+                chapter(10_000, 15_000, "[SponsorBlock]: Sponsor", Some("sponsor")),
+                chapter(
+                    15_000,
+                    25_000,
+                    "[SponsorBlock]: Unpaid/Self Promotion",
+                    Some("selfpromo")
+                ),
+                chapter(25_000, 40_000, "Video", None),
+                chapter(
+                    40_000,
+                    42_000,
+                    "[SponsorBlock]: Endcards/Credits",
+                    Some("outro")
+                ),
+                chapter(
+                    42_000,
+                    45_000,
+                    "[SponsorBlock]: Interaction Reminder",
+                    Some("interaction")
+                ),
+                chapter(
+                    45_000,
+                    50_000,
+                    "[SponsorBlock]: Endcards/Credits",
+                    Some("outro")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_segments_leave_chapters_alone() {
+        let mut chapters = vec![chapter(0, 0, "Intro", None)];
+
+        mark(&mut chapters, &[], Some(60_000), "Video");
+        assert_eq!(chapters, [chapter(0, 0, "Intro", None)]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Subtitle tracks to SRT or WebVTT
+
+use crate::{
+    helpers::{or_fail::OrFail, reqwests::get_isahc},
+    Error, Subtitle,
+};
+use serde_json::Value;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    /// Milliseconds
+    pub start: u64,
+    pub end: u64,
+    pub text: Box<str>,
+}
+
+/// Download the cues of a youtube timedtext track
+pub fn fetch(subtitle: &Subtitle, user_agent: &str, referrer: &str) -> Result<Vec<Cue>, Error> {
+    // json3 is the easiest of the timedtext formats to convert
+    let url = subtitle
+        .url
+        .split('&')
+        .filter(|param| !param.starts_with("fmt="))
+        .collect::<Vec<_>>()
+        .join("&")
+        + "&fmt=json3";
+
+    timedtext(&get_isahc(&url, user_agent, referrer)?)
+}
+
+/// Cues of the timedtext json3 format
+pub fn timedtext(json: &str) -> Result<Vec<Cue>, Error> {
+    let data: Value = serde_json::from_str(json)?;
+    let events = data["events"].as_array().or_fail("timedtext", "events")?;
+
+    Ok(events
+        .iter()
+        .filter_map(|event| {
+            let start = event["tStartMs"].as_u64()?;
+            let text = event["segs"]
+                .as_array()?
+                .iter()
+                .filter_map(|seg| seg["utf8"].as_str())
+                .collect::<String>();
+
+            // blank lines would end the cue early in both formats
+            let text = text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            (!text.is_empty()).then(|| Cue {
+                start,
+                end: start + event["dDurationMs"].as_u64().unwrap_or_default(),
+                text: text.into(),
+            })
+        })
+        .collect())
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+
+    for (i, cue) in cues.iter().enumerate() {
+        let _ = write!(
+            srt,
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            timestamp(cue.start, ','),
+            timestamp(cue.end, ','),
+            cue.text
+        );
+    }
+
+    srt
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+
+    for cue in cues {
+        let _ = write!(
+            vtt,
+            "{} --> {}\n{}\n\n",
+            timestamp(cue.start, '.'),
+            timestamp(cue.end, '.'),
+            cue.text
+        );
+    }
+
+    vtt
+}
+
+/// `HH:MM:SS,mmm` for srt, `HH:MM:SS.mmm` for vtt
+fn timestamp(ms: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON3: &str = r#"{"wireMagic": "pb3", "events": [
+        {"tStartMs": 0, "dDurationMs": 1500, "id": 1, "wpWinPosId": 1},
+        {"tStartMs": 1200, "dDurationMs": 2300, "segs": [{"utf8": "Hello "}, {"utf8": "there"}]},
+        {"tStartMs": 4000, "dDurationMs": 1000, "segs": [{"utf8": "\n"}]},
+        {"tStartMs": 5000, "dDurationMs": 1000, "segs": [{"utf8": "first line\n\n"}, {"utf8": "  second line "}]},
+        {"tStartMs": 3599500, "dDurationMs": 1001, "segs": [{"utf8": "across the hour"}]},
+        {"tStartMs": 36000000, "segs": [{"utf8": "no duration"}]}
+    ]}"#;
+
+    #[test]
+    fn reads_timedtext() {
+        let cues = timedtext(JSON3).unwrap();
+        let cue = |start, end, text: &str| Cue {
+            start,
+            end,
+            text: text.into(),
+        };
+
+        assert_eq!(
+            cues,
+            [
+                cue(1200, 3500, "Hello there"),
+                cue(5000, 6000, "first line\nsecond line"),
+                cue(3_599_500, 3_600_501, "across the hour"),
+                cue(36_000_000, 36_000_000, "no duration"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_what_isnt_timedtext() {
+        assert!(matches!(timedtext("<xml/>"), Err(Error::Json(_))));
+        assert!(matches!(
+            timedtext(r#"{"wireMagic": "pb3"}"#),
+            Err(Error::ParseFailure {
+                field: "events",
+                ..
+            })
+        ));
+        assert_eq!(timedtext(r#"{"events": []}"#).unwrap(), []);
+    }
+
+    #[test]
+    fn writes_srt() {
+        assert_eq!(
+            to_srt(&timedtext(JSON3).unwrap()),
+            "1
+00:00:01,200 --> 00:00:03,500
+Hello there
+
+2
+00:00:05,000 --> 00:00:06,000
+first line
+second line
+
+3
+00:59:59,500 --> 01:00:00,501
+across the hour
+
+4
+10:00:00,000 --> 10:00:00,000
+no duration
+
+"
+        );
+        assert_eq!(to_srt(&[]), "");
+    }
+
+    #[test]
+    fn writes_vtt() {
+        assert_eq!(
+            to_vtt(&timedtext(JSON3).unwrap()),
+            "WEBVTT
+
+00:00:01.200 --> 00:00:03.500
+Hello there
+
+00:00:05.000 --> 00:00:06.000
+first line
+second line
+
+00:59:59.500 --> 01:00:00.501
+across the hour
+
+10:00:00.000 --> 10:00:00.000
+no duration
+
+"
+        );
+        assert_eq!(to_vtt(&[]), "WEBVTT\n\n");
+    }
+}
@@ -0,0 +1,178 @@
+//! Picking, downloading & sniffing thumbnails
+
+use crate::{
+    helpers::reqwests::{client, get},
+    Error, Thumbnail,
+};
+use isahc::ReadResponseExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl ImageKind {
+    /// Image type by its magic bytes, the url or content-type lie too often
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else {
+            None
+        }
+    }
+
+    pub fn ext(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// Biggest thumbnail, sites list them small to big so the last one wins ties
+/// & thumbnails without a size
+pub fn best(thumbnails: &[Thumbnail]) -> Option<&Thumbnail> {
+    thumbnails.iter().max_by_key(|thumbnail| {
+        u32::from(thumbnail.width.unwrap_or_default())
+            * u32::from(thumbnail.height.unwrap_or_default())
+    })
+}
+
+/// Download the image of `thumbnail`
+pub fn fetch(
+    thumbnail: &Thumbnail,
+    user_agent: &str,
+    referrer: &str,
+) -> Result<(Vec<u8>, ImageKind), Error> {
+    let mut resp = get(&client(user_agent, referrer)?, &thumbnail.url)?;
+
+    if !resp.status().is_success() {
+        return Err(Error::Unavailable(
+            format!("thumbnail returned {}", resp.status()).into(),
+        ));
+    }
+
+    let data = resp.bytes()?;
+    let kind = ImageKind::sniff(&data)
+        .ok_or_else(|| Error::UnsupportedMedia("thumbnail image type".into()))?;
+
+    Ok((data, kind))
+}
+
+/// Base64 FLAC picture block, the `METADATA_BLOCK_PICTURE` tag ogg files
+/// (opus, vorbis) keep their cover art in
+pub fn flac_picture(image: &[u8], kind: ImageKind) -> String {
+    let mime = kind.mime();
+
+    // front cover, mime, no description, unknown size & colors
+    let mut block = 3u32.to_be_bytes().to_vec();
+    block.extend((mime.len() as u32).to_be_bytes());
+    block.extend(mime.as_bytes());
+    block.extend([0; 20]);
+    block.extend((image.len() as u32).to_be_bytes());
+    block.extend(image);
+
+    base64(&block)
+}
+
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(CHARS[(n >> (18 - 6 * i) & 63) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_rfc_4648_vectors() {
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(data.as_bytes()), encoded, "{data:?}");
+        }
+
+        // the last two chars of the alphabet
+        assert_eq!(base64(&[0xFB, 0xFF, 0xBF]), "+/+/");
+    }
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        let webp = b"RIFF\x24\0\0\0WEBPVP8 ";
+
+        assert_eq!(
+            ImageKind::sniff(b"\xFF\xD8\xFF\xE0\0\x10JFIF"),
+            Some(ImageKind::Jpeg)
+        );
+        assert_eq!(
+            ImageKind::sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(ImageKind::Png)
+        );
+        assert_eq!(ImageKind::sniff(webp), Some(ImageKind::Webp));
+
+        assert_eq!(ImageKind::sniff(b""), None);
+        assert_eq!(ImageKind::sniff(b"\xFF\xD8"), None);
+        assert_eq!(ImageKind::sniff(b"<!DOCTYPE html>"), None);
+        assert_eq!(ImageKind::sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(ImageKind::sniff(&webp[..12]), None);
+    }
+
+    #[test]
+    fn flac_picture_block() {
+        let image = b"\xFF\xD8\xFF not really a jpeg";
+        let mut block = Vec::new();
+
+        // picture type 3, front cover
+        block.extend([0, 0, 0, 3]);
+        block.extend([0, 0, 0, 10]);
+        block.extend(b"image/jpeg");
+        // description length, width, height, depth & colors
+        block.extend([0; 20]);
+        block.extend([0, 0, 0, image.len() as u8]);
+        block.extend(image);
+
+        assert_eq!(flac_picture(image, ImageKind::Jpeg), base64(&block));
+        assert!(flac_picture(image, ImageKind::Jpeg).starts_with("AAAAAwAAAAppbWFnZS9qcGVn"));
+    }
+}